@@ -0,0 +1,61 @@
+//! Embed build metadata for `chatpack --version-info`.
+
+use std::env;
+use std::fs;
+use std::process::Command;
+
+fn main() {
+    let git_sha = command_output("git", &["rev-parse", "--short=12", "HEAD"]);
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]);
+
+    println!(
+        "cargo:rustc-env=CHATPACK_GIT_SHA={}",
+        git_sha.unwrap_or_else(|| "unknown".to_string())
+    );
+    println!(
+        "cargo:rustc-env=CHATPACK_RUSTC_VERSION={}",
+        rustc_version.unwrap_or_else(|| "unknown".to_string())
+    );
+    println!(
+        "cargo:rustc-env=CHATPACK_TARGET={}",
+        env::var("TARGET").unwrap_or_default()
+    );
+    println!(
+        "cargo:rustc-env=CHATPACK_PROFILE={}",
+        env::var("PROFILE").unwrap_or_default()
+    );
+    println!(
+        "cargo:rustc-env=CHATPACK_LIB_VERSION={}",
+        library_version().unwrap_or_else(|| "unknown".to_string())
+    );
+
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=Cargo.lock");
+}
+
+/// Trimmed stdout of a command, if it ran successfully.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.trim().to_string())
+}
+
+/// Version of the `chatpack` library locked in `Cargo.lock`.
+fn library_version() -> Option<String> {
+    let lock = fs::read_to_string("Cargo.lock").ok()?;
+    let mut lines = lock.lines();
+
+    while let Some(line) = lines.next() {
+        if line == "name = \"chatpack\"" {
+            let version = lines.next()?.strip_prefix("version = \"")?;
+            return version.strip_suffix('"').map(ToString::to_string);
+        }
+    }
+
+    None
+}
@@ -0,0 +1,58 @@
+//! User-defined sender renames for `--sender-alias-file`.
+//!
+//! The file maps names as they appear in the export to the names wanted in
+//! the output, e.g. `{"alice_old": "Alice", "alice2024": "Alice"}`. Several
+//! names may map to the same alias to merge accounts that changed username.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chatpack::prelude::*;
+
+/// Export sender names mapped to output names
+#[derive(Debug, Clone, Default)]
+pub struct SenderAliases {
+    names: HashMap<String, String>,
+}
+
+impl SenderAliases {
+    /// Load a JSON object, or a TOML table for files ending in `.toml`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        let is_toml = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+        let names = if is_toml {
+            toml::from_str(&content).map_err(anyhow::Error::from)
+        } else {
+            serde_json::from_str(&content).map_err(anyhow::Error::from)
+        };
+
+        let names = names.with_context(|| {
+            format!(
+                "Invalid sender aliases in {}: expected a map of names to names",
+                path.display()
+            )
+        })?;
+        Ok(Self { names })
+    }
+
+    /// Output name for a sender; unmapped senders keep their name.
+    pub fn resolve<'a>(&'a self, sender: &'a str) -> &'a str {
+        self.names.get(sender).map_or(sender, String::as_str)
+    }
+
+    /// Rename the senders of `messages` in place.
+    pub fn apply(&self, messages: &mut [Message]) {
+        for msg in messages {
+            let name = self.resolve(&msg.sender);
+            if name != msg.sender {
+                msg.sender = name.to_string();
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Stage timings for the hidden `--benchmark` flag.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Wall-clock time of each pipeline stage, measured as laps.
+#[derive(Debug)]
+pub struct Timings {
+    started: Instant,
+    last_lap: Instant,
+    stages: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            last_lap: now,
+            stages: Vec::new(),
+        }
+    }
+
+    /// Record the time since the previous lap as `stage`.
+    pub fn lap(&mut self, stage: &'static str) {
+        let now = Instant::now();
+        self.stages.push((stage, now - self.last_lap));
+        self.last_lap = now;
+    }
+
+    /// Print the timing table and throughput to stderr.
+    pub fn print(&self, input: &Path, outputs: &[PathBuf], messages: usize) {
+        let total = self.started.elapsed();
+
+        eprintln!();
+        eprintln!("⏱️  \x1b[1mBenchmark\x1b[0m");
+        for (stage, duration) in &self.stages {
+            eprintln!("   {:<12}{:>10.2} ms", stage, millis(*duration));
+        }
+        eprintln!("   {:<12}{:>10.2} ms", "total", millis(total));
+
+        eprintln!();
+        eprintln!("   Input:      {} bytes", size_of(input));
+        eprintln!(
+            "   Output:     {} bytes",
+            outputs.iter().map(|path| size_of(path)).sum::<u64>()
+        );
+        eprintln!(
+            "   Throughput: {:.0} messages/s",
+            messages as f64 / total.as_secs_f64()
+        );
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Size of a file, or of all files directly inside a directory
+pub fn size_of(path: &Path) -> u64 {
+    match fs::read_dir(path) {
+        Ok(entries) => entries
+            .flatten()
+            .filter_map(|entry| entry.metadata().ok())
+            .map(|meta| meta.len())
+            .sum(),
+        Err(_) => fs::metadata(path).map(|meta| meta.len()).unwrap_or(0),
+    }
+}
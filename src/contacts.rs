@@ -0,0 +1,85 @@
+//! Display names for WhatsApp senders shown as phone numbers.
+//!
+//! WhatsApp exports use the phone number as the sender name when the contact
+//! wasn't saved on the exporting device. A `phone,name` CSV file maps those
+//! numbers back to names.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chatpack::prelude::*;
+use serde::Deserialize;
+
+/// Phone numbers mapped to display names
+#[derive(Debug, Clone, Default)]
+pub struct ContactMap {
+    /// Names keyed by the digits of the phone number
+    names: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct ContactRow {
+    phone: String,
+    name: String,
+}
+
+impl ContactMap {
+    /// Load a CSV file with `phone` and `name` columns.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = csv::Reader::from_path(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        let mut names = HashMap::new();
+        for row in reader.deserialize() {
+            let row: ContactRow =
+                row.with_context(|| format!("Invalid contact in {}", path.display()))?;
+            let key = digits(&row.phone);
+            if !key.is_empty() {
+                names.insert(key, row.name.trim().to_string());
+            }
+        }
+
+        Ok(Self { names })
+    }
+
+    /// Display name for a sender, or the sender itself if it isn't a known
+    /// phone number.
+    ///
+    /// Numbers are compared by their digits, so `+1 (555) 010-2030` matches
+    /// `15550102030` however the export formats it.
+    pub fn resolve<'a>(&'a self, sender: &'a str) -> &'a str {
+        if !looks_like_phone(sender) {
+            return sender;
+        }
+        self.names
+            .get(&digits(sender))
+            .map_or(sender, String::as_str)
+    }
+
+    /// Replace phone-number senders with their names in place.
+    pub fn apply(&self, messages: &mut [Message]) {
+        for msg in messages {
+            let name = self.resolve(&msg.sender);
+            if name != msg.sender {
+                msg.sender = name.to_string();
+            }
+        }
+    }
+}
+
+fn digits(text: &str) -> String {
+    text.chars().filter(char::is_ascii_digit).collect()
+}
+
+/// Whether a sender consists of a phone number and its formatting only
+fn looks_like_phone(sender: &str) -> bool {
+    sender.chars().any(|c| c.is_ascii_digit())
+        && sender.chars().all(|c| {
+            c.is_ascii_digit()
+                || matches!(c, '+' | '-' | '(' | ')' | '.')
+                || c.is_whitespace()
+                // Bidi marks WhatsApp wraps phone numbers in
+                || matches!(c, '\u{200e}' | '\u{202a}' | '\u{202c}')
+        })
+}
@@ -0,0 +1,178 @@
+//! Discord exports the `chatpack` parser can't read on its own.
+//!
+//! DiscordChatExporter can export a whole server as a directory with one JSON
+//! file per channel. Each file is parsed with the `chatpack` Discord parser and
+//! the channels are merged into a single timeline.
+//!
+//! Its HTML export is read here directly, from the `chatlog__*` elements of
+//! each message.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use chatpack::prelude::*;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use scraper::{ElementRef, Html, Selector};
+
+/// Channel export files (`*.json`) in a server export directory, sorted by name.
+pub fn channel_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+
+    if files.is_empty() {
+        bail!("No channel exports (*.json) found in {}", dir.display());
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Parse every channel in a server export, sorted by timestamp across channels.
+pub fn parse_server(dir: &Path) -> Result<Vec<Message>> {
+    let parser = create_parser(Platform::Discord);
+    let mut messages = Vec::new();
+
+    for file in channel_files(dir)? {
+        let channel = parser
+            .parse(&file)
+            .with_context(|| format!("Failed to parse Discord export {}", file.display()))?;
+        messages.extend(channel);
+    }
+
+    // Stable, so messages without timestamps keep their per-channel order
+    messages.sort_by_key(|msg| msg.timestamp);
+    Ok(messages)
+}
+
+/// Whether the file is a DiscordChatExporter HTML export (by extension).
+pub fn is_html(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("html") || ext.eq_ignore_ascii_case("htm"))
+}
+
+/// Parse a DiscordChatExporter HTML export.
+///
+/// Follow-up messages in a group have no header and take the author of the
+/// message before them. Timestamps are read from the `title` of the timestamp
+/// element, as UTC.
+pub fn parse_html(path: &Path) -> Result<Vec<Message>> {
+    let html =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let document = Html::parse_document(&html);
+
+    let container = selector(".chatlog__message-container");
+    let author = selector(".chatlog__author");
+    let timestamp = selector(".chatlog__timestamp, .chatlog__short-timestamp");
+    let content = selector(".chatlog__content");
+    let attachment = selector(".chatlog__attachment a");
+    let reply = selector(".chatlog__reply-link");
+
+    let containers: Vec<ElementRef> = document.select(&container).collect();
+    if containers.is_empty() {
+        bail!(
+            "No messages found in {}; expected a DiscordChatExporter HTML export",
+            path.display()
+        );
+    }
+
+    let mut messages = Vec::new();
+    let mut current_author = None;
+
+    for element in containers {
+        if let Some(name) = element.select(&author).next() {
+            current_author = Some(text_of(name));
+        }
+        let Some(sender) = current_author.clone() else {
+            continue;
+        };
+
+        let mut text = element
+            .select(&content)
+            .next()
+            .map(text_of)
+            .unwrap_or_default();
+        for link in element.select(&attachment) {
+            let Some(name) = link
+                .value()
+                .attr("href")
+                .and_then(|href| href.rsplit('/').next())
+            else {
+                continue;
+            };
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&format!("[Attachment: {}]", name));
+        }
+
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let sent_at = element
+            .select(&timestamp)
+            .next()
+            .and_then(|ts| ts.value().attr("title"))
+            .and_then(parse_timestamp);
+        // `onclick="scrollToMessage(event, '123')"`
+        let reply_to = element
+            .select(&reply)
+            .next()
+            .and_then(|link| link.value().attr("onclick"))
+            .and_then(|onclick| {
+                let id: String = onclick.chars().filter(char::is_ascii_digit).collect();
+                id.parse().ok()
+            });
+        let id = element
+            .value()
+            .attr("data-message-id")
+            .and_then(|id| id.parse().ok());
+
+        messages.push(Message::with_metadata(
+            sender, text, sent_at, id, reply_to, None,
+        ));
+    }
+
+    Ok(messages)
+}
+
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("selectors are static and valid")
+}
+
+/// Text of an element with whitespace runs collapsed, keeping line breaks.
+fn text_of(element: ElementRef) -> String {
+    let text: String = element.text().collect();
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Timestamp formats of the `title` attribute across exporter versions and locales
+const TIMESTAMP_FORMATS: &[&str] = &[
+    "%A, %B %d, %Y %I:%M %p",
+    "%A, %d %B %Y %H:%M",
+    "%m/%d/%Y %I:%M %p",
+    "%d/%m/%Y %H:%M",
+];
+
+fn parse_timestamp(title: &str) -> Option<DateTime<Utc>> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(title) {
+        return Some(ts.with_timezone(&Utc));
+    }
+
+    TIMESTAMP_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(title.trim(), format).ok())
+        .map(|ts| ts.and_utc())
+}
@@ -0,0 +1,171 @@
+//! Emoji frequencies for `--emoji-stats`.
+//!
+//! Emoji are found by code point ranges rather than the full Unicode emoji
+//! data: a pictographic character, or any character followed by the emoji
+//! variation selector, starts an emoji. Skin tones, keycaps, tag sequences
+//! and zero-width-joiner sequences stay attached to it, so "👍🏽" and "👨‍👩‍👧"
+//! each count as one emoji, and two regional indicators make one flag.
+
+use std::collections::HashMap;
+use std::fs;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
+use std::str::Chars;
+
+use anyhow::{Context, Result};
+use chatpack::Message;
+use serde::Serialize;
+
+const ZWJ: char = '\u{200D}';
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+const KEYCAP: char = '\u{20E3}';
+
+/// How often each emoji occurs in the text of `messages`
+pub fn count_emojis(messages: &[Message]) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for msg in messages {
+        for emoji in emojis_in(&msg.content) {
+            *counts.entry(emoji).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// The emoji of `text`, in order, one string per emoji sequence.
+pub fn emojis_in(text: &str) -> Vec<String> {
+    let mut emojis = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if is_regional_indicator(c) {
+            if let Some(second) = chars.next_if(|&next| is_regional_indicator(next)) {
+                emojis.push(format!("{c}{second}"));
+            }
+            continue;
+        }
+        if !is_pictographic(c) && chars.peek() != Some(&VARIATION_SELECTOR) {
+            continue;
+        }
+
+        let mut emoji = String::from(c);
+        take_modifiers(&mut chars, &mut emoji);
+        while chars.peek() == Some(&ZWJ) {
+            let mut lookahead = chars.clone();
+            lookahead.next();
+            match lookahead.next() {
+                Some(next) if is_pictographic(next) => {
+                    emoji.push(ZWJ);
+                    emoji.push(next);
+                    chars = lookahead;
+                    take_modifiers(&mut chars, &mut emoji);
+                }
+                _ => break,
+            }
+        }
+        emojis.push(emoji);
+    }
+    emojis
+}
+
+/// Move the variation selectors, skin tones, keycap and tag characters after
+/// an emoji onto it
+fn take_modifiers(chars: &mut Peekable<Chars<'_>>, emoji: &mut String) {
+    while let Some(c) =
+        chars.next_if(|&c| c == VARIATION_SELECTOR || c == KEYCAP || is_skin_tone(c) || is_tag(c))
+    {
+        emoji.push(c);
+    }
+}
+
+/// Extended_Pictographic code points, leaving out the dingbats that are
+/// plain text symbols such as ✓
+fn is_pictographic(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1F000}'..='\u{1FAFF}'
+            | '\u{2600}'..='\u{2605}'
+            | '\u{2607}'..='\u{2612}'
+            | '\u{2614}'..='\u{2685}'
+            | '\u{2690}'..='\u{2705}'
+            | '\u{2708}'..='\u{2712}'
+            | '\u{2714}'
+            | '\u{2716}'
+            | '\u{271D}'
+            | '\u{2721}'
+            | '\u{2728}'
+            | '\u{2733}'..='\u{2734}'
+            | '\u{2744}'
+            | '\u{2747}'
+            | '\u{274C}'
+            | '\u{274E}'
+            | '\u{2753}'..='\u{2755}'
+            | '\u{2757}'
+            | '\u{2763}'..='\u{2767}'
+            | '\u{2795}'..='\u{2797}'
+            | '\u{27A1}'
+            | '\u{27B0}'
+            | '\u{27BF}'
+            | '\u{231A}'..='\u{231B}'
+            | '\u{23E9}'..='\u{23FA}'
+            | '\u{2B05}'..='\u{2B07}'
+            | '\u{2B1B}'..='\u{2B1C}'
+            | '\u{2B50}'
+            | '\u{2B55}'
+    ) && !is_regional_indicator(c)
+        && !is_skin_tone(c)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+fn is_skin_tone(c: char) -> bool {
+    ('\u{1F3FB}'..='\u{1F3FF}').contains(&c)
+}
+
+fn is_tag(c: char) -> bool {
+    ('\u{E0020}'..='\u{E007F}').contains(&c)
+}
+
+#[derive(Debug, Serialize)]
+struct EmojiCount {
+    emoji: String,
+    count: usize,
+}
+
+/// Contents of the `--emoji-stats` file
+#[derive(Debug, Serialize)]
+pub struct EmojiStats {
+    total_unique: usize,
+    total_count: usize,
+    /// Most frequent first, ties in code point order
+    emojis: Vec<EmojiCount>,
+}
+
+impl EmojiStats {
+    pub fn new(counts: HashMap<String, usize>) -> Self {
+        let mut emojis: Vec<_> = counts
+            .into_iter()
+            .map(|(emoji, count)| EmojiCount { emoji, count })
+            .collect();
+        emojis.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.emoji.cmp(&b.emoji)));
+
+        Self {
+            total_unique: emojis.len(),
+            total_count: emojis.iter().map(|e| e.count).sum(),
+            emojis,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)? + "\n")
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// `OUTPUT.emoji_stats.json`, next to the output
+pub fn stats_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".emoji_stats.json");
+    PathBuf::from(name)
+}
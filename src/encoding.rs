@@ -0,0 +1,389 @@
+//! Character encodings for input and output files.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use encoding_rs::{Encoding, GBK, SHIFT_JIS, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1252};
+
+/// Text encoding of the input file
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum InputEncoding {
+    #[default]
+    #[value(name = "utf-8")]
+    Utf8,
+    /// Western European (older Android WhatsApp exports)
+    #[value(name = "windows-1252")]
+    Windows1252,
+    /// Latin-1, decoded as its Windows-1252 superset like browsers do
+    #[value(name = "iso-8859-1")]
+    Iso8859_1,
+    /// Japanese
+    #[value(name = "shift-jis")]
+    ShiftJis,
+    /// Simplified Chinese, decoded as its GBK superset
+    #[value(name = "gb2312")]
+    Gb2312,
+}
+
+impl InputEncoding {
+    fn encoding(self) -> &'static Encoding {
+        match self {
+            InputEncoding::Utf8 => UTF_8,
+            InputEncoding::Windows1252 | InputEncoding::Iso8859_1 => WINDOWS_1252,
+            InputEncoding::ShiftJis => SHIFT_JIS,
+            InputEncoding::Gb2312 => GBK,
+        }
+    }
+}
+
+/// UTF-8 copy of a non-UTF-8 input file, removed when dropped.
+///
+/// The parsers all read from paths, so the input is transcoded once into a
+/// temporary file with the same name before any of them sees it.
+pub struct DecodedInput {
+    dir: PathBuf,
+    path: PathBuf,
+    /// Whether some bytes were invalid and replaced with U+FFFD
+    pub had_errors: bool,
+}
+
+impl DecodedInput {
+    pub fn new(source: &Path, encoding: InputEncoding) -> Result<Self> {
+        if source.is_dir() {
+            bail!("--input-encoding expects a single export file, not a directory");
+        }
+
+        let bytes =
+            fs::read(source).with_context(|| format!("Failed to read {}", source.display()))?;
+        let (text, _, had_errors) = encoding.encoding().decode(&bytes);
+
+        let dir = std::env::temp_dir().join(format!("chatpack-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(source.file_name().unwrap_or(source.as_os_str()));
+        fs::write(&path, text.as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(Self {
+            dir,
+            path,
+            had_errors,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for DecodedInput {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Text encoding of the written output
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputEncoding {
+    /// UTF-8 without BOM
+    #[default]
+    #[value(name = "utf-8")]
+    Utf8,
+    /// UTF-8 with BOM (for Excel CSV import)
+    #[value(name = "utf-8-bom")]
+    Utf8Bom,
+    /// UTF-16 little-endian with BOM
+    #[value(name = "utf-16-le")]
+    Utf16Le,
+    /// UTF-16 big-endian with BOM
+    #[value(name = "utf-16-be")]
+    Utf16Be,
+}
+
+impl OutputEncoding {
+    fn bom(self) -> &'static [u8] {
+        match self {
+            OutputEncoding::Utf8 => &[],
+            OutputEncoding::Utf8Bom => &[0xEF, 0xBB, 0xBF],
+            OutputEncoding::Utf16Le => &[0xFF, 0xFE],
+            OutputEncoding::Utf16Be => &[0xFE, 0xFF],
+        }
+    }
+}
+
+/// Writer that transcodes the UTF-8 it receives into the target encoding.
+///
+/// The BOM (if any) is written on construction. UTF-8 sequences split across
+/// `write` calls are buffered until complete.
+pub struct EncodedWriter<W: Write> {
+    inner: W,
+    encoding: OutputEncoding,
+    pending: Vec<u8>,
+}
+
+impl<W: Write> EncodedWriter<W> {
+    pub fn new(mut inner: W, encoding: OutputEncoding) -> io::Result<Self> {
+        inner.write_all(encoding.bom())?;
+        Ok(Self::without_bom(inner, encoding))
+    }
+
+    /// Writer for appending to text that already starts with a BOM.
+    pub fn without_bom(inner: W, encoding: OutputEncoding) -> Self {
+        Self {
+            inner,
+            encoding,
+            pending: Vec::new(),
+        }
+    }
+
+    fn write_utf16(&mut self, text: &str) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+        for unit in text.encode_utf16() {
+            match self.encoding {
+                OutputEncoding::Utf16Be => bytes.extend_from_slice(&unit.to_be_bytes()),
+                _ => bytes.extend_from_slice(&unit.to_le_bytes()),
+            }
+        }
+        self.inner.write_all(&bytes)
+    }
+}
+
+impl<W: Write> Write for EncodedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if matches!(
+            self.encoding,
+            OutputEncoding::Utf8 | OutputEncoding::Utf8Bom
+        ) {
+            return self.inner.write(buf);
+        }
+
+        self.pending.extend_from_slice(buf);
+        let pending = std::mem::take(&mut self.pending);
+
+        let valid_up_to = match std::str::from_utf8(&pending) {
+            Ok(_) => pending.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+
+        // Safe to unwrap: the prefix was just validated
+        let text = std::str::from_utf8(&pending[..valid_up_to]).unwrap();
+        self.write_utf16(text)?;
+        self.pending = pending[valid_up_to..].to_vec();
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "incomplete UTF-8 sequence at end of output",
+            ));
+        }
+        self.inner.flush()
+    }
+}
+
+/// Line ending of text output
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Newline {
+    /// `\n` (Unix, macOS)
+    #[value(name = "LF", alias = "lf")]
+    Lf,
+    /// `\r\n` (Windows)
+    #[value(name = "CRLF", alias = "crlf")]
+    Crlf,
+    /// `\r` (classic Mac OS)
+    #[value(name = "CR", alias = "cr")]
+    Cr,
+}
+
+impl Default for Newline {
+    /// The platform's own line ending
+    fn default() -> Self {
+        if cfg!(windows) {
+            Newline::Crlf
+        } else {
+            Newline::Lf
+        }
+    }
+}
+
+impl Newline {
+    /// The line ending itself
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Newline::Lf => "\n",
+            Newline::Crlf => "\r\n",
+            Newline::Cr => "\r",
+        }
+    }
+}
+
+impl OutputEncoding {
+    /// Decode output bytes written in this encoding back to text.
+    ///
+    /// Fails on bytes the writer could not have produced, so a broken
+    /// encoding shows up as an error rather than as replacement characters.
+    pub fn decode(self, bytes: &[u8]) -> Result<String> {
+        let bom = self.bom();
+        let Some(body) = bytes.strip_prefix(bom) else {
+            bail!("missing byte order mark");
+        };
+
+        let encoding = match self {
+            OutputEncoding::Utf8 | OutputEncoding::Utf8Bom => UTF_8,
+            OutputEncoding::Utf16Le => UTF_16LE,
+            OutputEncoding::Utf16Be => UTF_16BE,
+        };
+        match encoding.decode_without_bom_handling_and_without_replacement(body) {
+            Some(text) => Ok(text.into_owned()),
+            None => bail!("invalid {}", encoding.name()),
+        }
+    }
+}
+
+/// Sign of an encoding problem in a raw input file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EncodingIssue {
+    /// Byte offset in the file
+    pub offset: usize,
+    pub kind: EncodingIssueKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EncodingIssueKind {
+    /// Bytes that are not valid UTF-8
+    InvalidUtf8,
+    /// UTF-8 byte order mark
+    Bom,
+    /// Windows line endings; reported once, at the first one
+    CrlfLineEndings { count: usize },
+    /// UTF-8 that was decoded as Latin-1 and encoded again, e.g. `Ã©` for `é`
+    Mojibake { found: String, intended: String },
+}
+
+impl fmt::Display for EncodingIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            EncodingIssueKind::InvalidUtf8 => write!(f, "invalid UTF-8")?,
+            EncodingIssueKind::Bom => write!(f, "byte order mark")?,
+            EncodingIssueKind::CrlfLineEndings { count } => {
+                write!(f, "CRLF line endings ({} lines), first", count)?
+            }
+            EncodingIssueKind::Mojibake { found, intended } => {
+                write!(f, "possible mojibake \"{}\" (\"{}\")", found, intended)?
+            }
+        }
+        write!(f, " at byte {}", self.offset)
+    }
+}
+
+/// Scan raw input bytes for signs of encoding problems, in file order.
+pub fn detect_encoding_issues(bytes: &[u8]) -> Vec<EncodingIssue> {
+    let mut issues = Vec::new();
+
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        issues.push(EncodingIssue {
+            offset: 0,
+            kind: EncodingIssueKind::Bom,
+        });
+    }
+
+    let mut crlf = bytes
+        .windows(2)
+        .enumerate()
+        .filter(|(_, pair)| pair == b"\r\n");
+    if let Some((first, _)) = crlf.next() {
+        issues.push(EncodingIssue {
+            offset: first,
+            kind: EncodingIssueKind::CrlfLineEndings {
+                count: 1 + crlf.count(),
+            },
+        });
+    }
+
+    // Valid stretches are checked for mojibake, the bytes between reported
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let (valid, skip) = match std::str::from_utf8(&bytes[pos..]) {
+            Ok(text) => (text, None),
+            Err(e) => (
+                // Safe to unwrap: the prefix was just validated
+                std::str::from_utf8(&bytes[pos..pos + e.valid_up_to()]).unwrap(),
+                Some(e.error_len().unwrap_or(bytes.len() - pos - e.valid_up_to())),
+            ),
+        };
+
+        find_mojibake(valid, pos, &mut issues);
+        pos += valid.len();
+
+        if let Some(len) = skip {
+            issues.push(EncodingIssue {
+                offset: pos,
+                kind: EncodingIssueKind::InvalidUtf8,
+            });
+            pos += len;
+        }
+    }
+
+    issues.sort_by_key(|issue| issue.offset);
+    issues
+}
+
+/// Find characters whose Latin-1 bytes form a multi-byte UTF-8 sequence.
+fn find_mojibake(text: &str, base: usize, issues: &mut Vec<EncodingIssue>) {
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let needed = match latin1_byte(chars[i].1) {
+            Some(0xC2..=0xDF) => 1,
+            Some(0xE0..=0xEF) => 2,
+            Some(0xF0..=0xF4) => 3,
+            _ => 0,
+        };
+        let Some(sequence) = chars.get(i..=i + needed).filter(|_| needed > 0) else {
+            i += 1;
+            continue;
+        };
+
+        let raw: Option<Vec<u8>> = sequence.iter().map(|&(_, c)| latin1_byte(c)).collect();
+        match raw.and_then(|raw| String::from_utf8(raw).ok()) {
+            Some(intended) => {
+                let (start, _) = sequence[0];
+                let (last, last_char) = sequence[needed];
+                issues.push(EncodingIssue {
+                    offset: base + start,
+                    kind: EncodingIssueKind::Mojibake {
+                        found: text[start..last + last_char.len_utf8()].to_string(),
+                        intended,
+                    },
+                });
+                i += needed + 1;
+            }
+            None => i += 1,
+        }
+    }
+}
+
+/// Byte a character was decoded from under Windows-1252 (Latin-1 superset)
+fn latin1_byte(c: char) -> Option<u8> {
+    if c < '\u{80}' {
+        return None;
+    }
+    if c <= '\u{FF}' {
+        return Some(c as u8);
+    }
+
+    let mut buf = [0u8; 4];
+    let (bytes, _, unmappable) = WINDOWS_1252.encode(c.encode_utf8(&mut buf));
+    match (&*bytes, unmappable) {
+        ([byte], false) => Some(*byte),
+        _ => None,
+    }
+}
@@ -0,0 +1,369 @@
+//! Per-message data that the `chatpack` parsers don't expose.
+//!
+//! Some output fields need information from the raw export that doesn't fit in
+//! [`Message`]. This module re-reads the export and collects that data keyed by
+//! message ID, so it can be joined back in when writing output.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chatpack::prelude::*;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::{discord, validate};
+
+/// Extra fields collected for a single message.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MessageExtras {
+    /// Names of users @mentioned in the message
+    pub mentions: Vec<String>,
+    /// Chat, channel, or thread the message belongs to
+    pub conversation_id: Option<String>,
+    /// The message this one replies to
+    pub reply_target: Option<ReplyTarget>,
+    /// Telegram channel post view count
+    pub views: Option<u64>,
+    /// Telegram channel post forward count
+    pub forwards: Option<u64>,
+    /// Line of the export the message starts on (1-based)
+    pub source_line: Option<usize>,
+}
+
+/// Sender and text of a replied-to message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplyTarget {
+    pub sender: String,
+    pub content: String,
+}
+
+impl MessageExtras {
+    /// Fold another message's extras into this one (used when merging).
+    fn absorb(&mut self, other: MessageExtras) {
+        for mention in other.mentions {
+            if !self.mentions.contains(&mention) {
+                self.mentions.push(mention);
+            }
+        }
+    }
+}
+
+/// Sender and timestamp of a message without an ID
+type SenderTime = (String, Option<DateTime<Utc>>);
+
+/// Extras for an export, keyed by message ID.
+///
+/// Export-wide values (such as the conversation) live in a default entry that
+/// is returned for messages without an entry of their own, which also covers
+/// platforms without message IDs. Where per-message data is needed for such
+/// platforms, entries are keyed by sender and timestamp instead, along with
+/// the text that tells apart messages sent in the same minute.
+#[derive(Debug, Clone, Default)]
+pub struct Extras {
+    by_id: HashMap<u64, MessageExtras>,
+    by_sender_time: HashMap<SenderTime, Vec<(String, MessageExtras)>>,
+    default: MessageExtras,
+}
+
+impl Extras {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the sender and text of each message's reply target.
+    ///
+    /// Call with the full parsed list so replies to filtered-out messages
+    /// still resolve.
+    pub fn record_reply_targets(&mut self, messages: &[Message]) {
+        let by_id: HashMap<u64, &Message> = messages
+            .iter()
+            .filter_map(|msg| msg.id.map(|id| (id, msg)))
+            .collect();
+
+        for msg in messages {
+            let (Some(id), Some(target)) = (msg.id, msg.reply_to.and_then(|r| by_id.get(&r)))
+            else {
+                continue;
+            };
+            self.by_id
+                .entry(id)
+                .or_insert_with(|| self.default.clone())
+                .reply_target = Some(ReplyTarget {
+                sender: target.sender.clone(),
+                content: target.content.clone(),
+            });
+        }
+    }
+
+    /// Entry of a message, keyed by ID or else by sender, timestamp and text
+    fn entry(&mut self, msg: &Message) -> &mut MessageExtras {
+        if let Some(id) = msg.id {
+            return self.by_id.entry(id).or_insert_with(|| self.default.clone());
+        }
+
+        let entries = self
+            .by_sender_time
+            .entry((msg.sender.clone(), msg.timestamp))
+            .or_default();
+        let index = match entries.iter().position(|(text, _)| *text == msg.content) {
+            Some(index) => index,
+            None => {
+                entries.push((msg.content.clone(), self.default.clone()));
+                entries.len() - 1
+            }
+        };
+        &mut entries[index].1
+    }
+
+    /// Record the conversation of each message, given at the same index.
+    pub fn record_conversations(&mut self, messages: &[Message], conversations: &[String]) {
+        for (msg, conversation) in messages.iter().zip(conversations) {
+            self.entry(msg).conversation_id = Some(conversation.clone());
+        }
+    }
+
+    /// Record the export line of each message, given at the same index.
+    ///
+    /// Used for exports whose lines are matched to messages by position, see
+    /// [`whatsapp_message_lines`].
+    pub fn record_message_lines(&mut self, messages: &[Message], lines: &[Option<usize>]) {
+        for (msg, line) in messages.iter().zip(lines) {
+            if let Some(line) = line {
+                // Identical messages in the same minute can't be told apart
+                self.entry(msg).source_line.get_or_insert(*line);
+            }
+        }
+    }
+
+    /// Record the line of the export each message starts on.
+    ///
+    /// The parsers don't track positions, so the raw export is scanned again
+    /// by the objects of its `messages` array. Only Telegram and Discord JSON
+    /// exports are read here; WhatsApp lines come from
+    /// [`whatsapp_message_lines`].
+    pub fn record_source_lines(&mut self, platform: Platform, path: &Path) -> Result<()> {
+        let json = matches!(platform, Platform::Telegram | Platform::Discord)
+            && path.extension().is_some_and(|ext| ext == "json");
+        if path.is_dir() || !json {
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        let bytes = content.as_bytes();
+        let (mut line, mut counted) = (1, 0);
+
+        for (start, object) in validate::array_objects(&content, "messages") {
+            line += count_newlines(&bytes[counted..start]);
+            counted = start;
+
+            let raw: Value = serde_json::from_str(object).unwrap_or_default();
+            let id = raw["id"]
+                .as_u64()
+                .or_else(|| raw["id"].as_str().and_then(|id| id.parse().ok()));
+            if let Some(id) = id {
+                self.by_id
+                    .entry(id)
+                    .or_insert_with(|| self.default.clone())
+                    .source_line = Some(line);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Extras recorded for a message.
+    pub fn get(&self, msg: &Message) -> &MessageExtras {
+        msg.id
+            .and_then(|id| self.by_id.get(&id))
+            .or_else(|| {
+                if self.by_sender_time.is_empty() {
+                    return None;
+                }
+                let entries = self
+                    .by_sender_time
+                    .get(&(msg.sender.clone(), msg.timestamp))?;
+                // A merged message starts with the text of its first message
+                entries
+                    .iter()
+                    .find(|(text, _)| *text == msg.content)
+                    .or_else(|| {
+                        entries
+                            .iter()
+                            .filter(|(text, _)| msg.content.starts_with(text.as_str()))
+                            .max_by_key(|(text, _)| text.len())
+                    })
+                    .map(|(_, extra)| extra)
+            })
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Lines of a WhatsApp export that start a message, in order (1-based).
+///
+/// The parser reads messages in file order, so the n-th of these lines is
+/// where the n-th parsed message starts. Continuation lines of multi-line
+/// messages don't parse as a message and are skipped.
+pub fn whatsapp_message_lines(path: &Path) -> Result<Vec<usize>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let parser = create_parser(Platform::WhatsApp);
+
+    Ok(content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| parser.parse_str(line).is_ok_and(|m| !m.is_empty()))
+        .map(|(index, _)| index + 1)
+        .collect())
+}
+
+fn count_newlines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&byte| byte == b'\n').count()
+}
+
+/// Read the raw export and collect extras for each message.
+///
+/// WhatsApp's plain-text export and Discord's CSV, TXT and HTML exports carry
+/// none of the data and yield empty extras. A directory is read as a Discord
+/// server export, one file per channel; other directories (Instagram inboxes)
+/// get their extras from [`Extras::record_conversations`].
+pub fn load_extras(platform: Platform, path: &Path) -> Result<Extras> {
+    let non_json_discord = platform == Platform::Discord
+        && path.is_file()
+        && path.extension().is_some_and(|ext| ext != "json");
+    if platform == Platform::WhatsApp
+        || non_json_discord
+        || (path.is_dir() && platform != Platform::Discord)
+    {
+        return Ok(Extras::new());
+    }
+
+    if path.is_dir() {
+        // Channels differ per file, so every message gets its own entry
+        let mut extras = Extras::new();
+        for file in discord::channel_files(path)? {
+            let root = read_export(platform, &file)?;
+            collect_messages(platform, &root, &mut extras, true);
+        }
+        return Ok(extras);
+    }
+
+    let root = read_export(platform, path)?;
+    let mut extras = Extras {
+        default: export_defaults(platform, &root),
+        ..Extras::default()
+    };
+    collect_messages(platform, &root, &mut extras, false);
+
+    Ok(extras)
+}
+
+fn read_export(platform: Platform, path: &Path) -> Result<Value> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content).with_context(|| format!("Failed to parse {} export", platform))
+}
+
+/// Export-wide extras shared by every message in the export.
+fn export_defaults(platform: Platform, root: &Value) -> MessageExtras {
+    let conversation_id = match platform {
+        Platform::Telegram => root["name"].as_str(),
+        Platform::Discord => root["channel"]["name"].as_str(),
+        _ => root["title"].as_str(),
+    };
+
+    MessageExtras {
+        conversation_id: conversation_id.map(ToString::to_string),
+        ..MessageExtras::default()
+    }
+}
+
+/// Record extras for the export's messages, skipping entries equal to the
+/// default unless `keep_all` is set.
+fn collect_messages(platform: Platform, root: &Value, extras: &mut Extras, keep_all: bool) {
+    let base = export_defaults(platform, root);
+    let raw_messages = root["messages"].as_array().map_or(&[][..], Vec::as_slice);
+
+    for raw in raw_messages {
+        let mut extra = base.clone();
+        let id = match platform {
+            Platform::Telegram => {
+                extra.mentions = telegram_mentions(raw);
+                extra.views = raw["views"].as_u64();
+                extra.forwards = raw["forwards"].as_u64();
+                raw["id"].as_u64()
+            }
+            Platform::Discord => {
+                extra.mentions = discord_mentions(raw);
+                raw["id"].as_str().and_then(|id| id.parse().ok())
+            }
+            _ => None,
+        };
+
+        if let Some(id) = id {
+            if keep_all || extra != extras.default {
+                extras.by_id.insert(id, extra);
+            }
+        }
+    }
+}
+
+fn telegram_mentions(raw: &Value) -> Vec<String> {
+    // Prefer `text_entities`; older exports only have the rich `text` array
+    let entities = raw["text_entities"]
+        .as_array()
+        .or_else(|| raw["text"].as_array())
+        .map_or(&[][..], Vec::as_slice);
+
+    let mut mentions: Vec<String> = Vec::new();
+    for entity in entities {
+        if !matches!(entity["type"].as_str(), Some("mention" | "mention_name")) {
+            continue;
+        }
+        if let Some(text) = entity["text"].as_str() {
+            let name = text.trim().trim_start_matches('@').to_string();
+            if !name.is_empty() && !mentions.contains(&name) {
+                mentions.push(name);
+            }
+        }
+    }
+
+    mentions
+}
+
+fn discord_mentions(raw: &Value) -> Vec<String> {
+    let users = raw["mentions"].as_array().map_or(&[][..], Vec::as_slice);
+
+    users
+        .iter()
+        .filter_map(|user| user["nickname"].as_str().or_else(|| user["name"].as_str()))
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Move extras of messages that will be merged into the first message of
+/// each group, mirroring how `merge_consecutive` keeps the first ID.
+pub fn fold_merged(messages: &[Message], extras: &mut Extras) {
+    let mut group: Option<(&str, Option<u64>)> = None;
+
+    for msg in messages {
+        match group {
+            Some((sender, first_id)) if sender == msg.sender => {
+                let (Some(first), Some(id)) = (first_id, msg.id) else {
+                    continue;
+                };
+                if let Some(extra) = extras.by_id.remove(&id) {
+                    extras
+                        .by_id
+                        .entry(first)
+                        .or_insert_with(|| extras.default.clone())
+                        .absorb(extra);
+                }
+            }
+            _ => group = Some((&msg.sender, msg.id)),
+        }
+    }
+}
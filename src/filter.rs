@@ -0,0 +1,91 @@
+//! Per-message filter predicates.
+//!
+//! [`apply_filters`] only returns the surviving messages; these helpers expose
+//! the same checks per message so callers can keep track of positions.
+
+use chatpack::prelude::*;
+
+/// Method form of [`apply_filters`]: `filter.apply(messages)`.
+pub trait FilterExt {
+    /// Keep the messages that pass the filter.
+    fn apply(&self, messages: Vec<Message>) -> Vec<Message>;
+
+    /// Lazily keep the messages that pass the filter, one at a time.
+    fn filter_iter<'a>(
+        &'a self,
+        messages: impl Iterator<Item = Message> + 'a,
+    ) -> impl Iterator<Item = Message> + 'a;
+}
+
+impl FilterExt for FilterConfig {
+    fn apply(&self, messages: Vec<Message>) -> Vec<Message> {
+        apply_filters(messages, self)
+    }
+
+    fn filter_iter<'a>(
+        &'a self,
+        messages: impl Iterator<Item = Message> + 'a,
+    ) -> impl Iterator<Item = Message> + 'a {
+        messages.filter(move |msg| matches(self, msg))
+    }
+}
+
+/// Whether a message passes the filter, with the same rules as [`apply_filters`].
+pub fn matches(config: &FilterConfig, msg: &Message) -> bool {
+    if let Some(ref from) = config.from {
+        if !msg.sender.eq_ignore_ascii_case(from) {
+            return false;
+        }
+    }
+
+    if config.has_date_filter() {
+        let Some(ts) = msg.timestamp else {
+            return false;
+        };
+        if config.after.is_some_and(|after| ts < after) {
+            return false;
+        }
+        if config.before.is_some_and(|before| ts > before) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Message ID bounds from `--after-id` / `--before-id`, both exclusive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IdRange {
+    pub after: Option<u64>,
+    pub before: Option<u64>,
+}
+
+impl IdRange {
+    pub fn is_active(&self) -> bool {
+        self.after.is_some() || self.before.is_some()
+    }
+
+    /// Whether the message's ID is inside the range.
+    ///
+    /// Messages without an ID never match an active range.
+    pub fn contains(&self, msg: &Message) -> bool {
+        if !self.is_active() {
+            return true;
+        }
+
+        let Some(id) = msg.id else {
+            return false;
+        };
+        self.after.is_none_or(|after| id > after) && self.before.is_none_or(|before| id < before)
+    }
+}
+
+/// Indices of the messages that pass the filter.
+pub fn matching_indices(messages: &[Message], config: &FilterConfig) -> Vec<usize> {
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(_, msg)| matches(config, msg))
+        .map(|(i, _)| i)
+        .collect()
+}
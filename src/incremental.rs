@@ -0,0 +1,68 @@
+//! State kept between `--incremental` runs.
+//!
+//! After each successful write the newest timestamp seen is stored next to
+//! the output as `{output}.state.json`. The next run only processes messages
+//! newer than that, so a nightly job over a growing export writes just the
+//! messages added since the previous night.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chatpack::prelude::*;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Progress recorded by the previous run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IncrementalState {
+    /// Timestamp of the newest message processed so far
+    pub last_timestamp: Option<DateTime<Utc>>,
+    /// Number of messages processed over all runs
+    pub message_count: usize,
+}
+
+impl IncrementalState {
+    /// Load the state file; a missing file means nothing was processed yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Invalid incremental state in {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Keep only messages newer than the last run.
+    ///
+    /// Once a run has been recorded, messages without a timestamp are dropped
+    /// too: there's no telling whether they were processed before.
+    pub fn retain_new(&self, messages: &mut Vec<Message>) {
+        if let Some(last) = self.last_timestamp {
+            messages.retain(|msg| msg.timestamp.is_some_and(|ts| ts > last));
+        }
+    }
+
+    /// State after processing `messages`.
+    pub fn advance(&self, messages: &[Message]) -> Self {
+        let newest = messages.iter().filter_map(|msg| msg.timestamp).max();
+        Self {
+            last_timestamp: newest.max(self.last_timestamp),
+            message_count: self.message_count + messages.len(),
+        }
+    }
+}
+
+/// Path of the state file for an output path.
+pub fn state_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".state.json");
+    PathBuf::from(name)
+}
@@ -0,0 +1,185 @@
+//! Instagram exports: inbox directories and older file layouts.
+//!
+//! "Download Your Information" produces `inbox/{conversation}/message_N.json`,
+//! with long conversations split over several files. Meta has also changed
+//! the file layout over the years; older layouts are rewritten into the
+//! current one before handing them to the `chatpack` Instagram parser.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use chatpack::prelude::*;
+use chrono::DateTime;
+use serde_json::{Value, json};
+
+/// Layout of an Instagram message export
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InstagramFormat {
+    /// Current layout: `participants` and `messages` at the root
+    Current,
+    /// v1: the root is the `messages` array itself
+    MessageArray,
+    /// v2: `participants` and `messages` nested under a conversation key
+    Nested,
+    /// v3: thread objects with `conversation` arrays and `magic_words` /
+    /// `words_to_highlight` keys, using `sender`/`created_at`/`text`
+    MagicWords,
+    Unknown,
+}
+
+/// Work out which layout an export uses.
+pub fn detect_instagram_format(value: &Value) -> InstagramFormat {
+    let is_thread = |v: &Value| {
+        v.get("conversation").is_some_and(Value::is_array)
+            || v.get("magic_words").is_some()
+            || v.get("words_to_highlight").is_some()
+    };
+
+    match value {
+        Value::Object(root) if root.get("messages").is_some_and(Value::is_array) => {
+            InstagramFormat::Current
+        }
+        Value::Object(_) if is_thread(value) => InstagramFormat::MagicWords,
+        Value::Object(root)
+            if root
+                .values()
+                .any(|v| v.get("messages").is_some_and(Value::is_array)) =>
+        {
+            InstagramFormat::Nested
+        }
+        Value::Array(items) if items.iter().any(is_thread) => InstagramFormat::MagicWords,
+        Value::Array(items)
+            if !items.is_empty() && items.iter().all(|m| m.get("sender_name").is_some()) =>
+        {
+            InstagramFormat::MessageArray
+        }
+        _ => InstagramFormat::Unknown,
+    }
+}
+
+/// Parse a single Instagram export file in any known layout.
+pub fn parse_file(path: &Path) -> Result<Vec<Message>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let value: Value = serde_json::from_str(&content)
+        .with_context(|| format!("Failed to parse Instagram export {}", path.display()))?;
+
+    let current = match detect_instagram_format(&value) {
+        InstagramFormat::Current => value,
+        InstagramFormat::MessageArray => json!({ "messages": value }),
+        InstagramFormat::Nested => value
+            .as_object()
+            .and_then(|root| {
+                root.values()
+                    .find(|v| v.get("messages").is_some_and(Value::is_array))
+            })
+            .cloned()
+            .unwrap_or_default(),
+        InstagramFormat::MagicWords => from_magic_words(&value),
+        InstagramFormat::Unknown => {
+            bail!("Unrecognized Instagram export format in {}", path.display())
+        }
+    };
+
+    create_parser(Platform::Instagram)
+        .parse_str(&current.to_string())
+        .with_context(|| format!("Failed to parse Instagram export {}", path.display()))
+}
+
+/// Rewrite v3 thread objects into the current `messages` layout.
+fn from_magic_words(value: &Value) -> Value {
+    let threads = match value {
+        Value::Array(threads) => threads.as_slice(),
+        thread => std::slice::from_ref(thread),
+    };
+
+    let messages: Vec<Value> = threads
+        .iter()
+        .filter_map(|thread| thread["conversation"].as_array())
+        .flatten()
+        .filter_map(|msg| {
+            // The parser needs a timestamp; entries without one can't be placed
+            let timestamp_ms = msg["created_at"]
+                .as_str()
+                .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())?
+                .timestamp_millis();
+            Some(json!({
+                "sender_name": msg["sender"].as_str()?,
+                "timestamp_ms": timestamp_ms,
+                "content": msg["text"],
+            }))
+        })
+        .collect();
+
+    json!({ "messages": messages })
+}
+
+/// Messages from every conversation in an inbox directory, with the
+/// conversation (directory name) of each message at the same index.
+///
+/// Conversations are concatenated in name order; each is sorted by timestamp.
+pub fn parse_inbox(dir: &Path) -> Result<(Vec<Message>, Vec<String>)> {
+    let mut messages = Vec::new();
+    let mut conversations = Vec::new();
+
+    for conversation_dir in conversation_dirs(dir)? {
+        let conversation = conversation_dir
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+
+        let mut thread = Vec::new();
+        for file in message_files(&conversation_dir)? {
+            thread.extend(parse_file(&file)?);
+        }
+
+        // message_1.json holds the newest messages, so restore global order
+        thread.sort_by_key(|msg| msg.timestamp);
+
+        conversations.extend(std::iter::repeat_n(conversation, thread.len()));
+        messages.extend(thread);
+    }
+
+    Ok((messages, conversations))
+}
+
+/// Conversation subdirectories that contain at least one `message_N.json`.
+fn conversation_dirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() && !message_files(&path)?.is_empty() {
+            dirs.push(path);
+        }
+    }
+
+    if dirs.is_empty() {
+        bail!(
+            "No conversations (*/message_N.json) found in {}",
+            dir.display()
+        );
+    }
+
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn message_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.is_file() && name.starts_with("message_") && name.ends_with(".json") {
+            files.push(path);
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
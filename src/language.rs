@@ -0,0 +1,53 @@
+//! Language detection for `--language-filter`.
+//!
+//! Detection uses `whichlang`, a small n-gram model that is fast but only
+//! knows 16 languages and guesses poorly on short texts, so short messages
+//! are kept without a verdict.
+
+use chatpack::prelude::Message;
+use whichlang::{Lang, detect_language};
+
+/// Codes accepted by `--language-filter`, ISO 639-1 first
+const LANGUAGES: &[(&str, &str, Lang)] = &[
+    ("ar", "ara", Lang::Ara),
+    ("de", "deu", Lang::Deu),
+    ("en", "eng", Lang::Eng),
+    ("es", "spa", Lang::Spa),
+    ("fr", "fra", Lang::Fra),
+    ("hi", "hin", Lang::Hin),
+    ("it", "ita", Lang::Ita),
+    ("ja", "jpn", Lang::Jpn),
+    ("ko", "kor", Lang::Kor),
+    ("nl", "nld", Lang::Nld),
+    ("pt", "por", Lang::Por),
+    ("ru", "rus", Lang::Rus),
+    ("sv", "swe", Lang::Swe),
+    ("tr", "tur", Lang::Tur),
+    ("vi", "vie", Lang::Vie),
+    ("zh", "cmn", Lang::Cmn),
+];
+
+/// Parse a language code like `en` or `eng`.
+pub fn parse_language(s: &str) -> Result<Lang, String> {
+    let code = s.to_ascii_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(short, long, _)| *short == code || *long == code)
+        .map(|(_, _, lang)| *lang)
+        .ok_or_else(|| {
+            let codes: Vec<&str> = LANGUAGES.iter().map(|(short, _, _)| *short).collect();
+            format!(
+                "unsupported language '{}'; expected one of {}",
+                s,
+                codes.join(", ")
+            )
+        })
+}
+
+/// Keep messages detected as `lang`, and every message shorter than
+/// `min_words` words.
+pub fn retain_language(messages: &mut Vec<Message>, lang: Lang, min_words: usize) {
+    messages.retain(|msg| {
+        msg.content.split_whitespace().count() < min_words || detect_language(&msg.content) == lang
+    });
+}
@@ -4,16 +4,65 @@
 //! Instagram, and Discord, and converting them to CSV, JSON, or JSONL formats.
 
 use anyhow::{Context, Result, bail};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeDelta, Utc};
 use clap::{Parser, ValueEnum};
-use std::path::PathBuf;
-
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use regex::Regex;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chatpack::error::StreamingErrorKind;
 use chatpack::prelude::*;
 
+mod aliases;
+mod benchmark;
+mod contacts;
+mod discord;
+mod emoji;
+mod encoding;
+mod extras;
+mod filter;
+mod incremental;
+mod instagram;
+mod language;
+mod media;
+mod memory;
+mod output;
+mod remote;
+mod report;
+mod schema;
+mod sender_stats;
+mod telegram;
+mod topics;
+mod transform;
+mod validate;
+mod warnings;
+mod watch;
+mod whatsapp;
+
+use aliases::SenderAliases;
+use benchmark::Timings;
+use contacts::ContactMap;
+use encoding::{DecodedInput, EncodedWriter, InputEncoding, Newline, OutputEncoding};
+use extras::Extras;
+use filter::{FilterExt, IdRange};
+use incremental::IncrementalState;
+use output::{CsvColumn, Envelope, OutputOptions, ReplyFormat, Template};
+use remote::{HttpMethod, RemoteInput};
+use report::ConversationStats;
+use telegram::TelegramOptions;
+use topics::TopicClassifier;
+use transform::{MergeIterator, MessageExt, PivotMetric, PivotPeriod, UnicodeForm};
+
 /// Parse and convert chat exports into LLM-friendly formats.
 ///
 /// Supports Telegram, WhatsApp, Instagram, and Discord exports.
 /// Outputs to CSV (default), JSON, or JSONL formats optimized for LLM context.
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 #[command(name = "chatpack")]
 #[command(version, about, long_about = None)]
 #[command(after_help = "\x1b[1mExamples:\x1b[0m
@@ -22,6 +71,11 @@ use chatpack::prelude::*;
   chatpack ig messages.json -f json           # Instagram to JSON
   chatpack dc export.json --after 2024-01-01  # Discord with date filter
   chatpack tg export.json --no-streaming      # Load entire file into memory
+  chatpack validate tg export.json            # Check an export before converting
+
+\x1b[1mIncremental Telegram Export:\x1b[0m
+  chatpack tg export.json --after-id 12345 -o new.jsonl --append
+                                              # Append messages newer than ID 12345
 
 \x1b[1mToken Compression:\x1b[0m
   CSV:   ~13x compression (92% savings) - best for LLM context
@@ -35,10 +89,40 @@ struct Cli {
     )]
     source: Source,
 
-    /// Input file path
-    #[arg(help = "Path to the exported chat file")]
+    /// Input file path; absent when fetching with --input-url
+    #[arg(
+        value_name = "INPUT",
+        required_unless_present = "input_url",
+        help = "Path to the exported chat file"
+    )]
+    input_arg: Option<PathBuf>,
+
+    /// Input file to read: INPUT, or the download of --input-url
+    #[arg(skip)]
     input: PathBuf,
 
+    /// `input` as shown in summaries, reports and metadata; stays the same
+    /// when `input` is swapped for a transcoded copy
+    #[arg(skip)]
+    display_input: PathBuf,
+
+    /// Fetch the export over HTTP(S) instead of reading INPUT
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = ["input_arg", "watch", "discord_server"],
+        help = "Fetch the export from an HTTP(S) URL instead of INPUT"
+    )]
+    input_url: Option<String>,
+
+    /// Extra request headers for --input-url and --output-url
+    #[arg(
+        long,
+        value_name = "HEADER",
+        help = "HTTP header for --input-url/--output-url as \"Name: value\" (repeatable)"
+    )]
+    http_header: Vec<String>,
+
     /// Output file path
     #[arg(
         short,
@@ -48,8 +132,46 @@ struct Cli {
     )]
     output: PathBuf,
 
-    /// Output format
-    #[arg(short, long, value_enum, default_value = "csv", help = "Output format")]
+    /// Send the output to an HTTP endpoint instead of writing OUTPUT
+    #[arg(
+        long,
+        value_name = "URL",
+        conflicts_with_all = [
+            "append",
+            "incremental",
+            "report",
+            "split_by_sender",
+            "split_by_conversation",
+            "window_size",
+            "conversation_turns",
+        ],
+        help = "Send the output to an HTTP(S) URL instead of writing a file"
+    )]
+    output_url: Option<String>,
+
+    /// HTTP method for --output-url
+    #[arg(
+        long,
+        value_enum,
+        value_name = "METHOD",
+        default_value = "post",
+        requires = "output_url",
+        help = "HTTP method for --output-url: post, put, patch"
+    )]
+    http_method: HttpMethod,
+
+    /// Output format; inferred from the output extension when omitted
+    #[arg(
+        short = 'f',
+        long = "format",
+        value_enum,
+        alias = "output-format",
+        help = "Output format [default: from the output extension, else csv]"
+    )]
+    format_arg: Option<Format>,
+
+    /// Output format after inference
+    #[arg(skip)]
     format: Format,
 
     /// Include timestamps in output
@@ -60,6 +182,15 @@ struct Cli {
     #[arg(short, long, help = "Include reply-to references")]
     replies: bool,
 
+    /// How reply references are rendered
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        help = "Reply rendering: id, quote, inline, omit [default: id with -r, else omit]"
+    )]
+    reply_format: Option<ReplyFormat>,
+
     /// Include edit timestamps
     #[arg(short, long, help = "Include edit timestamps")]
     edited: bool,
@@ -68,6 +199,420 @@ struct Cli {
     #[arg(long, help = "Include message IDs")]
     ids: bool,
 
+    /// Include @mentioned users (Telegram, Discord)
+    #[arg(long, help = "Include lists of @mentioned users")]
+    include_mentions: bool,
+
+    /// Include the conversation (chat, channel, thread) name
+    #[arg(long, help = "Include the conversation name/ID")]
+    include_conversation_id: bool,
+
+    /// Leave the sender out of the output
+    #[arg(long, help = "Omit the sender column/field")]
+    omit_sender: bool,
+
+    /// Start each message's text with its sender's name
+    #[arg(
+        long,
+        conflicts_with = "omit_sender",
+        help = "Prefix message text with 'Sender: '"
+    )]
+    prepend_sender: bool,
+
+    /// Show timestamps relative to a reference time
+    #[arg(long, help = "Show timestamps as '2 hours ago' (implies -t)")]
+    relative_timestamps: bool,
+
+    /// Reference point for --relative-timestamps
+    #[arg(
+        long,
+        value_name = "DATE",
+        value_parser = parse_reference_time,
+        requires = "relative_timestamps",
+        help = "Reference time for relative timestamps [default: last message]"
+    )]
+    reference_time: Option<DateTime<Utc>>,
+
+    /// Separate messages from different calendar days
+    #[arg(long, help = "Insert a separator between days")]
+    group_by_day: bool,
+
+    /// Insert a boundary after silences longer than this many minutes
+    #[arg(
+        long,
+        value_name = "GAP_MINUTES",
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Insert a boundary after silences longer than GAP_MINUTES"
+    )]
+    conversation_boundary: Option<u32>,
+
+    /// Truncate each message's text to N characters
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Truncate message text to N characters (adds …)"
+    )]
+    truncate_text: Option<usize>,
+
+    /// Hard-wrap message text in CSV and template output
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Wrap CSV/template message text so no line is longer than N characters"
+    )]
+    max_line_length: Option<usize>,
+
+    /// Marker written before each --max-line-length break
+    #[arg(
+        long,
+        value_name = "TEXT",
+        requires = "max_line_length",
+        help = "Text before each wrapped line break [default: \" ↵ \"]"
+    )]
+    continuation_marker: Option<String>,
+
+    /// Drop quoted reply lines (`> ...`) from the start of message text
+    #[arg(long, help = "Strip leading \"> \" quoted lines from message text")]
+    strip_quotes: bool,
+
+    /// Regex replacements for message text, as REGEX REPLACEMENT pairs
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["REGEX", "REPLACEMENT"],
+        action = clap::ArgAction::Append,
+        allow_hyphen_values = true,
+        help = "Replace REGEX matches in message text ($1 for groups, repeatable)"
+    )]
+    replace_text_pattern: Vec<String>,
+
+    /// Compiled --replace-text-pattern pairs
+    #[arg(skip)]
+    text_replacements: Vec<(Regex, String)>,
+
+    /// Include Telegram channel view and forward counts
+    #[arg(long, help = "Include channel views/forwards in JSON output")]
+    include_channel_meta: bool,
+
+    /// CSV file mapping WhatsApp phone numbers to names
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Replace WhatsApp phone-number senders using a phone,name CSV file"
+    )]
+    pretty_names: Option<PathBuf>,
+
+    /// JSON or TOML file mapping sender names to output names
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Rename senders using a JSON or TOML {\"old name\": \"new name\"} file"
+    )]
+    sender_alias_file: Option<PathBuf>,
+
+    /// Derive IDs from sender, time and text for messages without one
+    #[arg(
+        long,
+        help = "Generate stable content-based IDs for messages without one"
+    )]
+    hash_ids: bool,
+
+    /// Include the line of the original export each message starts on
+    #[arg(long, help = "Include the export line each message starts on")]
+    line_numbers: bool,
+
+    /// Replace media messages with descriptive placeholders
+    #[arg(long, help = "Describe media as [Photo: ...], [Audio: 12s], etc.")]
+    include_media_description: bool,
+
+    /// Directory of exported WhatsApp media files
+    #[arg(
+        long,
+        value_name = "PATH",
+        requires = "include_media_description",
+        help = "Mark WhatsApp media as (exists)/(missing) in this directory"
+    )]
+    whatsapp_media_dir: Option<PathBuf>,
+
+    /// Character encoding of the output file
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ENCODING",
+        default_value = "utf-8",
+        help = "Output encoding (utf-8-bom for Excel)"
+    )]
+    output_encoding: OutputEncoding,
+
+    /// Start CSV output with a UTF-8 byte order mark for Excel
+    #[arg(
+        long,
+        conflicts_with = "output_encoding",
+        help = "Start the CSV with a UTF-8 BOM so Excel on Windows reads it as UTF-8"
+    )]
+    output_bom: bool,
+
+    /// Line ending of CSV, JSONL and template output
+    #[arg(
+        long,
+        value_enum,
+        value_name = "NEWLINE",
+        help = "Line endings of CSV/JSONL output: LF, CRLF, CR [default: LF, CRLF on Windows]"
+    )]
+    output_newline: Option<Newline>,
+
+    /// Re-read the written output and check it parses in its format
+    #[arg(
+        long,
+        conflicts_with = "output_url",
+        help = "Check that the written output parses as CSV/JSON/JSONL"
+    )]
+    verify_output: bool,
+
+    /// Write a wide CSV of activity per period and sender
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "append",
+            "output_url",
+            "split_by_sender",
+            "split_by_conversation",
+            "window_size",
+            "conversation_turns",
+        ],
+        help = "Write a wide CSV: rows are periods, columns are senders"
+    )]
+    pivot: bool,
+
+    /// Period of each --pivot row
+    #[arg(
+        long = "by",
+        value_enum,
+        value_name = "PERIOD",
+        default_value = "day",
+        requires = "pivot",
+        help = "Period of each --pivot row: day, hour"
+    )]
+    pivot_by: PivotPeriod,
+
+    /// What the --pivot cells count
+    #[arg(
+        long,
+        value_enum,
+        value_name = "METRIC",
+        default_value = "count",
+        requires = "pivot",
+        help = "Value of each --pivot cell: count (messages), chars"
+    )]
+    pivot_metric: PivotMetric,
+
+    /// Write each message through a template instead of CSV/JSON/JSONL
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = parse_template,
+        conflicts_with_all = [
+            "output_url",
+            "verify_output",
+            "pivot",
+            "split_by_sender",
+            "split_by_conversation",
+            "window_size",
+            "conversation_turns",
+        ],
+        help = "Write each message as TEMPLATE with {sender}, {text}, {timestamp}, {id} (\\n, \\t escapes)"
+    )]
+    context_window_format: Option<String>,
+
+    /// Text written before the first templated message
+    #[arg(
+        long,
+        value_name = "TEXT",
+        value_parser = parse_template,
+        requires = "context_window_format",
+        help = "Text written before the --context-window-format output"
+    )]
+    conversation_start_marker: Option<String>,
+
+    /// Text written after the last templated message
+    #[arg(
+        long,
+        value_name = "TEXT",
+        value_parser = parse_template,
+        requires = "context_window_format",
+        help = "Text written after the --context-window-format output"
+    )]
+    conversation_end_marker: Option<String>,
+
+    /// Text written between templated messages
+    #[arg(
+        long,
+        value_name = "TEXT",
+        value_parser = parse_template,
+        requires = "context_window_format",
+        help = "Text between --context-window-format messages [default: none]"
+    )]
+    turn_separator: Option<String>,
+
+    /// Append to the output file instead of replacing it
+    #[arg(
+        long,
+        help = "Append to existing output (CSV, JSONL) instead of overwriting"
+    )]
+    append: bool,
+
+    /// Character encoding of the input file
+    #[arg(
+        long,
+        value_enum,
+        value_name = "ENCODING",
+        default_value = "utf-8",
+        help = "Input encoding: utf-8, windows-1252, iso-8859-1, shift-jis, gb2312"
+    )]
+    input_encoding: InputEncoding,
+
+    /// Scan the raw input for encoding problems before processing
+    #[arg(
+        long,
+        help = "Warn about invalid UTF-8, BOMs, CRLF line endings and mojibake in the input"
+    )]
+    char_encoding_report: bool,
+
+    /// Write the CSV header row (default)
+    #[arg(
+        long,
+        overrides_with = "no_csv_header",
+        help = "Write the CSV header row (default)"
+    )]
+    csv_header: bool,
+
+    /// Omit the CSV header row
+    #[arg(long, overrides_with = "csv_header", help = "Omit the CSV header row")]
+    no_csv_header: bool,
+
+    /// CSV quote character
+    #[arg(
+        long,
+        value_name = "CHAR",
+        default_value = "\"",
+        value_parser = parse_csv_char,
+        help = "CSV quote character"
+    )]
+    csv_quote_char: u8,
+
+    /// CSV escape character for quotes inside fields
+    #[arg(
+        long,
+        value_name = "CHAR",
+        value_parser = parse_csv_char,
+        help = "Escape quotes with CHAR instead of doubling them"
+    )]
+    csv_escape_char: Option<u8>,
+
+    /// Text written in CSV fields that have no value
+    #[arg(
+        long,
+        value_name = "TEXT",
+        help = "Write TEXT in CSV fields with no value, e.g. N/A or \\N for MySQL"
+    )]
+    null_replacement: Option<String>,
+
+    /// CSV columns to write, in order
+    #[arg(
+        long,
+        value_name = "FIELDS",
+        help = "Write only these CSV columns, in this order (e.g. sender,text,timestamp)"
+    )]
+    field_order: Option<String>,
+
+    /// Wrap JSON/JSONL output with export metadata
+    #[arg(long, help = "Add export metadata to JSON/JSONL output")]
+    json_envelope: bool,
+
+    /// Write one output file per sender
+    #[arg(long, help = "Write one output file per sender (implies --no-merge)")]
+    split_by_sender: bool,
+
+    /// Write one output file per conversation
+    #[arg(
+        long,
+        conflicts_with_all = ["split_by_sender", "window_size", "conversation_turns"],
+        help = "Write one output file per conversation/channel"
+    )]
+    split_by_conversation: bool,
+
+    /// Write overlapping windows of N consecutive messages
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with = "split_by_sender",
+        help = "Write sliding windows of N messages"
+    )]
+    window_size: Option<u64>,
+
+    /// Write stretches of conversation between exactly two senders
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "output_url",
+            "pivot",
+            "context_window_format",
+            "split_by_sender",
+            "split_by_conversation",
+            "window_size",
+            "conversation_turns",
+        ],
+        help = "Write two-person dialogues, split when a third sender interjects"
+    )]
+    chunk_by_sender_pairs: bool,
+
+    /// Write one output file per keyword topic
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = [
+            "output_url",
+            "pivot",
+            "context_window_format",
+            "split_by_sender",
+            "split_by_conversation",
+            "window_size",
+            "conversation_turns",
+            "chunk_by_sender_pairs",
+        ],
+        help = "Write one file per topic of a JSON {\"topic\": [\"keyword\", ...]} file, plus _unclassified"
+    )]
+    topic_filter: Option<PathBuf>,
+
+    /// Messages between the starts of consecutive windows
+    #[arg(
+        long,
+        value_name = "M",
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires = "window_size",
+        help = "Step between windows [default: window size]"
+    )]
+    window_step: Option<u64>,
+
+    /// Write human/assistant turn pairs instead of messages
+    #[arg(
+        long,
+        requires = "assistant_name",
+        conflicts_with_all = ["split_by_sender", "window_size"],
+        help = "Write human/assistant turn pairs (needs --assistant-name)"
+    )]
+    conversation_turns: bool,
+
+    /// Sender whose messages are the assistant's in --conversation-turns
+    #[arg(
+        long,
+        value_name = "NAME",
+        requires = "conversation_turns",
+        help = "Sender treated as the assistant"
+    )]
+    assistant_name: Option<String>,
+
     /// Don't merge consecutive messages from the same sender
     #[arg(long, help = "Disable message merging")]
     no_merge: bool,
@@ -80,126 +625,1266 @@ struct Cli {
     #[arg(long, value_name = "DATE", help = "Only messages before this date")]
     before: Option<String>,
 
+    /// Filter: only messages with an ID greater than this
+    #[arg(
+        long,
+        value_name = "ID",
+        help = "Only messages with an ID above this one"
+    )]
+    after_id: Option<u64>,
+
+    /// Filter: only messages with an ID less than this
+    #[arg(
+        long,
+        value_name = "ID",
+        help = "Only messages with an ID below this one"
+    )]
+    before_id: Option<u64>,
+
+    /// Only process messages newer than the previous --incremental run
+    #[arg(
+        long,
+        help = "Only process messages added since the last run (state in OUTPUT.state.json)"
+    )]
+    incremental: bool,
+
     /// Filter: only messages from specific sender
     #[arg(long, value_name = "USER", help = "Only messages from this sender")]
     from: Option<String>,
 
-    /// Disable streaming mode (load entire file into memory)
-    #[arg(long, help = "Load entire file into memory instead of streaming")]
-    no_streaming: bool,
+    /// Filter: only messages from this conversation
+    #[arg(long, value_name = "ID", help = "Only messages from this conversation")]
+    only_conversation: Option<String>,
 
-    /// Show progress during processing
-    #[arg(long, short = 'p', help = "Show processing progress")]
-    progress: bool,
+    /// Drop automatic greeting/away messages from WhatsApp Business chats
+    #[arg(long, help = "Exclude WhatsApp Business auto-replies")]
+    exclude_auto_replies: bool,
 
-    /// Quiet mode: suppress all output except errors
-    #[arg(long, short = 'q', help = "Suppress informational output")]
-    quiet: bool,
-}
+    /// Abort when parsing produces warnings
+    #[arg(long, help = "Exit with an error if parsing produces warnings")]
+    fail_on_warning: bool,
 
-/// Supported chat source platforms
-#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
-enum Source {
-    /// Telegram (JSON export)
-    #[value(alias = "tg")]
-    Telegram,
-    /// WhatsApp (TXT export)
-    #[value(alias = "wa")]
-    Whatsapp,
-    /// Instagram (JSON export)
-    #[value(alias = "ig")]
-    Instagram,
-    /// Discord (JSON/TXT/CSV export)
-    #[value(alias = "dc")]
-    Discord,
-}
+    /// Warn about messages without visible text
+    #[arg(long, help = "Print a warning for each message without text")]
+    warn_empty_messages: bool,
 
-impl Source {
-    fn to_platform(self) -> Platform {
-        match self {
-            Source::Telegram => Platform::Telegram,
-            Source::Whatsapp => Platform::WhatsApp,
-            Source::Instagram => Platform::Instagram,
-            Source::Discord => Platform::Discord,
+    /// Drop messages without visible text
+    #[arg(long, help = "Drop messages without text")]
+    skip_empty_messages: bool,
+
+    /// Keep only messages detected as this language
+    #[arg(
+        long,
+        value_name = "LANG",
+        value_parser = language::parse_language,
+        help = "Keep only messages in LANG (en, de, fr, es, ru, zh, ...). Detection is \
+                statistical: it knows 16 languages and misjudges short, mixed or slang-heavy text"
+    )]
+    language_filter: Option<whichlang::Lang>,
+
+    /// Messages with fewer words are kept without detection
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 5,
+        requires = "language_filter",
+        help = "Keep messages under N words without detecting their language"
+    )]
+    language_min_words: usize,
+
+    /// Drop repeated messages (same sender and text), keeping the first
+    #[arg(long, help = "Drop repeats of a message from the same sender")]
+    first_occurrence_only: bool,
+
+    /// Only count repeats within this many seconds of the previous occurrence
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        requires = "first_occurrence_only",
+        help = "Only drop repeats within SECONDS of the previous one [default: whole export]"
+    )]
+    dedup_window: Option<u32>,
+
+    /// Keep only each sender's first message
+    #[arg(long, help = "Keep only the first message from each sender")]
+    first_per_sender: bool,
+
+    /// Keep only each sender's last message
+    #[arg(
+        long,
+        conflicts_with = "first_per_sender",
+        help = "Keep only the last message from each sender"
+    )]
+    last_per_sender: bool,
+
+    /// Randomly keep at most N messages from each sender
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["first_per_sender", "last_per_sender"],
+        help = "Keep a random sample of at most N messages per sender"
+    )]
+    sample_balanced_by_sender: Option<u64>,
+
+    /// Seed for --sample-balanced-by-sender
+    #[arg(
+        long,
+        value_name = "SEED",
+        requires = "sample_balanced_by_sender",
+        help = "Seed the sampling to get the same sample on every run"
+    )]
+    seed: Option<u64>,
+
+    /// Refuse to process conversations with more senders than this
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Exit with an error if the filtered messages have more than N senders"
+    )]
+    max_participants: Option<usize>,
+
+    /// Process anyway when a safeguard like --max-participants trips
+    #[arg(
+        long,
+        requires = "max_participants",
+        help = "Ignore --max-participants"
+    )]
+    force: bool,
+
+    /// Keep N messages of context around each filter match
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Include N surrounding messages around each filter match"
+    )]
+    include_context: Option<usize>,
+
+    /// Normalize message text and sender names to a Unicode form
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORM",
+        help = "Normalize text and senders to NFC, NFD, NFKC, or NFKD"
+    )]
+    normalize_unicode: Option<UnicodeForm>,
+
+    /// Lowercase sender names in messages and in --from
+    #[arg(long, help = "Normalize sender names to lowercase")]
+    lowercase_senders: bool,
+
+    /// Treat INPUT as a directory of Discord channel exports
+    #[arg(
+        long,
+        help = "INPUT is a Discord server export directory (one JSON per channel)"
+    )]
+    discord_server: bool,
+
+    /// Attribute sender-less Telegram channel posts to the channel
+    #[arg(
+        long,
+        help = "Use the channel name as sender for Telegram channel posts"
+    )]
+    channel_mode: bool,
+
+    /// Keep Telegram service messages (renames, pins, migrations)
+    #[arg(
+        long,
+        help = "Keep Telegram group events like renames and pins as messages"
+    )]
+    include_service_messages: bool,
+
+    /// Sender name for Telegram Saved Messages exports
+    #[arg(
+        long,
+        value_name = "NAME",
+        default_value = "Me",
+        help = "Sender for Telegram Saved Messages"
+    )]
+    saved_messages_sender: String,
+
+    /// Refuse in-memory parsing when the estimated memory use is above this
+    #[arg(
+        long,
+        value_name = "BYTES",
+        value_parser = memory::parse_byte_size,
+        help = "Refuse to load the export into memory above this estimate (e.g. 2GB)"
+    )]
+    max_memory: Option<u64>,
+
+    /// Disable streaming mode (load entire file into memory)
+    #[arg(long, help = "Load entire file into memory instead of streaming")]
+    no_streaming: bool,
+
+    /// Retries after I/O errors while streaming
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 0,
+        conflicts_with = "no_streaming",
+        help = "Resume streaming up to N times after an I/O error"
+    )]
+    max_retries: u32,
+
+    /// Show progress during processing
+    #[arg(long, short = 'p', help = "Show processing progress")]
+    progress: bool,
+
+    /// Re-process whenever the input file changes
+    #[arg(long, help = "Watch the input file and re-process on change")]
+    watch: bool,
+
+    /// Debounce interval for --watch in milliseconds
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 500,
+        requires = "watch",
+        help = "Wait N ms of quiet before re-processing"
+    )]
+    debounce_ms: u64,
+
+    /// Print build metadata for bug reports
+    #[arg(
+        long,
+        exclusive = true,
+        help = "Print version, commit, toolchain and build details"
+    )]
+    version_info: bool,
+
+    /// Print the JSON Schema of the output for the chosen format and flags
+    #[arg(
+        long,
+        help = "Print the JSON Schema of the output and exit",
+        conflicts_with = "watch"
+    )]
+    output_schema: bool,
+
+    /// Print per-stage timings (for profiling)
+    #[arg(long, hide = true)]
+    benchmark: bool,
+
+    /// Count messages without writing any output file
+    #[arg(
+        long,
+        group = "listing",
+        conflicts_with_all = [
+            "watch",
+            "incremental",
+            "append",
+            "split_by_sender",
+            "split_by_conversation",
+            "window_size",
+            "conversation_turns",
+        ],
+        help = "Print message counts to stdout without writing output"
+    )]
+    summary_only: bool,
+
+    /// List the senders of the filtered messages instead of converting
+    #[arg(
+        long,
+        group = "listing",
+        conflicts_with_all = [
+            "watch",
+            "incremental",
+            "append",
+            "split_by_sender",
+            "split_by_conversation",
+            "window_size",
+            "conversation_turns",
+        ],
+        help = "Print unique sender names to stdout without writing output"
+    )]
+    participant_list: bool,
+
+    /// List the days with at least one filtered message instead of converting
+    #[arg(
+        long,
+        group = "listing",
+        conflicts_with_all = [
+            "watch",
+            "incremental",
+            "append",
+            "split_by_sender",
+            "split_by_conversation",
+            "window_size",
+            "conversation_turns",
+        ],
+        help = "Print the dates (YYYY-MM-DD) with messages to stdout without writing output"
+    )]
+    date_list: bool,
+
+    /// Report the N longest two-person exchanges instead of converting
+    #[arg(
+        long,
+        value_name = "N",
+        group = "listing",
+        conflicts_with_all = [
+            "watch",
+            "incremental",
+            "append",
+            "split_by_sender",
+            "split_by_conversation",
+            "window_size",
+            "conversation_turns",
+        ],
+        help = "Print the N longest two-person exchanges as JSON without writing output"
+    )]
+    longest_exchange: Option<usize>,
+
+    /// Write a Markdown report of the conversion next to the output
+    #[arg(
+        long,
+        conflicts_with = "listing",
+        help = "Write a Markdown report to OUTPUT.report.md"
+    )]
+    report: bool,
+
+    /// Words left out of the top words of --report and --summary-only
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Leave the words in PATH (one per line) out of the top words"
+    )]
+    stopwords_file: Option<PathBuf>,
+
+    /// Count the emoji of the written messages next to the output
+    #[arg(
+        long,
+        conflicts_with = "listing",
+        help = "Write emoji frequencies to OUTPUT.emoji_stats.json"
+    )]
+    emoji_stats: bool,
+
+    /// Write per-participant statistics next to the output
+    #[arg(
+        long,
+        conflicts_with = "listing",
+        help = "Write per-sender statistics to OUTPUT.sender_stats.csv (.json for JSON output)"
+    )]
+    sender_stats: bool,
+
+    /// Print the --summary-only report or a listing as JSON
+    #[arg(long, requires = "listing", help = "Print the summary or list as JSON")]
+    json: bool,
+
+    /// Quiet mode: suppress all output except errors
+    #[arg(long, short = 'q', help = "Suppress informational output")]
+    quiet: bool,
+
+    /// Only print the final summary, on a single line
+    #[arg(
+        long,
+        short = 'Q',
+        conflicts_with = "quiet",
+        help = "Suppress progress output, keep a one-line summary"
+    )]
+    quiet_progress: bool,
+}
+
+/// Check that an export looks processable without converting it.
+///
+/// Reads only the start of the file and checks its first messages.
+#[derive(Parser, Debug)]
+#[command(name = "chatpack validate")]
+#[command(version, about, long_about = None)]
+struct ValidateCli {
+    /// Chat source platform
+    #[arg(
+        value_enum,
+        help = "Source platform: telegram, whatsapp, instagram, discord"
+    )]
+    source: Source,
+
+    /// Input file path
+    #[arg(help = "Path to the exported chat file")]
+    input: PathBuf,
+}
+
+/// Supported chat source platforms
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum Source {
+    /// Telegram (JSON export)
+    #[value(alias = "tg")]
+    Telegram,
+    /// WhatsApp (TXT export)
+    #[value(alias = "wa")]
+    Whatsapp,
+    /// Instagram (JSON export)
+    #[value(alias = "ig")]
+    Instagram,
+    /// Discord (JSON/TXT/CSV export)
+    #[value(alias = "dc")]
+    Discord,
+}
+
+impl Source {
+    fn to_platform(self) -> Platform {
+        match self {
+            Source::Telegram => Platform::Telegram,
+            Source::Whatsapp => Platform::WhatsApp,
+            Source::Instagram => Platform::Instagram,
+            Source::Discord => Platform::Discord,
+        }
+    }
+}
+
+/// Output format options
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// CSV format (best for LLM context, ~13x token compression)
+    #[default]
+    Csv,
+    /// JSON array format
+    Json,
+    /// JSON Lines format (one object per line, for RAG pipelines)
+    Jsonl,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::Csv => "CSV",
+            Format::Json => "JSON",
+            Format::Jsonl => "JSONL",
+        }
+    }
+
+    /// MIME type of the format, for HTTP uploads
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv",
+            Format::Json => "application/json",
+            Format::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Json => "json",
+            Format::Jsonl => "jsonl",
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    // Checked before parsing: SOURCE and INPUT aren't needed for it
+    if std::env::args_os()
+        .skip(1)
+        .any(|arg| arg == "--version-info")
+    {
+        print_version_info();
+        return Ok(());
+    }
+
+    // Checked by hand so that `chatpack <SOURCE> <INPUT>` needs no subcommand
+    if std::env::args_os()
+        .nth(1)
+        .is_some_and(|arg| arg == "validate")
+    {
+        return run_validate(&ValidateCli::parse_from(std::env::args_os().skip(1)));
+    }
+
+    let mut cli = Cli::parse();
+
+    // An explicit -f always wins over the output extension
+    cli.format = match cli.format_arg {
+        Some(format) => format,
+        None => infer_format(&cli.output).unwrap_or_else(|| {
+            // Templates write plain text whatever the extension
+            if !cli.quiet && cli.context_window_format.is_none() {
+                eprintln!(
+                    "⚠️  Unknown output extension for {}, writing CSV (use -f to choose)",
+                    cli.output.display()
+                );
+            }
+            Format::Csv
+        }),
+    };
+
+    if cli.output_schema {
+        return print_output_schema(&cli);
+    }
+
+    cli.text_replacements = cli
+        .replace_text_pattern
+        .chunks(2)
+        .map(|pair| {
+            let pattern = Regex::new(&pair[0])
+                .with_context(|| format!("Invalid --replace-text-pattern regex '{}'", pair[0]))?;
+            Ok((pattern, pair[1].clone()))
+        })
+        .collect::<Result<_>>()?;
+
+    if cli.append && cli.format == Format::Json {
+        bail!(
+            "--append can't extend a JSON array; use -f jsonl for incremental output\n\n\
+             Tip: JSONL adds one object per line, so new runs can be appended."
+        );
+    }
+
+    if cli.pivot && cli.format != Format::Csv {
+        bail!("--pivot writes CSV; use a .csv output or -f csv");
+    }
+
+    if let Some(max_chars) = cli.max_line_length {
+        // Room for the marker and the indent of template continuations
+        let marker = cli.continuation_marker.as_deref().unwrap_or(" ↵ ");
+        if max_chars <= marker.chars().count() + 2 {
+            bail!(
+                "--max-line-length {} leaves no room for text next to the continuation marker",
+                max_chars
+            );
+        }
+    }
+
+    if cli.output_bom && (cli.format != Format::Csv || cli.context_window_format.is_some()) {
+        bail!(
+            "--output-bom is for CSV opened in Excel\n\n\
+             Tip: --output-encoding utf-8-bom adds a BOM to any format."
+        );
+    }
+
+    if !cli.http_header.is_empty() && cli.input_url.is_none() && cli.output_url.is_none() {
+        bail!("--http-header needs --input-url or --output-url");
+    }
+
+    // Removed when main returns, after every reader is done with it
+    let remote = match &cli.input_url {
+        Some(url) => Some(RemoteInput::fetch(url, &cli.http_header)?),
+        None => None,
+    };
+    cli.input = match (&remote, &cli.input_arg) {
+        (Some(remote), _) => remote.path().to_path_buf(),
+        (None, Some(input)) => input.clone(),
+        (None, None) => unreachable!("clap requires INPUT without --input-url"),
+    };
+    cli.display_input = cli.input.clone();
+
+    // Validate input file exists
+    if !cli.input.exists() {
+        bail!(
+            "Input file not found: {}\n\nTip: Make sure the path is correct and the file exists.",
+            cli.input.display()
+        );
+    }
+
+    if let (Some(after), Some(before)) = (cli.after_id, cli.before_id) {
+        if after >= before {
+            bail!(
+                "--after-id ({}) must be less than --before-id ({})",
+                after,
+                before
+            );
+        }
+    }
+
+    if cli.discord_server && (cli.source != Source::Discord || !cli.input.is_dir()) {
+        bail!("--discord-server expects a Discord source and a directory of channel exports");
+    }
+
+    if cli.watch {
+        let debounce = Duration::from_millis(cli.debounce_ms);
+        return watch::watch_file(&cli.input, debounce, cli.quiet, || run(&cli));
+    }
+
+    run(&cli)
+}
+
+/// `chatpack` library features enabled in Cargo.toml (`full`)
+const LIBRARY_FEATURES: &str =
+    "telegram, whatsapp, instagram, discord, csv-output, json-output, streaming";
+
+/// Print the details asked for in bug reports
+fn print_version_info() {
+    println!("chatpack-cli {}", env!("CARGO_PKG_VERSION"));
+    println!("commit:     {}", env!("CHATPACK_GIT_SHA"));
+    println!("library:    chatpack {}", env!("CHATPACK_LIB_VERSION"));
+    println!("features:   {}", LIBRARY_FEATURES);
+    println!("rustc:      {}", env!("CHATPACK_RUSTC_VERSION"));
+    println!("profile:    {}", env!("CHATPACK_PROFILE"));
+    println!("target:     {}", env!("CHATPACK_TARGET"));
+
+    let platforms: Vec<_> = Source::value_variants()
+        .iter()
+        .map(|source| source.to_platform().to_string())
+        .collect();
+    println!("platforms:  {}", platforms.join(", "));
+
+    let formats: Vec<_> = Format::value_variants()
+        .iter()
+        .map(|format| format.extension())
+        .collect();
+    println!("formats:    {}", formats.join(", "));
+}
+
+/// Print the schema of the output `cli` would write, without reading INPUT
+fn print_output_schema(cli: &Cli) -> Result<()> {
+    let mut options = output_options(cli)?;
+    if cli.relative_timestamps {
+        options = options.with_relative_timestamps(Utc::now());
+    }
+
+    let schema = if cli.assistant_name.is_some() {
+        schema::generate_turns_schema(cli.format)
+    } else if cli.window_size.is_some() && cli.format == Format::Jsonl {
+        schema::generate_window_schema(&options)
+    } else {
+        schema::generate_schema(&options, cli.format)
+    };
+
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+    Ok(())
+}
+
+/// Check the start of an export and report whether it looks processable
+fn run_validate(args: &ValidateCli) -> Result<()> {
+    if !args.input.is_file() {
+        bail!("Input file not found: {}", args.input.display());
+    }
+
+    let report = validate::validate(args.source.to_platform(), &args.input)?;
+    report.print(&args.input);
+
+    if !report.is_ok() {
+        bail!(
+            "{} does not look like a processable {} export",
+            args.input.display(),
+            args.source.to_platform()
+        );
+    }
+
+    Ok(())
+}
+
+/// Run the parse → filter → merge → write pipeline once
+fn run(cli: &Cli) -> Result<()> {
+    let mut timings = Timings::start();
+
+    if !cli.quiet && !cli.quiet_progress {
+        eprintln!(
+            "📦 Parsing {} export: {}",
+            cli.source.to_platform(),
+            cli.input.display()
+        );
+    }
+
+    if cli.char_encoding_report && cli.input.is_file() {
+        report_encoding_issues(cli)?;
+    }
+
+    // Transcode first so every reader below sees UTF-8
+    let decoded = match cli.input_encoding {
+        InputEncoding::Utf8 => None,
+        encoding => Some(DecodedInput::new(&cli.input, encoding)?),
+    };
+    let decoded_cli;
+    let cli = match decoded {
+        Some(ref decoded) => {
+            if decoded.had_errors && !cli.quiet {
+                eprintln!("⚠️  Some input bytes were invalid and replaced with �");
+            }
+            decoded_cli = Cli {
+                input: decoded.path().to_path_buf(),
+                ..cli.clone()
+            };
+            &decoded_cli
+        }
+        None => cli,
+    };
+
+    let contacts = match &cli.pretty_names {
+        Some(path) if cli.source == Source::Whatsapp => Some(ContactMap::load(path)?),
+        _ => None,
+    };
+    let aliases = cli
+        .sender_alias_file
+        .as_deref()
+        .map(SenderAliases::load)
+        .transpose()?;
+    let stopwords = cli
+        .stopwords_file
+        .as_deref()
+        .map(report::load_stopwords)
+        .transpose()?;
+    let conversation_stats = |messages: &[Message], parsed: usize| {
+        let stats = ConversationStats::new(messages, parsed);
+        match &stopwords {
+            Some(words) => stats.with_stopwords(words.clone()),
+            None => stats,
+        }
+    };
+
+    // Build filter configuration
+    let mut filter = FilterConfig::new();
+
+    if let Some(ref after_date) = cli.after {
+        filter = filter.with_date_from(after_date).with_context(|| {
+            format!(
+                "Invalid --after date format: '{}'. Expected YYYY-MM-DD",
+                after_date
+            )
+        })?;
+    }
+
+    if let Some(ref before_date) = cli.before {
+        filter = filter.with_date_to(before_date).with_context(|| {
+            format!(
+                "Invalid --before date format: '{}'. Expected YYYY-MM-DD",
+                before_date
+            )
+        })?;
+    }
+
+    if let Some(ref sender) = cli.from {
+        // Compare against senders in the same form as the normalized messages
+        let mut sender = match cli.normalize_unicode {
+            Some(form) => form.apply(sender),
+            None => sender.clone(),
+        };
+        if cli.lowercase_senders {
+            sender = sender.to_lowercase();
+        }
+        filter = filter.with_sender(sender);
+    }
+
+    let mut output_options = output_options(cli)?;
+
+    // Parse messages
+    let mut telegram_options = TelegramOptions {
+        media_descriptions: cli.include_media_description,
+        default_sender: None,
+        channel_mode: cli.channel_mode,
+        service_messages: cli.include_service_messages,
+    };
+
+    if cli.source == Source::Telegram
+        && telegram::chat_type(&cli.input)?.as_deref() == Some("saved_messages")
+    {
+        // Saved Messages have no `from`; they're all from the exporting user
+        telegram_options.default_sender = Some(cli.saved_messages_sender.clone());
+    }
+
+    // Conversation of each message, for multi-conversation inputs without IDs
+    let mut conversations = Vec::new();
+    let mut parsed_count = None;
+
+    let mut messages = if cli.discord_server {
+        discord::parse_server(&cli.input)?
+    } else if cli.source == Source::Discord && discord::is_html(&cli.input) {
+        discord::parse_html(&cli.input)?
+    } else if cli.source == Source::Instagram && cli.input.is_dir() {
+        let (messages, inbox_conversations) = instagram::parse_inbox(&cli.input)?;
+        conversations = inbox_conversations;
+        messages
+    } else if cli.source == Source::Telegram && telegram_options.is_active() {
+        // The library parser drops these messages; read the raw export
+        check_memory_limit(cli, false)?;
+        telegram::parse(&cli.input, &telegram_options)
+            .with_context(|| format!("Failed to parse {} export", cli.source.to_platform()))?
+    } else if cli.source == Source::Instagram {
+        // Older Instagram layouts need format detection before parsing
+        match parse_messages(cli, None) {
+            Ok((messages, _)) if !messages.is_empty() => messages,
+            _ => instagram::parse_file(&cli.input)?,
+        }
+    } else {
+        let prefilter = filters_while_parsing(cli, &filter, &output_options).then_some(&filter);
+        let (messages, count) = parse_messages(cli, prefilter)?;
+        parsed_count = Some(count);
+        messages
+    };
+
+    // WhatsApp lines are matched to messages by position, so they're taken
+    // before any message is dropped or sender renamed
+    let mut source_lines: Option<Vec<Option<usize>>> =
+        if cli.line_numbers && cli.source == Source::Whatsapp && cli.input.is_file() {
+            let lines = extras::whatsapp_message_lines(&cli.input)?;
+            Some(
+                (0..messages.len())
+                    .map(|index| lines.get(index).copied())
+                    .collect(),
+            )
+        } else {
+            None
+        };
+
+    if let Some(contacts) = &contacts {
+        contacts.apply(&mut messages);
+    }
+
+    if cli.hash_ids {
+        transform::hash_ids(&mut messages);
+    }
+
+    // After hashing, so IDs stay the same when the aliases change
+    if let Some(aliases) = &aliases {
+        aliases.apply(&mut messages);
+    }
+
+    if cli.include_media_description && cli.source != Source::Telegram {
+        let media_dir = cli
+            .whatsapp_media_dir
+            .as_deref()
+            .filter(|_| cli.source == Source::Whatsapp);
+        for msg in &mut messages {
+            msg.content = media::describe_attachments(&msg.content, media_dir);
+        }
+    }
+
+    let total_parsed = parsed_count.unwrap_or(messages.len());
+    timings.lap("parse");
+
+    if !cli.quiet || cli.fail_on_warning {
+        let warnings = warnings::collect(cli.source.to_platform(), &cli.input, &messages)?;
+        if !cli.quiet {
+            for warning in &warnings {
+                eprintln!("⚠️  Parse warning: {}", warning);
+            }
+        }
+        if cli.fail_on_warning && !warnings.is_empty() {
+            bail!(
+                "{} parse warning(s) found\n\nTip: Drop --fail-on-warning to process the export anyway.",
+                warnings.len()
+            );
+        }
+    }
+
+    if cli.source == Source::Whatsapp {
+        if let whatsapp::WhatsAppVariant::Business { name } = whatsapp::detect_variant(&cli.input)?
+        {
+            whatsapp::strip_business_notices(&mut messages);
+
+            if let (true, Some(business)) = (cli.exclude_auto_replies, name) {
+                let kinds = whatsapp::classify(&messages, &business);
+                let mut kinds = kinds.into_iter();
+                transform::retain_aligned(&mut messages, source_lines.as_mut(), |_| {
+                    kinds.next() != Some(whatsapp::MessageKind::AutoReply)
+                });
+            }
+        }
+    }
+
+    if cli.warn_empty_messages || cli.skip_empty_messages {
+        transform::retain_aligned(&mut messages, source_lines.as_mut(), |msg| {
+            if !msg.is_empty_text() {
+                return true;
+            }
+            if cli.warn_empty_messages {
+                let at = msg.timestamp.map_or_else(
+                    || "unknown time".to_string(),
+                    |ts| ts.format("%Y-%m-%d %H:%M:%S").to_string(),
+                );
+                eprintln!("⚠️  Empty message from {} at {}", msg.sender, at);
+            }
+            !cli.skip_empty_messages
+        });
+    }
+
+    if let Some(form) = cli.normalize_unicode {
+        transform::normalize_unicode(&mut messages, form);
+    }
+
+    if cli.lowercase_senders {
+        transform::lowercase_senders(&mut messages);
+    }
+
+    // Collect fields the parsers don't expose
+    let needs_extras = output_options.needs_extras()
+        || cli.only_conversation.is_some()
+        || cli.split_by_conversation;
+    let mut extras = if needs_extras {
+        extras::load_extras(cli.source.to_platform(), &cli.input)?
+    } else {
+        Extras::new()
+    };
+
+    extras.record_conversations(&messages, &conversations);
+
+    if cli.line_numbers {
+        extras.record_source_lines(cli.source.to_platform(), &cli.input)?;
+    }
+    if let Some(lines) = &source_lines {
+        extras.record_message_lines(&messages, lines);
+    }
+
+    if matches!(
+        output_options.reply_format,
+        ReplyFormat::Quote | ReplyFormat::Inline
+    ) {
+        extras.record_reply_targets(&messages);
+    }
+
+    // IDs cut the export itself, so context never reaches outside the range
+    let id_range = IdRange {
+        after: cli.after_id,
+        before: cli.before_id,
+    };
+    if id_range.is_active() {
+        messages.retain(|msg| id_range.contains(msg));
+    }
+
+    // New state is computed before filtering: filtered-out messages count as seen
+    let incremental = if cli.incremental {
+        let path = incremental::state_path(&cli.output);
+        let state = IncrementalState::load(&path)?;
+        state.retain_new(&mut messages);
+        Some((path, state.advance(&messages)))
+    } else {
+        None
+    };
+
+    // Apply filters, optionally keeping surrounding context
+    let mut filtered = match cli.include_context {
+        Some(n) if filter.is_active() => {
+            let matched = filter::matching_indices(&messages, &filter);
+            transform::expand_context(&matched, &messages, n)
+        }
+        _ => filter.apply(messages),
+    };
+
+    if let Some(ref conversation) = cli.only_conversation {
+        filtered.retain(|msg| extras.get(msg).conversation_id.as_ref() == Some(conversation));
+    }
+
+    if let Some(lang) = cli.language_filter {
+        language::retain_language(&mut filtered, lang, cli.language_min_words);
+    }
+
+    if cli.first_occurrence_only {
+        let window = cli
+            .dedup_window
+            .map(|secs| TimeDelta::seconds(i64::from(secs)));
+        filtered = transform::dedup_first_occurrence(filtered, window);
+    }
+
+    if cli.first_per_sender {
+        filtered = transform::first_per_sender(filtered);
+    } else if cli.last_per_sender {
+        filtered = transform::last_per_sender(filtered);
+    }
+
+    if let Some(max) = cli.sample_balanced_by_sender {
+        let mut rng = match cli.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => rand::make_rng(),
+        };
+        filtered = transform::sample_balanced(filtered, max as usize, &mut rng);
+    }
+    let filtered_count = filtered.len();
+
+    if let Some(max) = cli.max_participants.filter(|_| !cli.force) {
+        let participants: HashSet<&str> = filtered.iter().map(|msg| msg.sender.as_str()).collect();
+        if participants.len() > max {
+            bail!(
+                "Too many participants ({} > {}); use --force to process anyway",
+                participants.len(),
+                max
+            );
+        }
+    }
+
+    if cli.relative_timestamps {
+        let reference = cli
+            .reference_time
+            .or_else(|| filtered.iter().filter_map(|msg| msg.timestamp).max())
+            .unwrap_or_else(Utc::now);
+        output_options = output_options.with_relative_timestamps(reference);
+    }
+
+    if cli.summary_only {
+        let stats = conversation_stats(&filtered, total_parsed);
+        return print_summary_only(cli, filtered, &stats);
+    }
+
+    if cli.participant_list {
+        return print_participants(cli, &filtered, &output_options);
+    }
+
+    if cli.date_list {
+        return print_dates(cli, &filtered, &output_options);
+    }
+
+    if let Some(count) = cli.longest_exchange {
+        return print_longest_exchanges(filtered, count);
+    }
+    timings.lap("filter");
+
+    let stats = cli
+        .report
+        .then(|| conversation_stats(&filtered, total_parsed));
+    let emoji_stats = cli
+        .emoji_stats
+        .then(|| emoji::EmojiStats::new(emoji::count_emojis(&filtered)));
+    let sender_stats = cli
+        .sender_stats
+        .then(|| sender_stats::sender_stats(&filtered));
+
+    let outputs = write_results(
+        cli,
+        filtered,
+        &mut extras,
+        &output_options,
+        total_parsed,
+        filtered_count,
+        &mut timings,
+    )?;
+
+    if cli.verify_output {
+        for path in &outputs {
+            verify_output(path, cli.format, &output_options)?;
+        }
+    }
+
+    if cli.benchmark {
+        timings.print(&cli.display_input, &outputs, total_parsed);
+    }
+
+    if let Some(stats) = stats {
+        let path = report::report_path(&cli.output);
+        report::write_report(&stats.with_outputs(&outputs), cli, &path)?;
+    }
+
+    if let Some(emoji_stats) = emoji_stats {
+        emoji_stats.write(&emoji::stats_path(&cli.output))?;
+    }
+
+    if let Some(sender_stats) = sender_stats {
+        let json = cli.format != Format::Csv;
+        let path = sender_stats::stats_path(&cli.output, json);
+        sender_stats::write(&sender_stats, &path, json)?;
+    }
+
+    // Only recorded once the output is safely written
+    if let Some((path, state)) = incremental {
+        state.save(&path)?;
+    }
+
+    Ok(())
+}
+
+/// Write the filtered messages in the layout selected on the command line.
+///
+/// Returns the files written.
+fn write_results(
+    cli: &Cli,
+    filtered: Vec<Message>,
+    extras: &mut Extras,
+    output_options: &OutputOptions,
+    total_parsed: usize,
+    filtered_count: usize,
+    timings: &mut Timings,
+) -> Result<Vec<PathBuf>> {
+    // Pivot tables count the messages as exported, before merging
+    if cli.pivot {
+        let table = transform::pivot_messages(&filtered, cli.pivot_by, cli.pivot_metric);
+        output::write_pivot_csv(&table, &cli.output, output_options)
+            .with_context(|| format!("Failed to write CSV to {}", cli.output.display()))?;
+        timings.lap("write");
+
+        let outputs = vec![cli.output.clone()];
+        if !cli.quiet {
+            print_summary(cli, total_parsed, filtered_count, filtered_count, &outputs);
+        }
+
+        return Ok(outputs);
+    }
+
+    // Per-sender files are written unmerged
+    if cli.split_by_sender {
+        let outputs = write_split_by_sender(filtered, extras, cli, output_options)?;
+        timings.lap("write");
+
+        if !cli.quiet {
+            print_summary(cli, total_parsed, filtered_count, filtered_count, &outputs);
+        }
+
+        return Ok(outputs);
+    }
+
+    // Turns merge each speaker's consecutive messages themselves
+    if let Some(ref assistant) = cli.assistant_name {
+        let turns = transform::conversation_turns(&filtered, assistant);
+        write_turns(&turns, &cli.output, cli.format, output_options)?;
+        timings.lap("write");
+
+        if !cli.quiet {
+            print_summary(
+                cli,
+                total_parsed,
+                filtered_count,
+                turns.len(),
+                std::slice::from_ref(&cli.output),
+            );
+        }
+
+        return Ok(vec![cli.output.clone()]);
+    }
+
+    if cli.split_by_conversation {
+        let (outputs, final_count) =
+            write_split_by_conversation(filtered, extras, cli, output_options)?;
+        timings.lap("merge + write");
+
+        if !cli.quiet {
+            print_summary(cli, total_parsed, filtered_count, final_count, &outputs);
+        }
+
+        return Ok(outputs);
+    }
+
+    // Optionally merge consecutive messages
+    let processed = if cli.no_merge {
+        filtered
+    } else {
+        extras::fold_merged(&filtered, extras);
+        MergeIterator::new(filtered.into_iter()).collect()
+    };
+
+    let final_count = processed.len();
+    timings.lap("merge");
+
+    if let Some(size) = cli.window_size {
+        let step = cli.window_step.unwrap_or(size);
+        let windows = transform::sliding_window(&processed, size as usize, step as usize);
+        let outputs = write_windows(&windows, extras, cli, output_options)?;
+        timings.lap("write");
+
+        if !cli.quiet {
+            print_summary(cli, total_parsed, filtered_count, final_count, &outputs);
         }
+
+        return Ok(outputs);
     }
 
-    fn name(self) -> &'static str {
-        match self {
-            Source::Telegram => "Telegram",
-            Source::Whatsapp => "WhatsApp",
-            Source::Instagram => "Instagram",
-            Source::Discord => "Discord",
+    if cli.chunk_by_sender_pairs {
+        let dialogues = transform::extract_two_party_dialogues(processed);
+        let outputs = write_dialogues(&dialogues, extras, cli, output_options)?;
+        timings.lap("write");
+
+        if !cli.quiet {
+            print_summary(cli, total_parsed, filtered_count, final_count, &outputs);
         }
+
+        return Ok(outputs);
     }
-}
 
-/// Output format options
-#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
-enum Format {
-    /// CSV format (best for LLM context, ~13x token compression)
-    Csv,
-    /// JSON array format
-    Json,
-    /// JSON Lines format (one object per line, for RAG pipelines)
-    Jsonl,
-}
+    if let Some(ref path) = cli.topic_filter {
+        let classifier = TopicClassifier::load(path)?;
+        let outputs = write_split_by_topic(processed, extras, &classifier, cli, output_options)?;
+        timings.lap("write");
 
-impl Format {
-    fn name(self) -> &'static str {
-        match self {
-            Format::Csv => "CSV",
-            Format::Json => "JSON",
-            Format::Jsonl => "JSONL",
+        if !cli.quiet {
+            print_summary(cli, total_parsed, filtered_count, final_count, &outputs);
         }
+
+        return Ok(outputs);
     }
-}
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+    if let Some(ref url) = cli.output_url {
+        let body = render_output(&processed, extras, cli.format, output_options)?;
+        remote::upload(
+            url,
+            cli.http_method,
+            cli.format.content_type(),
+            &cli.http_header,
+            &body,
+        )?;
+        timings.lap("write");
+
+        let outputs = vec![PathBuf::from(url)];
+        if !cli.quiet {
+            print_summary(cli, total_parsed, filtered_count, final_count, &outputs);
+        }
 
-    // Validate input file exists
-    if !cli.input.exists() {
-        bail!(
-            "Input file not found: {}\n\nTip: Make sure the path is correct and the file exists.",
-            cli.input.display()
-        );
+        return Ok(outputs);
     }
 
+    // Write output
+    match cli.context_window_format {
+        Some(ref message) => {
+            let template = Template {
+                message: message.clone(),
+                start_marker: cli.conversation_start_marker.clone().unwrap_or_default(),
+                end_marker: cli.conversation_end_marker.clone().unwrap_or_default(),
+                separator: cli.turn_separator.clone().unwrap_or_default(),
+            };
+            output::write_template_to_file(
+                &processed,
+                extras,
+                &template,
+                &cli.output,
+                output_options,
+            )
+            .with_context(|| format!("Failed to write {}", cli.output.display()))?;
+        }
+        None => write_output(&processed, extras, &cli.output, cli.format, output_options)?,
+    }
+    timings.lap("write");
+
+    // Print summary
     if !cli.quiet {
-        eprintln!(
-            "📦 Parsing {} export: {}",
-            cli.source.name(),
-            cli.input.display()
+        print_summary(
+            cli,
+            total_parsed,
+            filtered_count,
+            final_count,
+            std::slice::from_ref(&cli.output),
         );
     }
 
-    // Build filter configuration
-    let mut filter = FilterConfig::new();
+    Ok(vec![cli.output.clone()])
+}
 
-    if let Some(ref after_date) = cli.after {
-        filter = filter.with_date_from(after_date).with_context(|| {
-            format!(
-                "Invalid --after date format: '{}'. Expected YYYY-MM-DD",
-                after_date
-            )
-        })?;
+/// Parse a --context-window-format template, expanding `\n`, `\t` and `\\`
+/// so line breaks can be typed on the command line
+fn parse_template(s: &str) -> Result<String, String> {
+    let mut template = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            template.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => template.push('\n'),
+            Some('t') => template.push('\t'),
+            Some('\\') => template.push('\\'),
+            Some(other) => {
+                template.push('\\');
+                template.push(other);
+            }
+            None => template.push('\\'),
+        }
     }
+    Ok(template)
+}
 
-    if let Some(ref before_date) = cli.before {
-        filter = filter.with_date_to(before_date).with_context(|| {
-            format!(
-                "Invalid --before date format: '{}'. Expected YYYY-MM-DD",
-                before_date
-            )
-        })?;
+/// Parse a single ASCII character for CSV dialect options
+fn parse_csv_char(s: &str) -> Result<u8, String> {
+    match s.as_bytes() {
+        [b] if b.is_ascii() => Ok(*b),
+        _ => Err(format!("expected a single ASCII character, got '{}'", s)),
     }
+}
 
-    if let Some(ref sender) = cli.from {
-        filter = filter.with_sender(sender);
+/// Parse a --reference-time value: RFC 3339, `YYYY-MM-DD HH:MM:SS`, or `YYYY-MM-DD`
+fn parse_reference_time(s: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(ts) = DateTime::parse_from_rfc3339(s) {
+        return Ok(ts.with_timezone(&Utc));
     }
+    if let Ok(ts) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S") {
+        return Ok(ts.and_utc());
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map(|date| date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+        .map_err(|_| format!("expected YYYY-MM-DD or an RFC 3339 time, got '{}'", s))
+}
 
-    // Build output configuration
+/// Build the output configuration from the command line
+fn output_options(cli: &Cli) -> Result<OutputOptions> {
     let mut output_config = OutputConfig::new();
 
     if cli.timestamps {
@@ -218,53 +1903,237 @@ fn main() -> Result<()> {
         output_config = output_config.with_ids();
     }
 
-    // Parse messages
-    let messages = if cli.no_streaming {
-        parse_full(&cli)?
+    let mut output_options = OutputOptions::new(output_config);
+
+    if cli.include_mentions {
+        output_options = output_options.with_mentions();
+    }
+
+    if cli.include_conversation_id {
+        output_options = output_options.with_conversation_id();
+    }
+
+    if cli.discord_server {
+        output_options = output_options.with_channel_name();
+    }
+
+    // A merged inbox needs the conversation to tell threads apart
+    if cli.source == Source::Instagram && cli.input.is_dir() && !cli.split_by_conversation {
+        output_options = output_options.with_conversation_id();
+    }
+
+    if cli.include_channel_meta {
+        output_options = output_options.with_channel_meta();
+    }
+
+    if cli.line_numbers {
+        output_options = output_options.with_source_lines();
+    }
+
+    if let Some(format) = cli.reply_format {
+        output_options = output_options.with_reply_format(format);
+    }
+
+    if cli.omit_sender {
+        output_options = output_options.without_sender();
+    }
+
+    if cli.prepend_sender {
+        output_options = output_options.with_prepended_sender();
+    }
+
+    if cli.group_by_day {
+        output_options = output_options.with_day_separators();
+    }
+
+    if let Some(minutes) = cli.conversation_boundary {
+        output_options =
+            output_options.with_conversation_boundary(TimeDelta::minutes(i64::from(minutes)));
+    }
+
+    if cli.strip_quotes {
+        output_options = output_options.with_stripped_quotes();
+    }
+
+    if let Some(max_chars) = cli.truncate_text {
+        output_options = output_options.with_truncate_text(max_chars);
+    }
+
+    if let Some(max_chars) = cli.max_line_length {
+        let marker = cli.continuation_marker.as_deref().unwrap_or(" ↵ ");
+        output_options = output_options.with_line_wrapping(max_chars, marker.to_string());
+    }
+
+    if !cli.text_replacements.is_empty() {
+        output_options = output_options.with_text_replacements(cli.text_replacements.clone());
+    }
+
+    if cli.no_csv_header {
+        output_options = output_options.without_csv_header();
+    }
+
+    output_options = output_options.with_csv_quote(cli.csv_quote_char);
+
+    if let Some(escape) = cli.csv_escape_char {
+        output_options = output_options.with_csv_escape(escape);
+    }
+
+    if let Some(ref text) = cli.null_replacement {
+        output_options = output_options.with_null_replacement(text.clone());
+    }
+
+    // --output-bom is the Excel shorthand for --output-encoding utf-8-bom
+    let encoding = if cli.output_bom {
+        OutputEncoding::Utf8Bom
     } else {
-        parse_streaming(&cli)?
+        cli.output_encoding
     };
+    output_options = output_options.with_encoding(encoding);
 
-    let total_parsed = messages.len();
+    if let Some(newline) = cli.output_newline {
+        output_options = output_options.with_newline(newline);
+    }
 
-    // Apply filters
-    let filtered = apply_filters(messages, &filter);
-    let filtered_count = filtered.len();
+    if cli.append {
+        output_options = output_options.with_append();
+    }
 
-    // Optionally merge consecutive messages
-    let processed = if cli.no_merge {
-        filtered
+    if cli.json_envelope {
+        output_options = output_options
+            .with_envelope(Envelope::new(cli.source.to_platform(), &cli.display_input));
+    }
+
+    // Checked last: fields are valid only once their flags are applied
+    if let Some(ref spec) = cli.field_order {
+        let columns = CsvColumn::parse_order(spec, &output_options)?;
+        output_options = output_options.with_field_order(columns);
+    }
+
+    Ok(output_options)
+}
+
+/// Parse with the library parser, streaming unless disabled.
+///
+/// Returns the messages and how many were parsed. A `prefilter` is applied
+/// while streaming, so messages it rejects are never collected.
+fn parse_messages(cli: &Cli, prefilter: Option<&FilterConfig>) -> Result<(Vec<Message>, usize)> {
+    if cli.no_streaming || !has_streaming_parser(cli) {
+        let messages = parse_full(cli)?;
+        let count = messages.len();
+        Ok((messages, count))
     } else {
-        merge_consecutive(filtered)
+        parse_streaming(cli, prefilter)
+    }
+}
+
+/// Refuse to load the export into memory if that could exceed --max-memory.
+///
+/// `streamable` tells whether dropping --no-streaming would avoid the load.
+fn check_memory_limit(cli: &Cli, streamable: bool) -> Result<()> {
+    let Some(limit) = cli.max_memory else {
+        return Ok(());
     };
 
-    let final_count = processed.len();
+    let size = benchmark::size_of(&cli.input);
+    let estimate = memory::estimate_memory_usage(size, cli.source.to_platform()) as u64;
+    if estimate <= limit {
+        return Ok(());
+    }
 
-    // Write output
-    write_output(&processed, &cli, &output_config)?;
+    let tip = if streamable {
+        "Drop --no-streaming to stream the export instead."
+    } else {
+        "This export is read into memory as a whole; raise --max-memory to allow it."
+    };
+    bail!(
+        "Loading {} would need about {} of memory, over the --max-memory limit of {}\n\nTip: {}",
+        cli.display_input.display(),
+        memory::format_bytes(estimate),
+        memory::format_bytes(limit),
+        tip
+    );
+}
 
-    // Print summary
-    if !cli.quiet {
-        print_summary(&cli, total_parsed, filtered_count, final_count);
+/// Whether the library can stream this export.
+///
+/// Discord's streaming parser only reads JSON; DiscordChatExporter's CSV and
+/// TXT exports go through the in-memory parser.
+fn has_streaming_parser(cli: &Cli) -> bool {
+    cli.source != Source::Discord
+        || cli
+            .input
+            .extension()
+            .is_none_or(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Whether the filter can run on the message stream before the rest of the
+/// pipeline: nothing before the filter step may need the rejected messages.
+fn filters_while_parsing(cli: &Cli, filter: &FilterConfig, options: &OutputOptions) -> bool {
+    filter.is_active()
+        && cli.include_context.is_none()
+        // The sender filter compares against normalized names
+        && cli.normalize_unicode.is_none()
+        && !cli.lowercase_senders
+        && cli.pretty_names.is_none()
+        && cli.sender_alias_file.is_none()
+        // Rejected messages still count as seen and as reply targets
+        && !cli.incremental
+        && !cli.exclude_auto_replies
+        // WhatsApp line numbers are matched to messages by position
+        && !(cli.line_numbers && cli.source == Source::Whatsapp)
+        && !matches!(options.reply_format, ReplyFormat::Quote | ReplyFormat::Inline)
+}
+
+/// Most encoding issues listed before the rest are only counted
+const MAX_ENCODING_ISSUES: usize = 20;
+
+/// Print the encoding issues of the raw input file to stderr.
+fn report_encoding_issues(cli: &Cli) -> Result<()> {
+    let bytes =
+        fs::read(&cli.input).with_context(|| format!("Failed to read {}", cli.input.display()))?;
+    let issues = encoding::detect_encoding_issues(&bytes);
+
+    if issues.is_empty() {
+        if !cli.quiet {
+            eprintln!("✅ No encoding issues found");
+        }
+        return Ok(());
+    }
+
+    for issue in issues.iter().take(MAX_ENCODING_ISSUES) {
+        eprintln!("⚠️  Encoding: {}", issue);
+    }
+    if issues.len() > MAX_ENCODING_ISSUES {
+        eprintln!(
+            "⚠️  Encoding: … and {} more",
+            issues.len() - MAX_ENCODING_ISSUES
+        );
     }
 
     Ok(())
 }
 
+/// Whether to print progress lines while parsing
+fn shows_progress(cli: &Cli) -> bool {
+    cli.progress && !cli.quiet && !cli.quiet_progress
+}
+
 /// Parse using full in-memory loading
 fn parse_full(cli: &Cli) -> Result<Vec<Message>> {
+    check_memory_limit(cli, has_streaming_parser(cli))?;
+
     let platform = cli.source.to_platform();
     let parser = create_parser(platform);
 
-    if cli.progress && !cli.quiet {
+    if shows_progress(cli) {
         eprintln!("⏳ Loading entire file into memory...");
     }
 
     let messages = parser
         .parse(&cli.input)
-        .with_context(|| format!("Failed to parse {} export", cli.source.name()))?;
+        .with_context(|| format!("Failed to parse {} export", cli.source.to_platform()))?;
 
-    if cli.progress && !cli.quiet {
+    if shows_progress(cli) {
         eprintln!("✓ Loaded {} messages", messages.len());
     }
 
@@ -272,69 +2141,534 @@ fn parse_full(cli: &Cli) -> Result<Vec<Message>> {
 }
 
 /// Parse using streaming (memory-efficient)
-fn parse_streaming(cli: &Cli) -> Result<Vec<Message>> {
+///
+/// With `--max-retries`, an I/O error reopens the export and resumes after
+/// the messages already read. Parse errors are never retried.
+fn parse_streaming(cli: &Cli, prefilter: Option<&FilterConfig>) -> Result<(Vec<Message>, usize)> {
     let platform = cli.source.to_platform();
     let parser = create_streaming_parser(platform);
 
     let mut messages = Vec::new();
     let mut count = 0;
+    let mut retries = 0;
 
-    if cli.progress && !cli.quiet {
+    if shows_progress(cli) {
         eprintln!("⏳ Streaming messages...");
     }
 
-    let stream = parser
-        .stream(&cli.input)
-        .with_context(|| format!("Failed to open {} export for streaming", cli.source.name()))?;
+    loop {
+        let stream = parser.stream(&cli.input).with_context(|| {
+            format!(
+                "Failed to open {} export for streaming",
+                cli.source.to_platform()
+            )
+        })?;
 
-    for result in stream {
-        let msg = result.with_context(|| format!("Error at message {}", count + 1))?;
-        messages.push(msg);
-        count += 1;
+        // Stops at the first error, which is handled once the stream is drained
+        let mut failure = None;
+        let parsed = stream
+            .map_while(|result| result.map_err(|e| failure = Some(e)).ok())
+            .skip(count)
+            .inspect(|_| {
+                count += 1;
+                if shows_progress(cli) && count % 10000 == 0 {
+                    eprint!("\r⏳ Processed {} messages...", count);
+                }
+            });
+
+        match prefilter {
+            Some(filter) => messages.extend(filter.filter_iter(parsed)),
+            None => messages.extend(parsed),
+        }
 
-        if cli.progress && !cli.quiet && count % 10000 == 0 {
-            eprint!("\r⏳ Processed {} messages...", count);
+        match failure {
+            None => break,
+            Some(e) if is_io_error(&e) && retries < cli.max_retries => {
+                retries += 1;
+                if !cli.quiet {
+                    eprintln!(
+                        "⚠️  {} after {} messages, retrying ({}/{})",
+                        e, count, retries, cli.max_retries
+                    );
+                }
+            }
+            Some(e) => {
+                return Err(e).with_context(|| format!("Error at message {}", count + 1));
+            }
         }
     }
 
-    if cli.progress && !cli.quiet && count >= 10000 {
+    if shows_progress(cli) && count >= 10000 {
         eprintln!("\r✓ Streamed {} messages    ", count);
-    } else if cli.progress && !cli.quiet {
+    } else if shows_progress(cli) {
         eprintln!("✓ Streamed {} messages", count);
     }
 
-    Ok(messages)
+    Ok((messages, count))
 }
 
-/// Write messages to the output file in the specified format
-fn write_output(messages: &[Message], cli: &Cli, config: &OutputConfig) -> Result<()> {
-    let output_path = cli
-        .output
-        .to_str()
-        .with_context(|| format!("Invalid output path: {}", cli.output.display()))?;
+/// Whether a parser error came from reading the file rather than its content
+fn is_io_error(error: &ChatpackError) -> bool {
+    matches!(
+        error,
+        ChatpackError::Io(_) | ChatpackError::Streaming(StreamingErrorKind::Io(_))
+    )
+}
 
-    match cli.format {
+/// Output format matching a file extension
+fn infer_format(path: &Path) -> Option<Format> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "csv" => Some(Format::Csv),
+        "json" => Some(Format::Json),
+        "jsonl" | "ndjson" => Some(Format::Jsonl),
+        _ => None,
+    }
+}
+
+/// Write messages to the output file in the specified format
+fn write_output(
+    messages: &[Message],
+    extras: &Extras,
+    output_path: &Path,
+    format: Format,
+    options: &OutputOptions,
+) -> Result<()> {
+    match format {
         Format::Csv => {
-            write_csv(messages, output_path, config)
-                .with_context(|| format!("Failed to write CSV to {}", cli.output.display()))?;
+            output::write_csv_to_file(messages, extras, output_path, options)
+                .with_context(|| format!("Failed to write CSV to {}", output_path.display()))?;
         }
         Format::Json => {
-            write_json(messages, output_path, config)
-                .with_context(|| format!("Failed to write JSON to {}", cli.output.display()))?;
+            output::write_json_to_file(messages, extras, output_path, options)
+                .with_context(|| format!("Failed to write JSON to {}", output_path.display()))?;
         }
         Format::Jsonl => {
-            write_jsonl(messages, output_path, config)
-                .with_context(|| format!("Failed to write JSONL to {}", cli.output.display()))?;
+            output::write_jsonl_to_file(messages, extras, output_path, options)
+                .with_context(|| format!("Failed to write JSONL to {}", output_path.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read a written output file back and check that it parses in `format`.
+fn verify_output(path: &Path, format: Format, options: &OutputOptions) -> Result<()> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let verified = options
+        .encoding
+        .decode(&bytes)
+        .and_then(|text| match format {
+            Format::Csv => output::verify_csv(&text, options),
+            Format::Json => output::verify_json(&text),
+            Format::Jsonl => output::verify_jsonl(&text),
+        });
+
+    verified.with_context(|| format!("{} is not valid {}", path.display(), format.name()))
+}
+
+/// Render the output in memory, as [`write_output`] would write it.
+fn render_output(
+    messages: &[Message],
+    extras: &Extras,
+    format: Format,
+    options: &OutputOptions,
+) -> Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let writer = EncodedWriter::new(&mut body, options.encoding)?;
+    match format {
+        Format::Csv => output::write_csv(messages, extras, writer, options)?,
+        Format::Json => output::write_json(messages, extras, writer, options)?,
+        Format::Jsonl => output::write_jsonl(messages, extras, writer, options)?,
+    }
+    Ok(body)
+}
+
+/// Write human/assistant turn pairs in the specified format
+fn write_turns(
+    turns: &[transform::Turn],
+    output_path: &Path,
+    format: Format,
+    options: &OutputOptions,
+) -> Result<()> {
+    let result = match format {
+        Format::Csv => output::write_turns_csv(turns, output_path, options),
+        Format::Json => output::write_turns_json(turns, output_path, options),
+        Format::Jsonl => output::write_turns_jsonl(turns, output_path, options),
+    };
+
+    result.with_context(|| {
+        format!(
+            "Failed to write {} to {}",
+            format.name(),
+            output_path.display()
+        )
+    })
+}
+
+/// `{prefix}_{suffix}.{ext}` next to the configured output file
+fn suffixed_output_path(cli: &Cli, suffix: &str) -> PathBuf {
+    let prefix = cli
+        .output
+        .file_stem()
+        .map_or_else(|| "optimized_chat".into(), |s| s.to_string_lossy());
+    let extension = cli
+        .output
+        .extension()
+        .map_or_else(|| cli.format.extension().into(), |s| s.to_string_lossy());
+
+    cli.output
+        .with_file_name(format!("{}_{}.{}", prefix, suffix, extension))
+}
+
+/// Write sliding windows: one JSONL line per window, or one file per window
+/// (`{prefix}_window_{index}.{ext}`) for CSV and JSON. Returns the paths written.
+fn write_windows(
+    windows: &[Vec<Message>],
+    extras: &Extras,
+    cli: &Cli,
+    options: &OutputOptions,
+) -> Result<Vec<PathBuf>> {
+    if cli.format == Format::Jsonl {
+        output::write_jsonl_windows(windows, extras, &cli.output, options)
+            .with_context(|| format!("Failed to write JSONL to {}", cli.output.display()))?;
+        return Ok(vec![cli.output.clone()]);
+    }
+
+    let mut outputs = Vec::with_capacity(windows.len());
+    for (index, window) in windows.iter().enumerate() {
+        let path = suffixed_output_path(cli, &format!("window_{}", index));
+        write_output(window, extras, &path, cli.format, options)?;
+        outputs.push(path);
+    }
+
+    Ok(outputs)
+}
+
+/// Write two-party dialogues: one JSONL line each, or one file each for
+/// CSV and JSON
+fn write_dialogues(
+    dialogues: &[transform::TwoPartyDialogue],
+    extras: &Extras,
+    cli: &Cli,
+    options: &OutputOptions,
+) -> Result<Vec<PathBuf>> {
+    if cli.format == Format::Jsonl {
+        output::write_jsonl_dialogues(dialogues, extras, &cli.output, options)
+            .with_context(|| format!("Failed to write JSONL to {}", cli.output.display()))?;
+        return Ok(vec![cli.output.clone()]);
+    }
+
+    let mut outputs = Vec::with_capacity(dialogues.len());
+    for (index, dialogue) in dialogues.iter().enumerate() {
+        let path = suffixed_output_path(cli, &format!("dialogue_{}", index));
+        write_output(&dialogue.messages, extras, &path, cli.format, options)?;
+        outputs.push(path);
+    }
+
+    Ok(outputs)
+}
+
+/// Slug for a name, numbered when another name already produced it
+fn unique_slug(used_slugs: &mut HashSet<String>, name: &str) -> String {
+    // Distinct names can share a slug ("Bob!" and "bob"); keep both files
+    let base_slug = transform::sender_slug(name);
+    let mut slug = base_slug.clone();
+    let mut n = 2;
+    while !used_slugs.insert(slug.clone()) {
+        slug = format!("{}_{}", base_slug, n);
+        n += 1;
+    }
+    slug
+}
+
+/// Write one `{prefix}_{conversation_slug}.{ext}` file per conversation,
+/// merging within each one. Returns the paths written and the entry count.
+fn write_split_by_conversation(
+    messages: Vec<Message>,
+    extras: &mut Extras,
+    cli: &Cli,
+    options: &OutputOptions,
+) -> Result<(Vec<PathBuf>, usize)> {
+    let mut groups: Vec<(String, Vec<Message>)> = Vec::new();
+    for msg in messages {
+        let conversation = extras
+            .get(&msg)
+            .conversation_id
+            .clone()
+            .unwrap_or_else(|| "unknown".to_string());
+        match groups.iter_mut().find(|(name, _)| *name == conversation) {
+            Some((_, group)) => group.push(msg),
+            None => groups.push((conversation, vec![msg])),
+        }
+    }
+
+    let mut used_slugs = HashSet::new();
+    let mut outputs = Vec::with_capacity(groups.len());
+    let mut total = 0;
+
+    for (conversation, group) in groups {
+        let processed = if cli.no_merge {
+            group
+        } else {
+            extras::fold_merged(&group, extras);
+            MergeIterator::new(group.into_iter()).collect()
+        };
+        total += processed.len();
+
+        let path = suffixed_output_path(cli, &unique_slug(&mut used_slugs, &conversation));
+        write_output(&processed, extras, &path, cli.format, options)?;
+        outputs.push(path);
+    }
+
+    Ok((outputs, total))
+}
+
+/// Write one `{prefix}_{sender_slug}.{ext}` file per sender, returning the paths written
+fn write_split_by_sender(
+    messages: Vec<Message>,
+    extras: &Extras,
+    cli: &Cli,
+    options: &OutputOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut groups: Vec<_> = transform::group_by_sender(messages).into_iter().collect();
+    groups.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut used_slugs = HashSet::new();
+    let mut outputs = Vec::with_capacity(groups.len());
+
+    for (sender, sender_messages) in groups {
+        let slug = unique_slug(&mut used_slugs, &sender);
+        let path = suffixed_output_path(cli, &slug);
+        write_output(&sender_messages, extras, &path, cli.format, options)?;
+        outputs.push(path);
+    }
+
+    Ok(outputs)
+}
+
+/// Write one `{prefix}_{topic_slug}.{ext}` file per topic, including the
+/// unclassified one, whether or not any message landed in it
+fn write_split_by_topic(
+    messages: Vec<Message>,
+    extras: &Extras,
+    classifier: &TopicClassifier,
+    cli: &Cli,
+    options: &OutputOptions,
+) -> Result<Vec<PathBuf>> {
+    let mut groups: Vec<(&str, Vec<Message>)> =
+        classifier.names().map(|name| (name, Vec::new())).collect();
+    for msg in messages {
+        let topic = classifier.classify(&msg.content);
+        if let Some((_, group)) = groups.iter_mut().find(|(name, _)| *name == topic) {
+            group.push(msg);
+        }
+    }
+
+    let mut used_slugs = HashSet::new();
+    let mut outputs = Vec::with_capacity(groups.len());
+
+    for (topic, topic_messages) in groups {
+        let path = suffixed_output_path(cli, &unique_slug(&mut used_slugs, topic));
+        write_output(&topic_messages, extras, &path, cli.format, options)?;
+        outputs.push(path);
+    }
+
+    Ok(outputs)
+}
+
+/// Print the `--summary-only` report to stdout
+fn print_summary_only(cli: &Cli, filtered: Vec<Message>, stats: &ConversationStats) -> Result<()> {
+    let total = stats.parsed;
+    let filtered_count = filtered.len();
+    let senders: HashSet<&str> = filtered.iter().map(|msg| msg.sender.as_str()).collect();
+    let senders = senders.len();
+    let first = filtered.iter().filter_map(|msg| msg.timestamp).min();
+    let last = filtered.iter().filter_map(|msg| msg.timestamp).max();
+
+    let final_count = if cli.no_merge {
+        filtered_count
+    } else {
+        MergeIterator::new(filtered.into_iter()).count()
+    };
+
+    if cli.json {
+        let summary = serde_json::json!({
+            "platform": cli.source.to_platform(),
+            "input": cli.display_input,
+            "parsed": total,
+            "filtered": filtered_count,
+            "entries": final_count,
+            "senders": senders,
+            "first_message": first,
+            "last_message": last,
+            // Plain arrays so they can go straight into a heatmap
+            "hourly_counts": stats.hourly_counts(),
+            "daily_counts": stats.daily_counts(),
+            "most_active_hour": stats.most_active_hour(),
+            "most_active_day": stats.most_active_day_of_week().map(report::day_name),
+            "response_times": stats.response_time_stats(),
+            "top_words": stats.top_n_words(report::TOP_WORDS),
+            "per_sender_top_words": stats.per_sender_top_words(report::TOP_WORDS),
+        });
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+        return Ok(());
+    }
+
+    println!("📥 Parsed:   {} messages", total);
+    println!("🔍 Filtered: {} messages", filtered_count);
+    if final_count != filtered_count {
+        println!("🔀 Merged:   {} → {} entries", filtered_count, final_count);
+    }
+    println!("👥 Senders:  {}", senders);
+    if let (Some(first), Some(last)) = (first, last) {
+        println!(
+            "📅 Period:   {} → {}",
+            first.format("%Y-%m-%d"),
+            last.format("%Y-%m-%d")
+        );
+    }
+    if let (Some(hour), Some(day)) = (stats.most_active_hour(), stats.most_active_day_of_week()) {
+        println!(
+            "⏰ Busiest:  {:02}:00 UTC, {}s",
+            hour,
+            report::day_name(day)
+        );
+    }
+    if let Some(response) = stats.response_time_stats() {
+        let median = TimeDelta::seconds(response.median_seconds.round() as i64);
+        println!(
+            "💬 Replies:  {} median response time",
+            transform::format_gap(median)
+        );
+    }
+    let top_words: Vec<String> = stats
+        .top_n_words(5)
+        .into_iter()
+        .map(|(word, count)| format!("{} ({})", word, count))
+        .collect();
+    if !top_words.is_empty() {
+        println!("🔤 Words:    {}", top_words.join(", "));
+    }
+
+    Ok(())
+}
+
+/// Print the unique senders in order of their first message.
+fn print_participants(cli: &Cli, messages: &[Message], options: &OutputOptions) -> Result<()> {
+    let mut senders: Vec<String> = Vec::new();
+    for msg in messages {
+        if !senders.contains(&msg.sender) {
+            senders.push(msg.sender.clone());
         }
     }
 
+    print_list(cli, "Sender", &senders, options)
+}
+
+/// Print the calendar days (UTC) with at least one message, oldest first.
+fn print_dates(cli: &Cli, messages: &[Message], options: &OutputOptions) -> Result<()> {
+    let days: BTreeSet<NaiveDate> = messages
+        .iter()
+        .filter_map(|msg| msg.timestamp)
+        .map(|ts| ts.date_naive())
+        .collect();
+    let days: Vec<String> = days
+        .iter()
+        .map(|day| day.format("%Y-%m-%d").to_string())
+        .collect();
+
+    print_list(cli, "Date", &days, options)
+}
+
+/// Print the `count` longest two-person exchanges as a JSON report.
+///
+/// Exchanges are ranked by message count; ties keep chronological order.
+fn print_longest_exchanges(messages: Vec<Message>, count: usize) -> Result<()> {
+    let mut dialogues = transform::extract_two_party_dialogues(messages);
+    dialogues.sort_by_key(|dialogue| std::cmp::Reverse(dialogue.messages.len()));
+
+    let report: Vec<_> = dialogues
+        .iter()
+        .take(count)
+        .map(|dialogue| {
+            let sample: Vec<_> = dialogue
+                .messages
+                .iter()
+                .take(3)
+                .map(|msg| {
+                    serde_json::json!({
+                        "sender": msg.sender,
+                        "content": msg.content,
+                        "timestamp": msg.timestamp,
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "speakers": dialogue.speakers,
+                "start_time": dialogue.messages.first().and_then(|msg| msg.timestamp),
+                "end_time": dialogue.messages.last().and_then(|msg| msg.timestamp),
+                "message_count": dialogue.messages.len(),
+                "sample": sample,
+            })
+        })
+        .collect();
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Print a listing to stdout.
+///
+/// One value per line, or a JSON array with `--json` (or `-f json`), or a
+/// single CSV column named `header` with `-f csv`.
+fn print_list(cli: &Cli, header: &str, values: &[String], options: &OutputOptions) -> Result<()> {
+    if cli.json || matches!(cli.format_arg, Some(Format::Json | Format::Jsonl)) {
+        println!("{}", serde_json::to_string_pretty(values)?);
+        return Ok(());
+    }
+
+    let mut stdout = io::stdout().lock();
+    if cli.format_arg == Some(Format::Csv) {
+        return output::write_csv_column(header, values, stdout, options);
+    }
+    for value in values {
+        writeln!(stdout, "{}", value)?;
+    }
     Ok(())
 }
 
 /// Print processing summary
-fn print_summary(cli: &Cli, total: usize, filtered: usize, final_count: usize) {
-    let has_filters = cli.after.is_some() || cli.before.is_some() || cli.from.is_some();
+fn print_summary(
+    cli: &Cli,
+    total: usize,
+    filtered: usize,
+    final_count: usize,
+    outputs: &[PathBuf],
+) {
+    let has_filters = !report::applied_filters(cli).is_empty();
     let merged = !cli.no_merge && filtered != final_count;
+    let kind = match cli.context_window_format {
+        Some(_) => "template",
+        None => cli.format.name(),
+    };
+    let output = match outputs {
+        [path] => format!("{} ({})", path.display(), kind),
+        _ => format!("{} files ({})", outputs.len(), kind),
+    };
+
+    if cli.quiet_progress {
+        let mut parts = vec![format!("Parsed: {} messages", total)];
+        if has_filters {
+            parts.push(format!("Filtered: {} messages", filtered));
+        }
+        if merged {
+            parts.push(format!("Merged: {} → {} entries", filtered, final_count));
+        }
+        parts.push(format!("Output: {}", output));
+        eprintln!("✅ Done! {}", parts.join(", "));
+        return;
+    }
 
     eprintln!();
     eprintln!("✅ \x1b[1mDone!\x1b[0m");
@@ -348,9 +2682,5 @@ fn print_summary(cli: &Cli, total: usize, filtered: usize, final_count: usize) {
         eprintln!("   🔀 Merged:   {} → {} entries", filtered, final_count);
     }
 
-    eprintln!(
-        "   📤 Output:   {} ({})",
-        cli.output.display(),
-        cli.format.name()
-    );
+    eprintln!("   📤 Output:   {}", output);
 }
@@ -0,0 +1,148 @@
+//! Descriptive placeholders for media messages.
+//!
+//! Exports represent attachments as file paths, `<Media omitted>`, or nothing
+//! at all. With `--include-media-description` these become readable markers
+//! such as `[Photo: beach.jpg]` or `[Audio: 12s]`.
+
+use std::path::Path;
+
+use serde_json::Value;
+
+/// Kind of attached media
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MediaKind {
+    Photo,
+    Video,
+    Audio,
+    Sticker,
+    Document,
+}
+
+impl MediaKind {
+    /// Guess the kind from a file name's extension.
+    pub fn from_file_name(name: &str) -> Self {
+        let extension = name
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "jpg" | "jpeg" | "png" | "gif" | "heic" | "bmp" => MediaKind::Photo,
+            "mp4" | "mov" | "webm" | "mkv" | "avi" | "3gp" => MediaKind::Video,
+            "mp3" | "ogg" | "opus" | "m4a" | "aac" | "wav" => MediaKind::Audio,
+            "webp" | "tgs" => MediaKind::Sticker,
+            _ => MediaKind::Document,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            MediaKind::Photo => "Photo",
+            MediaKind::Video => "Video",
+            MediaKind::Audio => "Audio",
+            MediaKind::Sticker => "Sticker",
+            MediaKind::Document => "Document",
+        }
+    }
+}
+
+/// Format a placeholder like `[Photo: beach.jpg]`, or `[Photo]` without detail.
+pub fn placeholder(kind: MediaKind, detail: Option<&str>) -> String {
+    match detail {
+        Some(detail) if !detail.is_empty() => format!("[{}: {}]", kind.label(), detail),
+        _ => format!("[{}]", kind.label()),
+    }
+}
+
+/// Last path component of an exported file path.
+fn base_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// Describe the media attached to a raw Telegram message, if any.
+pub fn telegram_description(raw: &Value) -> Option<String> {
+    let duration = raw["duration_seconds"]
+        .as_u64()
+        .map(|secs| format!("{}s", secs));
+
+    // Exports without media files put a "(File not included...)" note in `file`
+    let file_name = raw["file_name"]
+        .as_str()
+        .map(ToString::to_string)
+        .or_else(|| {
+            raw["file"]
+                .as_str()
+                .filter(|f| !f.starts_with('('))
+                .map(|f| base_name(f).to_string())
+        });
+
+    if let Some(photo) = raw["photo"].as_str() {
+        let name = (!photo.starts_with('(')).then(|| base_name(photo));
+        return Some(placeholder(MediaKind::Photo, name));
+    }
+
+    let description = match raw["media_type"].as_str() {
+        Some("sticker") => placeholder(MediaKind::Sticker, raw["sticker_emoji"].as_str()),
+        Some("video_file" | "video_message" | "animation") => {
+            placeholder(MediaKind::Video, duration.as_deref())
+        }
+        Some("voice_message" | "audio_file") => placeholder(MediaKind::Audio, duration.as_deref()),
+        _ if raw.get("file").is_some() => placeholder(MediaKind::Document, file_name.as_deref()),
+        _ => return None,
+    };
+
+    Some(description)
+}
+
+/// Replace attachment markers in message text with media placeholders.
+///
+/// Handles Discord's `[Attachment: name]` lines and WhatsApp's
+/// `name (file attached)`, `<attached: name>`, `<Media omitted>`, and
+/// `image omitted`-style lines. Other lines are kept as they are.
+///
+/// With a `media_dir`, file names are marked `(exists)` or `(missing)`
+/// depending on whether the file is in that directory.
+pub fn describe_attachments(content: &str, media_dir: Option<&Path>) -> String {
+    content
+        .lines()
+        .map(|line| describe_line(line, media_dir).unwrap_or_else(|| line.to_string()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn describe_line(line: &str, media_dir: Option<&Path>) -> Option<String> {
+    // iOS exports prefix attachment lines with a left-to-right mark
+    let trimmed = line.trim().trim_start_matches('\u{200e}');
+
+    let attached = trimmed
+        .strip_prefix("[Attachment: ")
+        .and_then(|rest| rest.strip_suffix(']'))
+        .or_else(|| trimmed.strip_suffix(" (file attached)"))
+        .or_else(|| {
+            trimmed
+                .strip_prefix("<attached: ")
+                .and_then(|rest| rest.strip_suffix('>'))
+        });
+
+    if let Some(name) = attached {
+        let kind = MediaKind::from_file_name(name);
+        let detail = match media_dir {
+            Some(dir) if dir.join(name).is_file() => format!("{} (exists)", name),
+            Some(_) => format!("{} (missing)", name),
+            None => name.to_string(),
+        };
+        return Some(placeholder(kind, Some(&detail)));
+    }
+
+    let kind = match trimmed.to_lowercase().as_str() {
+        "<media omitted>" => return Some("[Media]".to_string()),
+        "image omitted" => MediaKind::Photo,
+        "video omitted" | "gif omitted" => MediaKind::Video,
+        "audio omitted" => MediaKind::Audio,
+        "sticker omitted" => MediaKind::Sticker,
+        "document omitted" => MediaKind::Document,
+        _ => return None,
+    };
+
+    Some(placeholder(kind, None))
+}
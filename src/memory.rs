@@ -0,0 +1,66 @@
+//! Rough memory estimates for the `--max-memory` guard.
+
+use chatpack::prelude::Platform;
+
+/// Estimated peak memory of loading an export into memory, in bytes.
+///
+/// The file is held as text while it is parsed, then as messages, and the
+/// filtered and merged copies add to that. How much the messages weigh
+/// relative to the file depends on how verbose the export format is: JSON
+/// exports shrink when parsed, while WhatsApp's terse text lines gain
+/// per-message overhead.
+pub fn estimate_memory_usage(file_size_bytes: u64, platform: Platform) -> usize {
+    let multiplier = match platform {
+        Platform::WhatsApp => 4.0,
+        Platform::Telegram | Platform::Discord => 3.0,
+        Platform::Instagram => 2.5,
+        _ => 3.0,
+    };
+    (file_size_bytes as f64 * multiplier) as usize
+}
+
+/// Parse a size such as `2GB`, `512M` or `1048576` (units are powers of 1024).
+pub fn parse_byte_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("expected a size like 2GB or 512MB, got '{}'", s))?;
+    let scale: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => {
+            return Err(format!(
+                "unknown size unit '{}' (use KB, MB, GB or TB)",
+                unit
+            ));
+        }
+    };
+
+    Ok((number * scale as f64) as u64)
+}
+
+/// Human-readable size, e.g. `1.5 GB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
@@ -0,0 +1,1477 @@
+//! Output writers for CSV, JSON, and JSONL.
+//!
+//! The base columns match the `chatpack` library writers byte-for-byte; on top
+//! of that these writers render the CLI-only fields enabled in [`OutputOptions`].
+
+use std::borrow::{Borrow, Cow};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use chatpack::prelude::*;
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::encoding::{EncodedWriter, Newline, OutputEncoding};
+use crate::extras::{Extras, MessageExtras};
+use crate::transform::{self, MessageOrSeparator, PivotTable, Turn, TwoPartyDialogue};
+
+/// Output configuration: the library [`OutputConfig`] plus CLI-only fields.
+#[derive(Debug, Clone)]
+pub struct OutputOptions {
+    /// Base metadata columns (timestamps, IDs, replies, edits)
+    pub config: OutputConfig,
+    /// Include the list of @mentioned users
+    pub include_mentions: bool,
+    /// Include the conversation (chat, channel, thread) name
+    pub include_conversation_id: bool,
+    /// Include the revisions of edited messages
+    /// Include the channel name (Discord server exports)
+    pub include_channel_name: bool,
+    /// Include channel view/forward counters in JSON output
+    pub include_channel_meta: bool,
+    /// Include the line of the export each message starts on
+    pub include_source_lines: bool,
+    /// Leave out the sender column/field
+    pub omit_sender: bool,
+    /// Start each message text with `Sender: `
+    pub prepend_sender: bool,
+    /// How reply references are rendered
+    pub reply_format: ReplyFormat,
+    /// Render timestamps relative to this point in time
+    pub relative_to: Option<DateTime<Utc>>,
+    /// Insert a separator between messages from different days
+    pub group_by_day: bool,
+    /// Insert a boundary after silences longer than this
+    pub conversation_boundary: Option<TimeDelta>,
+    /// Maximum message text length in characters
+    pub truncate_text: Option<usize>,
+    /// Drop the leading `> ` quote lines of replies from message text
+    pub strip_quotes: bool,
+    /// Regex replacements applied to the final message text, in order
+    pub text_replacements: Vec<(Regex, String)>,
+    /// Hard-wrap CSV and template message text at this many characters
+    pub max_line_length: Option<usize>,
+    /// Written before each break made by `max_line_length`
+    pub continuation_marker: String,
+    /// Write the CSV header row
+    pub csv_header: bool,
+    /// CSV quote character
+    pub csv_quote: u8,
+    /// CSV escape character; `None` doubles quotes instead (RFC 4180)
+    pub csv_escape: Option<u8>,
+    /// CSV field text for missing values (no timestamp, ID, reply, ...)
+    pub null_replacement: String,
+    /// CSV columns in the order given by `--field-order`
+    pub field_order: Option<Vec<CsvColumn>>,
+    /// Metadata envelope for JSON/JSONL output
+    pub envelope: Option<Envelope>,
+    /// Character encoding of the output file
+    pub encoding: OutputEncoding,
+    /// Line ending of CSV, JSONL and template output
+    pub newline: Newline,
+    /// Append to existing output files instead of replacing them
+    pub append: bool,
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        Self {
+            config: OutputConfig::default(),
+            include_mentions: false,
+            include_conversation_id: false,
+            include_channel_name: false,
+            include_channel_meta: false,
+            include_source_lines: false,
+            omit_sender: false,
+            prepend_sender: false,
+            reply_format: ReplyFormat::default(),
+            relative_to: None,
+            group_by_day: false,
+            conversation_boundary: None,
+            truncate_text: None,
+            strip_quotes: false,
+            text_replacements: Vec::new(),
+            max_line_length: None,
+            continuation_marker: " ↵ ".to_string(),
+            csv_header: true,
+            csv_quote: b'"',
+            csv_escape: None,
+            null_replacement: String::new(),
+            field_order: None,
+            envelope: None,
+            encoding: OutputEncoding::default(),
+            newline: Newline::default(),
+            append: false,
+        }
+    }
+}
+
+impl OutputOptions {
+    pub fn new(config: OutputConfig) -> Self {
+        let reply_format = if config.include_replies {
+            ReplyFormat::Id
+        } else {
+            ReplyFormat::Omit
+        };
+
+        Self {
+            config,
+            reply_format,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_mentions(mut self) -> Self {
+        self.include_mentions = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_conversation_id(mut self) -> Self {
+        self.include_conversation_id = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_channel_name(mut self) -> Self {
+        self.include_channel_name = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_channel_meta(mut self) -> Self {
+        self.include_channel_meta = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_source_lines(mut self) -> Self {
+        self.include_source_lines = true;
+        self
+    }
+
+    #[must_use]
+    pub fn without_sender(mut self) -> Self {
+        self.omit_sender = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_prepended_sender(mut self) -> Self {
+        self.prepend_sender = true;
+        self
+    }
+
+    /// Set the reply rendering; `id` and `quote` use the reply column/field.
+    #[must_use]
+    pub fn with_reply_format(mut self, format: ReplyFormat) -> Self {
+        self.reply_format = format;
+        self.config.include_replies = matches!(format, ReplyFormat::Id | ReplyFormat::Quote);
+        self
+    }
+
+    /// Render timestamps like `2 hours ago` relative to `reference`.
+    #[must_use]
+    pub fn with_relative_timestamps(mut self, reference: DateTime<Utc>) -> Self {
+        self.config.include_timestamps = true;
+        self.relative_to = Some(reference);
+        self
+    }
+
+    #[must_use]
+    pub fn with_day_separators(mut self) -> Self {
+        self.group_by_day = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_conversation_boundary(mut self, gap: TimeDelta) -> Self {
+        self.conversation_boundary = Some(gap);
+        self
+    }
+
+    #[must_use]
+    pub fn with_truncate_text(mut self, max_chars: usize) -> Self {
+        self.truncate_text = Some(max_chars);
+        self
+    }
+
+    #[must_use]
+    pub fn with_stripped_quotes(mut self) -> Self {
+        self.strip_quotes = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_text_replacements(mut self, replacements: Vec<(Regex, String)>) -> Self {
+        self.text_replacements = replacements;
+        self
+    }
+
+    #[must_use]
+    pub fn with_line_wrapping(mut self, max_chars: usize, marker: String) -> Self {
+        self.max_line_length = Some(max_chars);
+        self.continuation_marker = marker;
+        self
+    }
+
+    #[must_use]
+    pub fn without_csv_header(mut self) -> Self {
+        self.csv_header = false;
+        self
+    }
+
+    #[must_use]
+    pub fn with_csv_quote(mut self, quote: u8) -> Self {
+        self.csv_quote = quote;
+        self
+    }
+
+    #[must_use]
+    pub fn with_csv_escape(mut self, escape: u8) -> Self {
+        self.csv_escape = Some(escape);
+        self
+    }
+
+    #[must_use]
+    pub fn with_null_replacement(mut self, text: String) -> Self {
+        self.null_replacement = text;
+        self
+    }
+
+    #[must_use]
+    pub fn with_field_order(mut self, columns: Vec<CsvColumn>) -> Self {
+        self.field_order = Some(columns);
+        self
+    }
+
+    #[must_use]
+    pub fn with_envelope(mut self, envelope: Envelope) -> Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    #[must_use]
+    pub fn with_newline(mut self, newline: Newline) -> Self {
+        self.newline = newline;
+        self
+    }
+
+    #[must_use]
+    pub fn with_append(mut self) -> Self {
+        self.append = true;
+        self
+    }
+
+    #[must_use]
+    pub fn with_encoding(mut self, encoding: OutputEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Message text as written to the output.
+    fn content<'a>(&self, msg: &'a Message, extra: &MessageExtras) -> Cow<'a, str> {
+        let text = if self.strip_quotes {
+            transform::text_without_quotes(&msg.content)
+        } else {
+            msg.content.as_str()
+        };
+        let mut content = match self.truncate_text {
+            Some(max_chars) => Cow::Owned(transform::truncate_text(text, max_chars)),
+            None => Cow::Borrowed(text),
+        };
+
+        if self.reply_format == ReplyFormat::Inline {
+            if let Some(target) = &extra.reply_target {
+                content = Cow::Owned(format!("@{} {}", target.sender, content));
+            }
+        }
+
+        // Merged messages are one entry, so the name appears once per group
+        if self.prepend_sender {
+            content = Cow::Owned(format!("{}: {}", msg.sender, content));
+        }
+
+        // Last, so the patterns see the text exactly as it would be written
+        for (pattern, replacement) in &self.text_replacements {
+            let replaced = match pattern.replace_all(&content, replacement.as_str()) {
+                Cow::Owned(replaced) => Some(replaced),
+                Cow::Borrowed(_) => None,
+            };
+            if let Some(replaced) = replaced {
+                content = Cow::Owned(replaced);
+            }
+        }
+
+        content
+    }
+
+    /// Message text for line-based output, hard-wrapped at `max_line_length`
+    /// with continuations starting with `indent`.
+    fn wrapped_content<'a>(
+        &self,
+        msg: &'a Message,
+        extra: &MessageExtras,
+        indent: &str,
+    ) -> Cow<'a, str> {
+        let content = self.content(msg, extra);
+        match self.max_line_length {
+            Some(max_chars) => Cow::Owned(transform::wrap_text(
+                &content,
+                max_chars,
+                &self.continuation_marker,
+                indent,
+            )),
+            None => content,
+        }
+    }
+
+    /// Timestamp as written to the output, in the given absolute format.
+    fn timestamp(&self, msg: &Message, absolute_format: &str) -> Option<String> {
+        let ts = msg.timestamp?;
+        Some(match self.relative_to {
+            Some(reference) => format_relative_to(ts, reference),
+            None => ts.format(absolute_format).to_string(),
+        })
+    }
+
+    /// Reply reference for the reply column/field.
+    fn reply_to(&self, msg: &Message, extra: &MessageExtras) -> Option<ReplyRef> {
+        match self.reply_format {
+            ReplyFormat::Id => msg.reply_to.map(ReplyRef::Id),
+            // Fall back to the ID when the original message isn't in the export
+            ReplyFormat::Quote => match &extra.reply_target {
+                Some(target) => Some(ReplyRef::Quote(quote(&target.content))),
+                None => msg.reply_to.map(ReplyRef::Id),
+            },
+            ReplyFormat::Inline | ReplyFormat::Omit => None,
+        }
+    }
+
+    /// Messages to write, with day separators and boundaries if enabled.
+    fn entries<'a>(&self, messages: &'a [Message]) -> Vec<MessageOrSeparator<'a>> {
+        let mut separators = self.separators();
+        let mut entries = Vec::with_capacity(messages.len());
+        for msg in messages {
+            entries.extend(separators.before(msg));
+            entries.push(MessageOrSeparator::Message(msg));
+        }
+        entries
+    }
+
+    fn separators(&self) -> transform::Separators {
+        transform::Separators::new(self.group_by_day, self.conversation_boundary)
+    }
+
+    /// Whether any enabled field needs data from [`Extras`].
+    pub fn needs_extras(&self) -> bool {
+        self.include_mentions
+            || self.include_conversation_id
+            || self.include_channel_name
+            || self.include_channel_meta
+            || self.include_source_lines
+    }
+}
+
+/// Describe a time difference like `5 minutes ago` or `3 days ago`.
+///
+/// Negative durations (times after the reference) read as `in 5 minutes`.
+pub fn format_relative(duration: TimeDelta) -> String {
+    let seconds = duration.num_seconds();
+    let magnitude = seconds.unsigned_abs();
+
+    let (count, unit) = match magnitude {
+        0..60 => return "just now".to_string(),
+        60..3_600 => (magnitude / 60, "minute"),
+        3_600..86_400 => (magnitude / 3_600, "hour"),
+        86_400..2_592_000 => (magnitude / 86_400, "day"),
+        2_592_000..31_536_000 => (magnitude / 2_592_000, "month"),
+        _ => (magnitude / 31_536_000, "year"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+
+    if seconds < 0 {
+        format!("in {} {}{}", count, unit, plural)
+    } else {
+        format!("{} {}{} ago", count, unit, plural)
+    }
+}
+
+/// Relative description of `ts`, using `yesterday at HH:MM` for the previous
+/// calendar day when it is more than a few hours back.
+fn format_relative_to(ts: DateTime<Utc>, reference: DateTime<Utc>) -> String {
+    let duration = reference - ts;
+    let is_yesterday = reference.date_naive().pred_opt() == Some(ts.date_naive());
+
+    if is_yesterday && duration >= TimeDelta::hours(6) {
+        format!("yesterday at {}", ts.format("%H:%M"))
+    } else {
+        format_relative(duration)
+    }
+}
+
+/// How reply references are rendered with `--reply-format`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReplyFormat {
+    /// ID of the replied-to message
+    Id,
+    /// Short quote of the replied-to message (`> Original message…`)
+    Quote,
+    /// Prefix the message text with `@Sender `
+    Inline,
+    /// No reply information
+    #[default]
+    Omit,
+}
+
+/// Maximum length of a `--reply-format quote` excerpt, in characters
+const QUOTE_LENGTH: usize = 50;
+
+/// Render a single-line excerpt of a replied-to message.
+fn quote(content: &str) -> String {
+    let line = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!("> {}", transform::truncate_text(&line, QUOTE_LENGTH))
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ReplyRef {
+    Id(u64),
+    Quote(String),
+}
+
+impl ReplyRef {
+    fn to_csv(&self) -> String {
+        match self {
+            ReplyRef::Id(id) => id.to_string(),
+            ReplyRef::Quote(quote) => quote.clone(),
+        }
+    }
+}
+
+/// Export metadata written alongside the messages with `--json-envelope`.
+///
+/// JSON output wraps the message array in an object carrying these fields;
+/// JSONL output writes them as a leading `"type": "metadata"` line.
+#[derive(Debug, Clone, Serialize)]
+pub struct Envelope {
+    pub chatpack_version: &'static str,
+    pub platform: Platform,
+    pub source_file: String,
+    pub exported_at: String,
+}
+
+impl Envelope {
+    pub fn new(platform: Platform, source: &Path) -> Self {
+        Self {
+            chatpack_version: env!("CARGO_PKG_VERSION"),
+            platform,
+            source_file: source
+                .file_name()
+                .unwrap_or(source.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+            exported_at: Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonEnvelope<'a> {
+    #[serde(flatten)]
+    meta: &'a Envelope,
+    message_count: usize,
+    messages: Vec<JsonEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonlMetadata<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(flatten)]
+    meta: &'a Envelope,
+    message_count: usize,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum JsonEntry<'a> {
+    Message(JsonRecord<'a>),
+    Separator(DaySeparator),
+    Boundary(Boundary),
+}
+
+impl<'a> JsonEntry<'a> {
+    fn new(entry: MessageOrSeparator<'a>, extras: &'a Extras, options: &OutputOptions) -> Self {
+        match entry {
+            MessageOrSeparator::Message(msg) => {
+                JsonEntry::Message(JsonRecord::new(msg, extras.get(msg), options))
+            }
+            MessageOrSeparator::DaySeparator(day) => JsonEntry::Separator(DaySeparator {
+                kind: "day_separator",
+                date: day.format("%Y-%m-%d").to_string(),
+            }),
+            MessageOrSeparator::Boundary(gap) => JsonEntry::Boundary(Boundary {
+                kind: "boundary",
+                gap_minutes: gap.num_minutes(),
+            }),
+        }
+    }
+}
+
+/// `{"type": "day_separator", "date": "2024-01-15"}` written with `--group-by-day`.
+#[derive(Serialize)]
+struct DaySeparator {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    date: String,
+}
+
+/// `{"type": "boundary", "gap_minutes": 120}` written with `--conversation-boundary`.
+#[derive(Serialize)]
+struct Boundary {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    gap_minutes: i64,
+}
+
+/// JSON/JSONL representation of a single message.
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sender: Option<&'a str>,
+    content: Cow<'a, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<ReplyRef>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    edited: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mentions: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    conversation_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel_name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    views: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    forwards: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source_line: Option<usize>,
+}
+
+impl<'a> JsonRecord<'a> {
+    fn new(msg: &'a Message, extra: &'a MessageExtras, options: &OutputOptions) -> Self {
+        let config = &options.config;
+        Self {
+            sender: (!options.omit_sender).then_some(msg.sender.as_str()),
+            content: options.content(msg, extra),
+            timestamp: config
+                .include_timestamps
+                .then(|| options.timestamp(msg, "%Y-%m-%dT%H:%M:%SZ"))
+                .flatten(),
+            id: config.include_ids.then_some(msg.id).flatten(),
+            reply_to: options.reply_to(msg, extra),
+            edited: config
+                .include_edited
+                .then(|| {
+                    msg.edited
+                        .map(|ts| ts.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+                })
+                .flatten(),
+            mentions: options
+                .include_mentions
+                .then_some(extra.mentions.as_slice()),
+            conversation_id: options
+                .include_conversation_id
+                .then_some(extra.conversation_id.as_deref())
+                .flatten(),
+            channel_name: options
+                .include_channel_name
+                .then_some(extra.conversation_id.as_deref())
+                .flatten(),
+            views: options
+                .include_channel_meta
+                .then_some(extra.views)
+                .flatten(),
+            forwards: options
+                .include_channel_meta
+                .then_some(extra.forwards)
+                .flatten(),
+            source_line: options
+                .include_source_lines
+                .then_some(extra.source_line)
+                .flatten(),
+        }
+    }
+}
+
+/// Whether output goes after existing content, so headers are skipped.
+fn appends_to_existing(path: &Path, options: &OutputOptions) -> bool {
+    options.append && fs::metadata(path).is_ok_and(|meta| meta.len() > 0)
+}
+
+/// Create (or open for appending) the output file, transcoding to the
+/// configured encoding.
+fn create_output(path: &Path, options: &OutputOptions) -> Result<EncodedWriter<BufWriter<File>>> {
+    if appends_to_existing(path, options) {
+        let file = OpenOptions::new().append(true).open(path)?;
+        return Ok(EncodedWriter::without_bom(
+            BufWriter::new(file),
+            options.encoding,
+        ));
+    }
+
+    let file = BufWriter::new(File::create(path)?);
+    Ok(EncodedWriter::new(file, options.encoding)?)
+}
+
+/// Options for writing to `path`: when appending after existing content,
+/// without the CSV header and the JSONL metadata line.
+fn continuing_if_appending<'a>(path: &Path, options: &'a OutputOptions) -> Cow<'a, OutputOptions> {
+    if !appends_to_existing(path, options) {
+        return Cow::Borrowed(options);
+    }
+
+    Cow::Owned(OutputOptions {
+        csv_header: false,
+        envelope: None,
+        ..options.clone()
+    })
+}
+
+/// Create a CSV writer using the configured dialect and line ending.
+///
+/// The line ending only ends records; line breaks inside quoted fields are
+/// written as they are in the message.
+fn csv_writer<W: Write>(writer: W, options: &OutputOptions) -> csv::Writer<W> {
+    let mut builder = csv::WriterBuilder::new();
+    builder
+        .delimiter(b';')
+        .quote(options.csv_quote)
+        .terminator(match options.newline {
+            Newline::Lf => csv::Terminator::Any(b'\n'),
+            Newline::Crlf => csv::Terminator::CRLF,
+            Newline::Cr => csv::Terminator::Any(b'\r'),
+        });
+
+    if let Some(escape) = options.csv_escape {
+        builder.double_quote(false).escape(escape);
+    }
+
+    builder.from_writer(writer)
+}
+
+/// Check that `text` parses as CSV in the configured dialect, with the same
+/// number of fields on every row.
+pub fn verify_csv(text: &str, options: &OutputOptions) -> Result<()> {
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .delimiter(b';')
+        .quote(options.csv_quote)
+        .has_headers(false);
+
+    if let Some(escape) = options.csv_escape {
+        builder.double_quote(false).escape(Some(escape));
+    }
+
+    for record in builder.from_reader(text.as_bytes()).records() {
+        record?;
+    }
+    Ok(())
+}
+
+/// Check that `text` is a single JSON document.
+pub fn verify_json(text: &str) -> Result<()> {
+    serde_json::from_str::<serde_json::Value>(text)?;
+    Ok(())
+}
+
+/// Check that every line of `text` is a JSON document, whatever the line
+/// endings.
+pub fn verify_jsonl(text: &str) -> Result<()> {
+    let text = text.replace("\r\n", "\n").replace('\r', "\n");
+    for (index, line) in text.lines().enumerate() {
+        serde_json::from_str::<serde_json::Value>(line)
+            .with_context(|| format!("line {}", index + 1))?;
+    }
+    Ok(())
+}
+
+/// Write `values` as a one-column CSV, as used by listings.
+pub fn write_csv_column<W: Write>(
+    header: &str,
+    values: &[String],
+    writer: W,
+    options: &OutputOptions,
+) -> Result<()> {
+    let mut writer = csv_writer(writer, options);
+    if options.csv_header {
+        writer.write_record([header])?;
+    }
+    for value in values {
+        writer.write_record([value])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write messages as semicolon-delimited UTF-8 CSV to `writer`.
+pub fn write_csv<W: Write>(
+    messages: &[Message],
+    extras: &Extras,
+    writer: W,
+    options: &OutputOptions,
+) -> Result<()> {
+    let mut writer = csv_writer(writer, options);
+
+    let header = csv_header(options);
+    if options.csv_header {
+        writer.write_record(&header)?;
+    }
+
+    for entry in options.entries(messages) {
+        match entry {
+            MessageOrSeparator::Message(msg) => {
+                writer.write_record(csv_record(msg, extras.get(msg), options))?;
+            }
+            MessageOrSeparator::DaySeparator(_) | MessageOrSeparator::Boundary(_) => {
+                // `--- 2024-01-15 ---` in the Content column, other columns null
+                let separator = separator_line(&entry).unwrap_or_default();
+                let record = header.iter().map(|&column| {
+                    if column == "Content" {
+                        separator.as_str()
+                    } else {
+                        options.null_replacement.as_str()
+                    }
+                });
+                writer.write_record(record)?;
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Text of a separator entry: `--- 2024-01-15 ---` or `--- [2h gap] ---`.
+fn separator_line(entry: &MessageOrSeparator<'_>) -> Option<String> {
+    match entry {
+        MessageOrSeparator::Message(_) => None,
+        MessageOrSeparator::DaySeparator(day) => {
+            Some(format!("--- {} ---", day.format("%Y-%m-%d")))
+        }
+        MessageOrSeparator::Boundary(gap) => {
+            Some(format!("--- [{} gap] ---", transform::format_gap(*gap)))
+        }
+    }
+}
+
+/// Write messages as CSV to a file in the configured encoding.
+///
+/// With `--append`, rows go after the existing content without a second header.
+pub fn write_csv_to_file(
+    messages: &[Message],
+    extras: &Extras,
+    path: &Path,
+    options: &OutputOptions,
+) -> Result<()> {
+    let options = continuing_if_appending(path, options);
+    write_csv(messages, extras, create_output(path, &options)?, &options)
+}
+
+/// A CSV output column
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CsvColumn {
+    Id,
+    Timestamp,
+    Sender,
+    Content,
+    ReplyTo,
+    Edited,
+    Mentions,
+    ConversationId,
+    Channel,
+    SourceLine,
+}
+
+impl CsvColumn {
+    const ALL: [CsvColumn; 10] = [
+        CsvColumn::Id,
+        CsvColumn::Timestamp,
+        CsvColumn::Sender,
+        CsvColumn::Content,
+        CsvColumn::ReplyTo,
+        CsvColumn::Edited,
+        CsvColumn::Mentions,
+        CsvColumn::ConversationId,
+        CsvColumn::Channel,
+        CsvColumn::SourceLine,
+    ];
+
+    /// Name of the column in a `--field-order` list
+    fn name(self) -> &'static str {
+        match self {
+            CsvColumn::Id => "id",
+            CsvColumn::Timestamp => "timestamp",
+            CsvColumn::Sender => "sender",
+            CsvColumn::Content => "text",
+            CsvColumn::ReplyTo => "reply_to",
+            CsvColumn::Edited => "edited_at",
+            CsvColumn::Mentions => "mentions",
+            CsvColumn::ConversationId => "conversation_id",
+            CsvColumn::Channel => "channel",
+            CsvColumn::SourceLine => "source_line",
+        }
+    }
+
+    fn header(self) -> &'static str {
+        match self {
+            CsvColumn::Id => "ID",
+            CsvColumn::Timestamp => "Timestamp",
+            CsvColumn::Sender => "Sender",
+            CsvColumn::Content => "Content",
+            CsvColumn::ReplyTo => "ReplyTo",
+            CsvColumn::Edited => "Edited",
+            CsvColumn::Mentions => "Mentions",
+            CsvColumn::ConversationId => "ConversationID",
+            CsvColumn::Channel => "Channel",
+            CsvColumn::SourceLine => "SourceLine",
+        }
+    }
+
+    /// Flag that adds the column; `None` for columns written by default
+    fn flag(self) -> Option<&'static str> {
+        match self {
+            CsvColumn::Id => Some("--ids"),
+            CsvColumn::Timestamp => Some("--timestamps"),
+            CsvColumn::Sender | CsvColumn::Content => None,
+            CsvColumn::ReplyTo => Some("--replies"),
+            CsvColumn::Edited => Some("--edited"),
+            CsvColumn::Mentions => Some("--include-mentions"),
+            CsvColumn::ConversationId => Some("--include-conversation-id"),
+            CsvColumn::Channel => Some("--discord-server"),
+            CsvColumn::SourceLine => Some("--line-numbers"),
+        }
+    }
+
+    /// Whether `options` write this column
+    fn enabled(self, options: &OutputOptions) -> bool {
+        let config = &options.config;
+        match self {
+            CsvColumn::Id => config.include_ids,
+            CsvColumn::Timestamp => config.include_timestamps,
+            CsvColumn::Sender => !options.omit_sender,
+            CsvColumn::Content => true,
+            CsvColumn::ReplyTo => config.include_replies,
+            CsvColumn::Edited => config.include_edited,
+            CsvColumn::Mentions => options.include_mentions,
+            CsvColumn::ConversationId => options.include_conversation_id,
+            CsvColumn::Channel => options.include_channel_name,
+            CsvColumn::SourceLine => options.include_source_lines,
+        }
+    }
+
+    /// Parse a comma-separated `--field-order` list such as
+    /// `sender,text,timestamp`.
+    ///
+    /// Every field must be enabled in `options` and listed once.
+    pub fn parse_order(spec: &str, options: &OutputOptions) -> Result<Vec<CsvColumn>> {
+        let mut columns = Vec::new();
+        for name in spec.split(',').map(str::trim) {
+            let Some(column) = Self::ALL.into_iter().find(|column| column.name() == name) else {
+                let names: Vec<_> = Self::ALL.iter().map(|column| column.name()).collect();
+                bail!(
+                    "Unknown field '{}' in --field-order; expected one of {}",
+                    name,
+                    names.join(", ")
+                );
+            };
+            if !column.enabled(options) {
+                match column.flag() {
+                    Some(flag) => bail!("Field '{}' in --field-order needs {}", name, flag),
+                    None => bail!(
+                        "Field '{}' in --field-order is left out by --omit-sender",
+                        name
+                    ),
+                }
+            }
+            if columns.contains(&column) {
+                bail!("Field '{}' is listed twice in --field-order", name);
+            }
+            columns.push(column);
+        }
+        Ok(columns)
+    }
+}
+
+/// Columns written to CSV: the `--field-order` list, or every enabled
+/// column in the default order.
+fn csv_columns(options: &OutputOptions) -> Vec<CsvColumn> {
+    match &options.field_order {
+        Some(columns) => columns.clone(),
+        None => CsvColumn::ALL
+            .into_iter()
+            .filter(|column| column.enabled(options))
+            .collect(),
+    }
+}
+
+/// Column names of the CSV header for the enabled options.
+pub fn csv_header(options: &OutputOptions) -> Vec<&'static str> {
+    csv_columns(options)
+        .into_iter()
+        .map(CsvColumn::header)
+        .collect()
+}
+
+fn csv_record(msg: &Message, extra: &MessageExtras, options: &OutputOptions) -> Vec<String> {
+    let null = |value: Option<String>| value.unwrap_or_else(|| options.null_replacement.clone());
+
+    csv_columns(options)
+        .into_iter()
+        .map(|column| match column {
+            CsvColumn::Id => null(msg.id.map(|id| id.to_string())),
+            CsvColumn::Timestamp => null(options.timestamp(msg, "%Y-%m-%d %H:%M:%S")),
+            CsvColumn::Sender => msg.sender.clone(),
+            // The quoted cell keeps the wrapped lines together
+            CsvColumn::Content => options.wrapped_content(msg, extra, "").into_owned(),
+            CsvColumn::ReplyTo => null(options.reply_to(msg, extra).map(|reply| reply.to_csv())),
+            CsvColumn::Edited => null(
+                msg.edited
+                    .map(|ts| ts.format("%Y-%m-%d %H:%M:%S").to_string()),
+            ),
+            CsvColumn::Mentions => {
+                null((!extra.mentions.is_empty()).then(|| extra.mentions.join("|")))
+            }
+            CsvColumn::ConversationId | CsvColumn::Channel => null(extra.conversation_id.clone()),
+            CsvColumn::SourceLine => null(extra.source_line.map(|line| line.to_string())),
+        })
+        .collect()
+}
+
+/// Write messages as a pretty-printed JSON array to `writer`.
+pub fn write_json<W: Write>(
+    messages: &[Message],
+    extras: &Extras,
+    mut writer: W,
+    options: &OutputOptions,
+) -> Result<()> {
+    let records: Vec<JsonEntry> = options
+        .entries(messages)
+        .into_iter()
+        .map(|entry| JsonEntry::new(entry, extras, options))
+        .collect();
+
+    let json = match &options.envelope {
+        Some(meta) => serde_json::to_string_pretty(&JsonEnvelope {
+            meta,
+            message_count: messages.len(),
+            messages: records,
+        })?,
+        None => serde_json::to_string_pretty(&records)?,
+    };
+    writer.write_all(json.as_bytes())?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write messages as a JSON array to a file in the configured encoding.
+pub fn write_json_to_file(
+    messages: &[Message],
+    extras: &Extras,
+    path: &Path,
+    options: &OutputOptions,
+) -> Result<()> {
+    write_json(messages, extras, create_output(path, options)?, options)
+}
+
+/// Write one line of line-oriented output, ended by the configured newline.
+fn write_line<W: Write>(writer: &mut W, line: &str, options: &OutputOptions) -> io::Result<()> {
+    writer.write_all(line.as_bytes())?;
+    writer.write_all(options.newline.as_str().as_bytes())
+}
+
+/// Write messages as JSON Lines to `writer`, one object per line.
+pub fn write_jsonl<W: Write>(
+    messages: &[Message],
+    extras: &Extras,
+    mut writer: W,
+    options: &OutputOptions,
+) -> Result<()> {
+    if let Some(meta) = &options.envelope {
+        let header = JsonlMetadata {
+            kind: "metadata",
+            meta,
+            message_count: messages.len(),
+        };
+        write_line(&mut writer, &serde_json::to_string(&header)?, options)?;
+    }
+
+    write_jsonl_streaming(messages, extras, writer, options)?;
+    Ok(())
+}
+
+/// Write messages as JSON Lines to `writer` as `messages` yields them.
+///
+/// Holds one message at a time, and takes owned or borrowed messages so
+/// [`write_jsonl`] can pass its slice without cloning. The metadata line
+/// needs the message count up front, so it is never written here. Returns
+/// the number of messages written.
+pub fn write_jsonl_streaming<I, W>(
+    messages: I,
+    extras: &Extras,
+    mut writer: W,
+    options: &OutputOptions,
+) -> Result<usize>
+where
+    I: IntoIterator,
+    I::Item: Borrow<Message>,
+    W: Write,
+{
+    let mut separators = options.separators();
+    let mut count = 0;
+
+    for msg in messages {
+        let msg = msg.borrow();
+        for separator in separators.before(msg) {
+            let record = JsonEntry::new(separator, extras, options);
+            write_line(&mut writer, &serde_json::to_string(&record)?, options)?;
+        }
+
+        let record = JsonEntry::new(MessageOrSeparator::Message(msg), extras, options);
+        write_line(&mut writer, &serde_json::to_string(&record)?, options)?;
+        count += 1;
+    }
+
+    writer.flush()?;
+    Ok(count)
+}
+
+/// Write messages as JSON Lines to a file in the configured encoding.
+///
+/// With `--append`, lines go after the existing content without a second
+/// metadata line.
+pub fn write_jsonl_to_file(
+    messages: &[Message],
+    extras: &Extras,
+    path: &Path,
+    options: &OutputOptions,
+) -> Result<()> {
+    let options = continuing_if_appending(path, options);
+    write_jsonl(messages, extras, create_output(path, &options)?, &options)
+}
+
+#[derive(Serialize)]
+struct JsonlWindow<'a> {
+    window: usize,
+    messages: Vec<JsonEntry<'a>>,
+}
+
+/// Write sliding windows as JSON Lines, one `{"window": i, "messages": [...]}` per line.
+pub fn write_jsonl_windows(
+    windows: &[Vec<Message>],
+    extras: &Extras,
+    path: &Path,
+    options: &OutputOptions,
+) -> Result<()> {
+    let write_metadata = !appends_to_existing(path, options);
+    let mut writer = create_output(path, options)?;
+
+    if let Some(meta) = options.envelope.as_ref().filter(|_| write_metadata) {
+        let header = JsonlMetadata {
+            kind: "metadata",
+            meta,
+            message_count: windows.iter().map(Vec::len).sum(),
+        };
+        write_line(&mut writer, &serde_json::to_string(&header)?, options)?;
+    }
+
+    for (index, window) in windows.iter().enumerate() {
+        let record = JsonlWindow {
+            window: index,
+            messages: options
+                .entries(window)
+                .into_iter()
+                .map(|entry| JsonEntry::new(entry, extras, options))
+                .collect(),
+        };
+        write_line(&mut writer, &serde_json::to_string(&record)?, options)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write human/assistant turn pairs as CSV with `Human` and `Assistant` columns.
+pub fn write_turns_csv(turns: &[Turn], path: &Path, options: &OutputOptions) -> Result<()> {
+    let write_header = options.csv_header && !appends_to_existing(path, options);
+    let mut writer = csv_writer(create_output(path, options)?, options);
+
+    if write_header {
+        writer.write_record(["Human", "Assistant"])?;
+    }
+    for turn in turns {
+        writer.write_record([&turn.human, &turn.assistant])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct JsonlDialogue<'a> {
+    speakers: &'a [String; 2],
+    messages: Vec<JsonEntry<'a>>,
+}
+
+/// Write two-party dialogues as JSON Lines, one
+/// `{"speakers": ["Alice", "Bob"], "messages": [...]}` per line.
+pub fn write_jsonl_dialogues(
+    dialogues: &[TwoPartyDialogue],
+    extras: &Extras,
+    path: &Path,
+    options: &OutputOptions,
+) -> Result<()> {
+    let write_metadata = !appends_to_existing(path, options);
+    let mut writer = create_output(path, options)?;
+
+    if let Some(meta) = options.envelope.as_ref().filter(|_| write_metadata) {
+        let header = JsonlMetadata {
+            kind: "metadata",
+            meta,
+            message_count: dialogues.iter().map(|d| d.messages.len()).sum(),
+        };
+        write_line(&mut writer, &serde_json::to_string(&header)?, options)?;
+    }
+
+    for dialogue in dialogues {
+        let record = JsonlDialogue {
+            speakers: &dialogue.speakers,
+            messages: options
+                .entries(&dialogue.messages)
+                .into_iter()
+                .map(|entry| JsonEntry::new(entry, extras, options))
+                .collect(),
+        };
+        write_line(&mut writer, &serde_json::to_string(&record)?, options)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write a pivot table as CSV: a `Period` column, then one column per sender.
+pub fn write_pivot_csv(table: &PivotTable, path: &Path, options: &OutputOptions) -> Result<()> {
+    let mut writer = csv_writer(create_output(path, options)?, options);
+
+    if options.csv_header {
+        let header = std::iter::once("Period").chain(table.senders.iter().map(String::as_str));
+        writer.write_record(header)?;
+    }
+    for (period, cells) in &table.rows {
+        let cells = cells.iter().map(usize::to_string);
+        writer.write_record(std::iter::once(period.clone()).chain(cells))?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Fill a `--context-window-format` template for one message.
+///
+/// `{sender}`, `{text}`, `{timestamp}` and `{id}` are replaced in a single
+/// pass, so braces inside message text are never expanded. A missing
+/// timestamp or ID renders as nothing; unknown `{...}` is kept as written.
+pub fn render_template(
+    template: &str,
+    msg: &Message,
+    extra: &MessageExtras,
+    options: &OutputOptions,
+) -> String {
+    let mut rendered = String::with_capacity(template.len() + msg.content.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        let token = &rest[start..];
+        let Some(end) = token.find('}') else {
+            rest = token;
+            break;
+        };
+
+        match &token[1..end] {
+            "sender" => rendered.push_str(&msg.sender),
+            "text" => rendered.push_str(&options.wrapped_content(msg, extra, "  ")),
+            "timestamp" => {
+                let ts = options.timestamp(msg, "%Y-%m-%d %H:%M:%S");
+                rendered.push_str(ts.as_deref().unwrap_or_default());
+            }
+            "id" => {
+                if let Some(id) = msg.id {
+                    rendered.push_str(&id.to_string());
+                }
+            }
+            // A stray `{`: keep it and look for a token after it
+            name if name.contains('{') => {
+                rendered.push('{');
+                rest = &token[1..];
+                continue;
+            }
+            _ => rendered.push_str(&token[..=end]),
+        }
+        rest = &token[end + 1..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+/// Text output layout for `--context-window-format`
+#[derive(Debug, Clone, Default)]
+pub struct Template {
+    /// Template rendered for each message, see [`render_template`]
+    pub message: String,
+    /// Written once before the first message
+    pub start_marker: String,
+    /// Written once after the last message
+    pub end_marker: String,
+    /// Written between consecutive messages
+    pub separator: String,
+}
+
+/// Write every message through a template, between the start and end markers.
+pub fn write_template_to_file(
+    messages: &[Message],
+    extras: &Extras,
+    template: &Template,
+    path: &Path,
+    options: &OutputOptions,
+) -> Result<()> {
+    let mut writer = create_output(path, options)?;
+
+    // Only the template's own line breaks take the configured line ending,
+    // message text keeps its own
+    let newline = options.newline.as_str();
+    let message_template = template.message.replace('\n', newline);
+
+    writer.write_all(template.start_marker.replace('\n', newline).as_bytes())?;
+    let mut first = true;
+    for entry in options.entries(messages) {
+        match entry {
+            MessageOrSeparator::Message(msg) => {
+                if !first {
+                    writer.write_all(template.separator.replace('\n', newline).as_bytes())?;
+                }
+                first = false;
+                let rendered = render_template(&message_template, msg, extras.get(msg), options);
+                writer.write_all(rendered.as_bytes())?;
+            }
+            // Separators get a line of their own between the rendered messages
+            separator => write_line(
+                &mut writer,
+                &separator_line(&separator).unwrap_or_default(),
+                options,
+            )?,
+        }
+    }
+    writer.write_all(template.end_marker.replace('\n', newline).as_bytes())?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write human/assistant turn pairs as a pretty-printed JSON array.
+pub fn write_turns_json(turns: &[Turn], path: &Path, options: &OutputOptions) -> Result<()> {
+    let json = serde_json::to_string_pretty(turns)?;
+    let mut file = create_output(path, options)?;
+    file.write_all(json.as_bytes())?;
+    file.flush()?;
+    Ok(())
+}
+
+/// Write human/assistant turn pairs as JSON Lines.
+pub fn write_turns_jsonl(turns: &[Turn], path: &Path, options: &OutputOptions) -> Result<()> {
+    let mut writer = create_output(path, options)?;
+
+    for turn in turns {
+        write_line(&mut writer, &serde_json::to_string(turn)?, options)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(hour * 3600, 0).unwrap()
+    }
+
+    /// JSONL rendered from the entry list the batch writers use
+    fn jsonl_batch(messages: &[Message], options: &OutputOptions) -> Vec<u8> {
+        let extras = Extras::default();
+        let mut out = Vec::new();
+        for entry in options.entries(messages) {
+            let record = JsonEntry::new(entry, &extras, options);
+            write_line(&mut out, &serde_json::to_string(&record).unwrap(), options).unwrap();
+        }
+        out
+    }
+
+    fn jsonl_streaming(messages: &[Message], options: &OutputOptions) -> Vec<u8> {
+        let mut out = Vec::new();
+        // Owned messages, as a streaming parser yields them
+        let iter = messages.iter().cloned();
+        let count = write_jsonl_streaming(iter, &Extras::default(), &mut out, options).unwrap();
+        assert_eq!(count, messages.len());
+        out
+    }
+
+    #[test]
+    fn test_jsonl_streaming_matches_batch() {
+        let messages = vec![
+            Message::new("Alice", "first").with_timestamp(at(1)),
+            Message::new("Bob", "no time"),
+            Message::new("Alice", "same day").with_timestamp(at(2)),
+            // A long silence that also crosses into the next day
+            Message::new("Bob", "next day").with_timestamp(at(30)),
+            Message::new("Alice", "later").with_timestamp(at(34)),
+        ];
+        let options = OutputOptions::new(OutputConfig::new().with_timestamps())
+            .with_day_separators()
+            .with_conversation_boundary(TimeDelta::hours(3));
+
+        let batch = jsonl_batch(&messages, &options);
+        assert_eq!(jsonl_streaming(&messages, &options), batch);
+
+        let lines: Vec<&str> = std::str::from_utf8(&batch).unwrap().lines().collect();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0], r#"{"type":"day_separator","date":"1970-01-01"}"#);
+        assert!(lines[4].contains("boundary"), "{}", lines[4]);
+        assert_eq!(lines[5], r#"{"type":"day_separator","date":"1970-01-02"}"#);
+    }
+
+    #[test]
+    fn test_jsonl_streaming_matches_batch_without_separators() {
+        let messages = vec![
+            Message::new("Alice", "hi").with_timestamp(at(1)),
+            Message::new("Bob", "hey").with_timestamp(at(30)),
+        ];
+        let options = OutputOptions::default();
+
+        assert_eq!(
+            jsonl_streaming(&messages, &options),
+            jsonl_batch(&messages, &options)
+        );
+    }
+
+    fn sample() -> Vec<Message> {
+        vec![
+            Message::new("Alice", "Hello; \"world\"")
+                .with_id(1)
+                .with_timestamp(at(1)),
+            Message::new("Bob", "two\nlines").with_id(2),
+        ]
+    }
+
+    fn lf(config: OutputConfig) -> OutputOptions {
+        OutputOptions::new(config).with_newline(Newline::Lf)
+    }
+
+    fn written(write: impl FnOnce(&mut Vec<u8>) -> Result<()>) -> String {
+        let mut out = Vec::new();
+        write(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_write_csv_bytes() {
+        let options = lf(OutputConfig::new().with_timestamps().with_ids());
+        let csv = written(|out| write_csv(&sample(), &Extras::default(), out, &options));
+
+        assert_eq!(
+            csv,
+            "ID;Timestamp;Sender;Content\n\
+             1;1970-01-01 01:00:00;Alice;\"Hello; \"\"world\"\"\"\n\
+             2;;Bob;\"two\nlines\"\n"
+        );
+    }
+
+    #[test]
+    fn test_write_csv_crlf_ends_records_only() {
+        let options = OutputOptions::default().with_newline(Newline::Crlf);
+        let csv = written(|out| write_csv(&sample(), &Extras::default(), out, &options));
+
+        assert_eq!(
+            csv,
+            "Sender;Content\r\nAlice;\"Hello; \"\"world\"\"\"\r\nBob;\"two\nlines\"\r\n"
+        );
+    }
+
+    #[test]
+    fn test_write_json_bytes() {
+        let options = lf(OutputConfig::new().with_ids());
+        let json = written(|out| write_json(&sample(), &Extras::default(), out, &options));
+
+        assert_eq!(
+            json,
+            r#"[
+  {
+    "sender": "Alice",
+    "content": "Hello; \"world\"",
+    "id": 1
+  },
+  {
+    "sender": "Bob",
+    "content": "two\nlines",
+    "id": 2
+  }
+]"#
+        );
+    }
+
+    #[test]
+    fn test_write_jsonl_bytes() {
+        let options = lf(OutputConfig::new().with_timestamps());
+        let jsonl = written(|out| write_jsonl(&sample(), &Extras::default(), out, &options));
+
+        assert_eq!(
+            jsonl,
+            concat!(
+                r#"{"sender":"Alice","content":"Hello; \"world\"","timestamp":"1970-01-01T01:00:00Z"}"#,
+                "\n",
+                r#"{"sender":"Bob","content":"two\nlines"}"#,
+                "\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_csv_mentions_cell() {
+        let options = OutputOptions::default()
+            .with_mentions()
+            .with_null_replacement("NULL".to_string());
+        let msg = Message::new("Bob", "hi");
+
+        let none = csv_record(&msg, &MessageExtras::default(), &options);
+        assert_eq!(none.last().unwrap(), "NULL");
+
+        let extra = MessageExtras {
+            mentions: vec!["Alice".to_string(), "Carol".to_string()],
+            ..MessageExtras::default()
+        };
+        let some = csv_record(&msg, &extra, &options);
+        assert_eq!(some.last().unwrap(), "Alice|Carol");
+    }
+}
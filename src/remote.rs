@@ -0,0 +1,113 @@
+//! HTTP(S) transfers: exports fetched for `--input-url` and output sent
+//! with `--output-url`.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+
+/// HTTP method used to send `--output-url` uploads
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum HttpMethod {
+    #[default]
+    Post,
+    Put,
+    Patch,
+}
+
+/// Downloaded copy of a remote export, removed when dropped.
+///
+/// The `chatpack` parsers only read from paths, so the response body is
+/// streamed into a temporary file that keeps the URL's file name (and with
+/// it the extension some parsers look at).
+pub struct RemoteInput {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl RemoteInput {
+    /// Fetch `url`, sending `headers` given as `Name: value`.
+    pub fn fetch(url: &str, headers: &[String]) -> Result<Self> {
+        let mut response = with_headers(ureq::get(url), headers)?
+            .call()
+            .with_context(|| format!("Failed to fetch {}", url))?;
+
+        let dir = std::env::temp_dir().join(format!("chatpack-url-{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(file_name(url));
+
+        let remote = Self { dir, path };
+        let mut file = BufWriter::new(
+            File::create(&remote.path)
+                .with_context(|| format!("Failed to write {}", remote.path.display()))?,
+        );
+        io::copy(&mut response.body_mut().as_reader(), &mut file)
+            .with_context(|| format!("Failed to download {}", url))?;
+        file.flush()?;
+
+        Ok(remote)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for RemoteInput {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Send `body` to `url` with the given content type and extra headers.
+pub fn upload(
+    url: &str,
+    method: HttpMethod,
+    content_type: &str,
+    headers: &[String],
+    body: &[u8],
+) -> Result<()> {
+    let request = match method {
+        HttpMethod::Post => ureq::post(url),
+        HttpMethod::Put => ureq::put(url),
+        HttpMethod::Patch => ureq::patch(url),
+    };
+
+    with_headers(request.header("Content-Type", content_type), headers)?
+        .send(body)
+        .with_context(|| format!("Failed to upload output to {}", url))?;
+    Ok(())
+}
+
+/// Add headers given as `Name: value` to a request.
+fn with_headers<B>(
+    mut request: ureq::RequestBuilder<B>,
+    headers: &[String],
+) -> Result<ureq::RequestBuilder<B>> {
+    for header in headers {
+        let Some((name, value)) = header.split_once(':') else {
+            bail!(
+                "Invalid --http-header '{}': expected \"Name: value\"",
+                header
+            );
+        };
+        request = request.header(name.trim(), value.trim());
+    }
+    Ok(request)
+}
+
+/// Last path segment of a URL, without query or fragment.
+fn file_name(url: &str) -> &str {
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let path = path.split_once("://").map_or(path, |(_, rest)| rest);
+    match path.split_once('/') {
+        Some((_, path)) => path
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty() && *name != "." && *name != "..")
+            .unwrap_or("export"),
+        None => "export",
+    }
+}
@@ -0,0 +1,657 @@
+//! Markdown processing report for `--report`.
+//!
+//! Unlike the summary printed to stderr, the report is written next to the
+//! output as `{output}.report.md`, so it can be shared along with the file.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chatpack::prelude::*;
+use chrono::{DateTime, Datelike, TimeDelta, Timelike, Utc, Weekday};
+use serde::Serialize;
+
+use crate::Cli;
+use crate::benchmark;
+use crate::transform;
+
+/// Number of words listed in the report and the summary
+pub const TOP_WORDS: usize = 10;
+
+/// Words too common to say anything about a conversation, unless replaced
+/// by `--stopwords-file`
+const STOP_WORDS: [&str; 57] = [
+    "a", "i", "am", "an", "as", "at", "be", "by", "do", "he", "if", "in", "is", "it", "me", "my",
+    "no", "of", "on", "or", "so", "to", "up", "us", "we", "the", "and", "for", "are", "but", "not",
+    "you", "all", "any", "can", "had", "her", "was", "one", "our", "out", "has", "him", "his",
+    "how", "its", "let", "she", "too", "use", "that", "with", "have", "this", "will", "your",
+    "from",
+];
+
+/// A reply only counts as a response within this long of the message before
+const MAX_RESPONSE_TIME: TimeDelta = TimeDelta::hours(24);
+
+/// How quickly senders answer each other.
+///
+/// A response is a message that follows, within 24 hours, a message from
+/// someone else.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResponseTimeStats {
+    pub median_seconds: f64,
+    /// 95th percentile (nearest rank)
+    pub p95_seconds: f64,
+    pub min_seconds: u64,
+    pub max_seconds: u64,
+    /// Median response time of each sender who responded
+    pub per_sender: HashMap<String, f64>,
+}
+
+impl ResponseTimeStats {
+    /// Response times of `messages`, in export order; `None` if nobody
+    /// responded to anyone.
+    fn new(messages: &[Message]) -> Option<Self> {
+        let mut all = Vec::new();
+        let mut by_sender: HashMap<&str, Vec<u64>> = HashMap::new();
+        let mut previous: Option<&Message> = None;
+
+        for msg in messages.iter().filter(|msg| msg.timestamp.is_some()) {
+            if let Some(prev) = previous.filter(|prev| prev.sender != msg.sender) {
+                let delay = msg
+                    .timestamp
+                    .zip(prev.timestamp)
+                    .map(|(ts, prev)| ts - prev);
+                if let Some(delay) =
+                    delay.filter(|d| *d >= TimeDelta::zero() && *d <= MAX_RESPONSE_TIME)
+                {
+                    let seconds = delay.num_seconds() as u64;
+                    all.push(seconds);
+                    by_sender.entry(&msg.sender).or_default().push(seconds);
+                }
+            }
+            previous = Some(msg);
+        }
+
+        if all.is_empty() {
+            return None;
+        }
+        all.sort_unstable();
+
+        let per_sender = by_sender
+            .into_iter()
+            .map(|(sender, mut times)| {
+                times.sort_unstable();
+                (sender.to_string(), median(&times))
+            })
+            .collect();
+
+        // Nearest rank: the smallest value with at least 95% at or below it
+        let p95_rank = (all.len() * 95).div_ceil(100);
+        Some(Self {
+            median_seconds: median(&all),
+            p95_seconds: all[p95_rank - 1] as f64,
+            min_seconds: all[0],
+            max_seconds: all[all.len() - 1],
+            per_sender,
+        })
+    }
+}
+
+/// Median of sorted, non-empty `values`
+fn median(values: &[u64]) -> f64 {
+    let mid = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[mid - 1] + values[mid]) as f64 / 2.0
+    } else {
+        values[mid] as f64
+    }
+}
+
+/// Counts and highlights of the messages that made it into the output.
+#[derive(Debug, Clone, Default)]
+pub struct ConversationStats {
+    /// Messages read from the export
+    pub parsed: usize,
+    /// Messages left after filtering
+    pub messages: usize,
+    /// Senders with their message counts, most active first
+    pub participants: Vec<(String, usize)>,
+    pub first_message: Option<Message>,
+    pub last_message: Option<Message>,
+    pub first_timestamp: Option<DateTime<Utc>>,
+    pub last_timestamp: Option<DateTime<Utc>>,
+    /// Timestamped messages per hour of the day (UTC), 0 to 23
+    hourly: [usize; 24],
+    /// Timestamped messages per day of the week, Monday first
+    daily: [usize; 7],
+    response_times: Option<ResponseTimeStats>,
+    /// Uses of each word, overall and per sender
+    words: HashMap<String, usize>,
+    sender_words: HashMap<String, HashMap<String, usize>>,
+    /// Words left out of the top words
+    stopwords: HashSet<String>,
+    /// Files written, with their sizes in bytes
+    pub outputs: Vec<(PathBuf, u64)>,
+}
+
+impl ConversationStats {
+    /// Stats of the filtered messages, before merging.
+    pub fn new(messages: &[Message], parsed: usize) -> Self {
+        let mut participants: HashMap<&str, usize> = HashMap::new();
+        let mut words: HashMap<String, usize> = HashMap::new();
+        let mut sender_words: HashMap<String, HashMap<String, usize>> = HashMap::new();
+        let mut hourly = [0; 24];
+        let mut daily = [0; 7];
+
+        for msg in messages {
+            *participants.entry(&msg.sender).or_default() += 1;
+            if let Some(ts) = msg.timestamp {
+                hourly[ts.hour() as usize] += 1;
+                daily[ts.weekday().num_days_from_monday() as usize] += 1;
+            }
+            let own_words = sender_words.entry(msg.sender.clone()).or_default();
+            for word in words_of(&msg.content) {
+                *own_words.entry(word.clone()).or_default() += 1;
+                *words.entry(word).or_default() += 1;
+            }
+        }
+
+        Self {
+            parsed,
+            messages: messages.len(),
+            participants: by_count(participants),
+            first_message: messages.first().cloned(),
+            last_message: messages.last().cloned(),
+            first_timestamp: messages.iter().filter_map(|msg| msg.timestamp).min(),
+            last_timestamp: messages.iter().filter_map(|msg| msg.timestamp).max(),
+            hourly,
+            daily,
+            response_times: ResponseTimeStats::new(messages),
+            words,
+            sender_words,
+            stopwords: STOP_WORDS.iter().map(|word| word.to_string()).collect(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Leave `stopwords` out of the top words instead of the built-in list.
+    #[must_use]
+    pub fn with_stopwords(mut self, stopwords: HashSet<String>) -> Self {
+        self.stopwords = stopwords;
+        self
+    }
+
+    /// The `n` most used words, lowercased, with their counts; most used
+    /// first, ties in alphabetical order.
+    pub fn top_n_words(&self, n: usize) -> Vec<(String, usize)> {
+        self.top_of(&self.words, n)
+    }
+
+    /// [`Self::top_n_words`] of each sender.
+    pub fn per_sender_top_words(&self, n: usize) -> HashMap<String, Vec<(String, usize)>> {
+        self.sender_words
+            .iter()
+            .map(|(sender, words)| (sender.clone(), self.top_of(words, n)))
+            .collect()
+    }
+
+    fn top_of(&self, words: &HashMap<String, usize>, n: usize) -> Vec<(String, usize)> {
+        let mut top: Vec<_> = words
+            .iter()
+            .filter(|(word, _)| !self.stopwords.contains(*word))
+            .map(|(word, &count)| (word.clone(), count))
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top.truncate(n);
+        top
+    }
+
+    /// How fast senders respond to each other; `None` when no message
+    /// answers someone else's within 24 hours.
+    pub fn response_time_stats(&self) -> Option<&ResponseTimeStats> {
+        self.response_times.as_ref()
+    }
+
+    /// Messages sent in each hour of the day, index 0 being 00:00–00:59 UTC.
+    pub fn hourly_counts(&self) -> [usize; 24] {
+        self.hourly
+    }
+
+    /// Messages sent on each day of the week, index 0 being Monday.
+    pub fn daily_counts(&self) -> [usize; 7] {
+        self.daily
+    }
+
+    /// Hour (0–23) with the most messages; the earliest one on a tie.
+    /// `None` without timestamps.
+    pub fn most_active_hour(&self) -> Option<u8> {
+        busiest(&self.hourly).map(|hour| hour as u8)
+    }
+
+    /// Day of the week with the most messages; the earliest from Monday on
+    /// a tie. `None` without timestamps.
+    pub fn most_active_day_of_week(&self) -> Option<Weekday> {
+        busiest(&self.daily).and_then(|day| Weekday::try_from(day as u8).ok())
+    }
+
+    #[must_use]
+    pub fn with_outputs(mut self, outputs: &[PathBuf]) -> Self {
+        self.outputs = outputs
+            .iter()
+            .map(|path| (path.clone(), benchmark::size_of(path)))
+            .collect();
+        self
+    }
+}
+
+/// Whitespace-split words of `text`, lowercased, without the punctuation
+/// around them: `"Sure,"` counts as `sure`, `don't` stays whole.
+fn words_of(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Index of the largest non-zero count, the first one on a tie
+fn busiest(counts: &[usize]) -> Option<usize> {
+    let max = counts.iter().copied().max().filter(|&max| max > 0)?;
+    counts.iter().position(|&count| count == max)
+}
+
+fn by_count(counts: HashMap<&str, usize>) -> Vec<(String, usize)> {
+    let mut counts: Vec<_> = counts
+        .into_iter()
+        .map(|(name, count)| (name.to_string(), count))
+        .collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// Load a `--stopwords-file`: one word per line, `#` starts a comment line.
+pub fn load_stopwords(path: &Path) -> Result<HashSet<String>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_lowercase)
+        .collect())
+}
+
+/// Path of the report for an output path.
+pub fn report_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".report.md");
+    PathBuf::from(name)
+}
+
+/// Filters given on the command line, as `--flag value` strings.
+pub fn applied_filters(cli: &Cli) -> Vec<String> {
+    let mut filters = Vec::new();
+    let values = [
+        ("--after", cli.after.clone()),
+        ("--before", cli.before.clone()),
+        ("--after-id", cli.after_id.map(|id| id.to_string())),
+        ("--before-id", cli.before_id.map(|id| id.to_string())),
+        ("--from", cli.from.clone()),
+        ("--only-conversation", cli.only_conversation.clone()),
+    ];
+    for (flag, value) in values {
+        if let Some(value) = value {
+            filters.push(format!("{} {}", flag, value));
+        }
+    }
+
+    let switches = [
+        ("--exclude-auto-replies", cli.exclude_auto_replies),
+        ("--first-per-sender", cli.first_per_sender),
+        ("--last-per-sender", cli.last_per_sender),
+    ];
+    for (flag, on) in switches {
+        if on {
+            filters.push(flag.to_string());
+        }
+    }
+
+    filters
+}
+
+/// Write the Markdown report of a conversion to `path`.
+pub fn write_report(stats: &ConversationStats, cli: &Cli, path: &Path) -> Result<()> {
+    let mut md = String::new();
+
+    writeln!(md, "# Chat export report")?;
+    writeln!(md)?;
+    writeln!(md, "- **File:** `{}`", cli.display_input.display())?;
+    writeln!(md, "- **Platform:** {}", cli.source.to_platform())?;
+    if let (Some(first), Some(last)) = (stats.first_timestamp, stats.last_timestamp) {
+        writeln!(
+            md,
+            "- **Period:** {} → {}",
+            first.format("%Y-%m-%d"),
+            last.format("%Y-%m-%d")
+        )?;
+    }
+    writeln!(
+        md,
+        "- **Messages:** {} parsed, {} kept",
+        stats.parsed, stats.messages
+    )?;
+
+    writeln!(md)?;
+    writeln!(md, "## Participants")?;
+    writeln!(md)?;
+    writeln!(md, "| Sender | Messages |")?;
+    writeln!(md, "|--------|----------|")?;
+    for (sender, count) in &stats.participants {
+        writeln!(md, "| {} | {} |", escape_cell(sender), count)?;
+    }
+
+    let top_words = stats.top_n_words(TOP_WORDS);
+    if !top_words.is_empty() {
+        writeln!(md)?;
+        writeln!(md, "## Top words")?;
+        writeln!(md)?;
+        for (i, (word, count)) in top_words.iter().enumerate() {
+            writeln!(md, "{}. {} ({})", i + 1, word, count)?;
+        }
+    }
+
+    if let (Some(hour), Some(day)) = (stats.most_active_hour(), stats.most_active_day_of_week()) {
+        writeln!(md)?;
+        writeln!(md, "## Activity")?;
+        writeln!(md)?;
+        writeln!(md, "- **Busiest hour:** {:02}:00–{:02}:59 UTC", hour, hour)?;
+        writeln!(md, "- **Busiest day:** {}", day_name(day))?;
+    }
+    if let Some(response) = stats.response_time_stats() {
+        let seconds = |s: f64| TimeDelta::seconds(s.round() as i64);
+        writeln!(
+            md,
+            "- **Response time:** {} median, {} for 95% of replies",
+            transform::format_gap(seconds(response.median_seconds)),
+            transform::format_gap(seconds(response.p95_seconds))
+        )?;
+    }
+
+    if let (Some(first), Some(last)) = (&stats.first_message, &stats.last_message) {
+        writeln!(md)?;
+        writeln!(md, "## First and last message")?;
+        writeln!(md)?;
+        writeln!(md, "- **First:** {}", describe(first))?;
+        writeln!(md, "- **Last:** {}", describe(last))?;
+    }
+
+    writeln!(md)?;
+    writeln!(md, "## Filters")?;
+    writeln!(md)?;
+    let filters = applied_filters(cli);
+    if filters.is_empty() {
+        writeln!(md, "None")?;
+    }
+    for filter in filters {
+        writeln!(md, "- `{}`", filter)?;
+    }
+
+    writeln!(md)?;
+    writeln!(md, "## Output")?;
+    writeln!(md)?;
+    for (output, size) in &stats.outputs {
+        writeln!(
+            md,
+            "- `{}` ({}, {} bytes)",
+            output.display(),
+            cli.format.name(),
+            size
+        )?;
+    }
+
+    fs::write(path, md).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// One-line rendering of a message: time, sender and shortened text.
+fn describe(msg: &Message) -> String {
+    let text = transform::truncate_text(&msg.content.replace('\n', " "), 80);
+    match msg.timestamp {
+        Some(ts) => format!("{} — {}: {}", ts.format("%Y-%m-%d %H:%M"), msg.sender, text),
+        None => format!("{}: {}", msg.sender, text),
+    }
+}
+
+pub fn day_name(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "Monday",
+        Weekday::Tue => "Tuesday",
+        Weekday::Wed => "Wednesday",
+        Weekday::Thu => "Thursday",
+        Weekday::Fri => "Friday",
+        Weekday::Sat => "Saturday",
+        Weekday::Sun => "Sunday",
+    }
+}
+
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn stats(texts: &[(&str, &str)]) -> ConversationStats {
+        let messages: Vec<Message> = texts
+            .iter()
+            .map(|&(sender, text)| Message::new(sender, text))
+            .collect();
+        ConversationStats::new(&messages, messages.len())
+    }
+
+    fn words(top: &[(String, usize)]) -> Vec<(&str, usize)> {
+        top.iter()
+            .map(|(word, count)| (word.as_str(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn test_top_words_split_on_whitespace() {
+        let stats = stats(&[
+            ("Alice", "Go, go GO! It's ok"),
+            ("Bob", "ok... it's a re-run"),
+        ]);
+
+        // Punctuation around a word is trimmed, inside it kept; "a" is a stopword
+        assert_eq!(
+            words(&stats.top_n_words(10)),
+            [("go", 3), ("it's", 2), ("ok", 2), ("re-run", 1)]
+        );
+    }
+
+    #[test]
+    fn test_top_words_ties_alphabetical_and_truncated() {
+        let stats = stats(&[("Alice", "pear apple fig apple pear kiwi")]);
+
+        assert_eq!(
+            words(&stats.top_n_words(3)),
+            [("apple", 2), ("pear", 2), ("fig", 1)]
+        );
+    }
+
+    #[test]
+    fn test_top_words_custom_stopwords_replace_built_in() {
+        let stopwords = HashSet::from(["apple".to_string()]);
+        let stats = stats(&[("Alice", "the apple is the best apple")]).with_stopwords(stopwords);
+
+        assert_eq!(
+            words(&stats.top_n_words(10)),
+            [("the", 2), ("best", 1), ("is", 1)]
+        );
+    }
+
+    #[test]
+    fn test_per_sender_top_words() {
+        let stats = stats(&[
+            ("Alice", "tea tea coffee"),
+            ("Bob", "coffee"),
+            ("Alice", "the"),
+        ]);
+        let per_sender = stats.per_sender_top_words(1);
+
+        assert_eq!(words(&per_sender["Alice"]), [("tea", 2)]);
+        assert_eq!(words(&per_sender["Bob"]), [("coffee", 1)]);
+    }
+
+    /// 2024-01-15 is a Monday
+    fn jan(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, day, hour, minute, 0).unwrap()
+    }
+
+    fn timed(messages: &[(&str, DateTime<Utc>)]) -> Vec<Message> {
+        messages
+            .iter()
+            .map(|&(sender, ts)| Message::new(sender, "hi").with_timestamp(ts))
+            .collect()
+    }
+
+    #[test]
+    fn test_hourly_and_daily_counts() {
+        let mut messages = timed(&[
+            ("Alice", jan(15, 9, 0)),
+            ("Bob", jan(15, 9, 59)),
+            ("Alice", jan(16, 23, 30)),
+            ("Bob", jan(21, 0, 5)),
+        ]);
+        messages.push(Message::new("Bob", "no time"));
+        let stats = ConversationStats::new(&messages, messages.len());
+
+        let mut hourly = [0; 24];
+        hourly[9] = 2;
+        hourly[23] = 1;
+        hourly[0] = 1;
+        assert_eq!(stats.hourly_counts(), hourly);
+        // Monday, Tuesday and Sunday
+        assert_eq!(stats.daily_counts(), [2, 1, 0, 0, 0, 0, 1]);
+
+        assert_eq!(stats.most_active_hour(), Some(9));
+        assert_eq!(stats.most_active_day_of_week(), Some(Weekday::Mon));
+    }
+
+    #[test]
+    fn test_busiest_tie_goes_to_earliest() {
+        let messages = timed(&[
+            ("Alice", jan(21, 22, 0)),
+            ("Bob", jan(19, 6, 0)),
+            ("Alice", jan(21, 6, 30)),
+            ("Bob", jan(19, 22, 30)),
+        ]);
+        let stats = ConversationStats::new(&messages, messages.len());
+
+        // Two messages each at 06 and 22, and on Friday and Sunday
+        assert_eq!(stats.most_active_hour(), Some(6));
+        assert_eq!(stats.most_active_day_of_week(), Some(Weekday::Fri));
+
+        assert_eq!(busiest(&[0, 3, 1, 3]), Some(1));
+        assert_eq!(busiest(&[0, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_activity_without_timestamps() {
+        let stats = stats(&[("Alice", "hi")]);
+
+        assert_eq!(stats.hourly_counts(), [0; 24]);
+        assert_eq!(stats.daily_counts(), [0; 7]);
+        assert_eq!(stats.most_active_hour(), None);
+        assert_eq!(stats.most_active_day_of_week(), None);
+    }
+
+    fn response_times(messages: &[(&str, DateTime<Utc>)]) -> Option<ResponseTimeStats> {
+        ResponseTimeStats::new(&timed(messages))
+    }
+
+    #[test]
+    fn test_single_response() {
+        let stats = response_times(&[("Alice", jan(15, 9, 0)), ("Bob", jan(15, 9, 5))]).unwrap();
+
+        assert_eq!(stats.median_seconds, 300.0);
+        assert_eq!(stats.p95_seconds, 300.0);
+        assert_eq!((stats.min_seconds, stats.max_seconds), (300, 300));
+        assert_eq!(
+            stats.per_sender,
+            HashMap::from([("Bob".to_string(), 300.0)])
+        );
+    }
+
+    #[test]
+    fn test_no_response_from_same_sender() {
+        assert_eq!(
+            response_times(&[("Alice", jan(15, 9, 0)), ("Alice", jan(15, 9, 5))]),
+            None
+        );
+        assert_eq!(response_times(&[]), None);
+    }
+
+    #[test]
+    fn test_even_count_median_is_mean_of_middle_two() {
+        // Responses of 60, 120, 180 and 600 seconds
+        let stats = response_times(&[
+            ("Alice", jan(15, 9, 0)),
+            ("Bob", jan(15, 9, 1)),
+            ("Alice", jan(15, 9, 3)),
+            ("Bob", jan(15, 9, 6)),
+            ("Alice", jan(15, 9, 16)),
+        ])
+        .unwrap();
+
+        assert_eq!(stats.median_seconds, 150.0);
+        assert_eq!((stats.min_seconds, stats.max_seconds), (60, 600));
+        // Bob: 60 and 180; Alice: 120 and 600
+        assert_eq!(stats.per_sender["Bob"], 120.0);
+        assert_eq!(stats.per_sender["Alice"], 360.0);
+    }
+
+    #[test]
+    fn test_response_cutoff_at_24_hours() {
+        let exactly = response_times(&[("Alice", jan(15, 9, 0)), ("Bob", jan(16, 9, 0))]).unwrap();
+        assert_eq!(exactly.max_seconds, 24 * 3600);
+
+        assert_eq!(
+            response_times(&[("Alice", jan(15, 9, 0)), ("Bob", jan(16, 9, 1))]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_out_of_order_timestamps_dropped() {
+        let stats = response_times(&[
+            ("Alice", jan(15, 9, 10)),
+            // Before the message it follows in the export
+            ("Bob", jan(15, 9, 0)),
+            ("Alice", jan(15, 9, 2)),
+        ])
+        .unwrap();
+
+        assert_eq!((stats.min_seconds, stats.max_seconds), (120, 120));
+        assert!(!stats.per_sender.contains_key("Bob"));
+    }
+
+    #[test]
+    fn test_p95_nearest_rank() {
+        // Twenty responses of 1 to 20 minutes: rank ceil(0.95 * 20) = 19
+        let mut messages = Vec::new();
+        let mut ts = jan(15, 0, 0);
+        for minutes in 0..=20 {
+            ts += TimeDelta::minutes(minutes);
+            let sender = if minutes % 2 == 0 { "Alice" } else { "Bob" };
+            messages.push((sender, ts));
+        }
+        let stats = response_times(&messages).unwrap();
+
+        assert_eq!(stats.p95_seconds, 19.0 * 60.0);
+        assert_eq!(stats.max_seconds, 20 * 60);
+        assert_eq!(stats.median_seconds, 10.5 * 60.0);
+    }
+}
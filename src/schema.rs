@@ -0,0 +1,254 @@
+//! JSON Schema (draft-07) of the output written with the current options.
+//!
+//! Built from the same switches as the writers in [`crate::output`], so a
+//! field only appears in the schema when it can appear in the output.
+
+use serde_json::{Map, Value, json};
+
+use crate::Format;
+use crate::output::{OutputOptions, ReplyFormat};
+
+const DRAFT_07: &str = "http://json-schema.org/draft-07/schema#";
+
+/// Schema of the output for `format` with the given options.
+///
+/// JSON output is described as a whole document, JSONL output as a single
+/// line, and CSV output as a single row keyed by column name.
+pub fn generate_schema(options: &OutputOptions, format: Format) -> Value {
+    let (title, body) = match format {
+        Format::Csv => ("chatpack CSV row", csv_row(options)),
+        Format::Json => {
+            let messages = json!({ "type": "array", "items": entry(options) });
+            let body = match options.envelope {
+                Some(_) => envelope(messages),
+                None => messages,
+            };
+            ("chatpack JSON output", body)
+        }
+        Format::Jsonl => {
+            let body = match options.envelope {
+                Some(_) => json!({ "oneOf": [jsonl_metadata(), entry(options)] }),
+                None => entry(options),
+            };
+            ("chatpack JSONL line", body)
+        }
+    };
+
+    document(title, body)
+}
+
+/// Schema of a JSONL line written with `--window-size`.
+pub fn generate_window_schema(options: &OutputOptions) -> Value {
+    let window = object(
+        [
+            ("window", json!({ "type": "integer", "minimum": 0 })),
+            (
+                "messages",
+                json!({ "type": "array", "items": entry(options) }),
+            ),
+        ],
+        &["window", "messages"],
+    );
+
+    let body = match options.envelope {
+        Some(_) => json!({ "oneOf": [jsonl_metadata(), window] }),
+        None => window,
+    };
+
+    document("chatpack JSONL window", body)
+}
+
+/// Schema of the output written with `--conversation-turns`.
+pub fn generate_turns_schema(format: Format) -> Value {
+    let turn = |human: &str, assistant: &str| {
+        object(
+            [
+                (human, json!({ "type": "string" })),
+                (assistant, json!({ "type": "string" })),
+            ],
+            &[human, assistant],
+        )
+    };
+
+    match format {
+        Format::Csv => document("chatpack CSV turn", turn("Human", "Assistant")),
+        Format::Json => document(
+            "chatpack JSON turns",
+            json!({ "type": "array", "items": turn("human", "assistant") }),
+        ),
+        Format::Jsonl => document("chatpack JSONL turn", turn("human", "assistant")),
+    }
+}
+
+fn document(title: &str, body: Value) -> Value {
+    let mut schema = Map::new();
+    schema.insert("$schema".into(), DRAFT_07.into());
+    schema.insert("title".into(), title.into());
+
+    if let Value::Object(body) = body {
+        schema.extend(body);
+    }
+
+    Value::Object(schema)
+}
+
+/// Closed object schema with the given properties.
+fn object<'a>(properties: impl IntoIterator<Item = (&'a str, Value)>, required: &[&str]) -> Value {
+    let properties: Map<String, Value> = properties
+        .into_iter()
+        .map(|(name, schema)| (name.to_string(), schema))
+        .collect();
+
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// A message, a day separator with `--group-by-day`, or a boundary with
+/// `--conversation-boundary`.
+fn entry(options: &OutputOptions) -> Value {
+    let mut entries = vec![message(options)];
+
+    if options.group_by_day {
+        entries.push(object(
+            [
+                ("type", json!({ "const": "day_separator" })),
+                ("date", json!({ "type": "string", "format": "date" })),
+            ],
+            &["type", "date"],
+        ));
+    }
+
+    if options.conversation_boundary.is_some() {
+        entries.push(object(
+            [
+                ("type", json!({ "const": "boundary" })),
+                ("gap_minutes", json!({ "type": "integer", "minimum": 0 })),
+            ],
+            &["type", "gap_minutes"],
+        ));
+    }
+
+    match entries.len() {
+        1 => entries.remove(0),
+        _ => json!({ "oneOf": entries }),
+    }
+}
+
+fn message(options: &OutputOptions) -> Value {
+    let config = &options.config;
+    let string = || json!({ "type": "string" });
+    let integer = || json!({ "type": "integer", "minimum": 0 });
+    let timestamp = || match options.relative_to {
+        Some(_) => json!({ "type": "string" }),
+        None => json!({ "type": "string", "format": "date-time" }),
+    };
+
+    let mut properties = Vec::new();
+    let mut required = Vec::new();
+
+    if !options.omit_sender {
+        properties.push(("sender", string()));
+        required.push("sender");
+    }
+    properties.push(("content", string()));
+    required.push("content");
+
+    if config.include_timestamps {
+        properties.push(("timestamp", timestamp()));
+    }
+    if config.include_ids {
+        properties.push(("id", integer()));
+    }
+    match options.reply_format {
+        ReplyFormat::Id => properties.push(("reply_to", integer())),
+        // Falls back to the ID when the replied-to message isn't in the export
+        ReplyFormat::Quote => properties.push((
+            "reply_to",
+            json!({ "type": ["string", "integer"], "pattern": "^> ", "minimum": 0 }),
+        )),
+        ReplyFormat::Inline | ReplyFormat::Omit => {}
+    }
+    if config.include_edited {
+        properties.push(("edited", json!({ "type": "string", "format": "date-time" })));
+    }
+    if options.include_mentions {
+        properties.push(("mentions", json!({ "type": "array", "items": string() })));
+    }
+    if options.include_conversation_id {
+        properties.push(("conversation_id", string()));
+    }
+    if options.include_channel_name {
+        properties.push(("channel_name", string()));
+    }
+    if options.include_channel_meta {
+        properties.push(("views", integer()));
+        properties.push(("forwards", integer()));
+    }
+    if options.include_source_lines {
+        properties.push(("source_line", json!({ "type": "integer", "minimum": 1 })));
+    }
+
+    object(properties, &required)
+}
+
+/// CSV rows: every column is a string; empty when the value is missing.
+fn csv_row(options: &OutputOptions) -> Value {
+    let header = crate::output::csv_header(options);
+    let columns = header
+        .iter()
+        .map(|&column| (column, json!({ "type": "string" })));
+    object(columns, &header)
+}
+
+fn envelope(messages: Value) -> Value {
+    object(
+        envelope_fields().into_iter().chain([
+            ("message_count", json!({ "type": "integer", "minimum": 0 })),
+            ("messages", messages),
+        ]),
+        &[
+            "chatpack_version",
+            "platform",
+            "source_file",
+            "exported_at",
+            "message_count",
+            "messages",
+        ],
+    )
+}
+
+fn jsonl_metadata() -> Value {
+    object(
+        [("type", json!({ "const": "metadata" }))]
+            .into_iter()
+            .chain(envelope_fields())
+            .chain([("message_count", json!({ "type": "integer", "minimum": 0 }))]),
+        &[
+            "type",
+            "chatpack_version",
+            "platform",
+            "source_file",
+            "exported_at",
+            "message_count",
+        ],
+    )
+}
+
+fn envelope_fields() -> [(&'static str, Value); 4] {
+    [
+        ("chatpack_version", json!({ "type": "string" })),
+        (
+            "platform",
+            json!({ "enum": ["telegram", "whatsapp", "instagram", "discord"] }),
+        ),
+        ("source_file", json!({ "type": "string" })),
+        (
+            "exported_at",
+            json!({ "type": "string", "format": "date-time" }),
+        ),
+    ]
+}
@@ -0,0 +1,114 @@
+//! Per-participant statistics for `--sender-stats`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chatpack::Message;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// One row of the `--sender-stats` file
+#[derive(Debug, Serialize)]
+pub struct SenderStats {
+    pub sender: String,
+    pub message_count: usize,
+    pub total_chars: usize,
+    pub avg_chars: f64,
+    pub total_words: usize,
+    pub avg_words: f64,
+    pub first_message_at: Option<String>,
+    pub last_message_at: Option<String>,
+    /// Messages that directly follow a message from someone else
+    pub response_count: usize,
+}
+
+#[derive(Default)]
+struct Totals {
+    messages: usize,
+    chars: usize,
+    words: usize,
+    first: Option<DateTime<Utc>>,
+    last: Option<DateTime<Utc>>,
+    responses: usize,
+}
+
+/// Statistics for every sender in `messages`, most messages first.
+pub fn sender_stats(messages: &[Message]) -> Vec<SenderStats> {
+    let mut totals: HashMap<&str, Totals> = HashMap::new();
+    let mut previous: Option<&str> = None;
+
+    for msg in messages {
+        let entry = totals.entry(&msg.sender).or_default();
+        entry.messages += 1;
+        entry.chars += msg.content.chars().count();
+        entry.words += msg.content.split_whitespace().count();
+        if let Some(ts) = msg.timestamp {
+            entry.first = Some(entry.first.map_or(ts, |first| first.min(ts)));
+            entry.last = Some(entry.last.map_or(ts, |last| last.max(ts)));
+        }
+        if previous.is_some_and(|sender| sender != msg.sender) {
+            entry.responses += 1;
+        }
+        previous = Some(&msg.sender);
+    }
+
+    let mut stats: Vec<_> = totals
+        .into_iter()
+        .map(|(sender, totals)| SenderStats {
+            sender: sender.to_string(),
+            message_count: totals.messages,
+            total_chars: totals.chars,
+            avg_chars: average(totals.chars, totals.messages),
+            total_words: totals.words,
+            avg_words: average(totals.words, totals.messages),
+            first_message_at: totals.first.map(format_timestamp),
+            last_message_at: totals.last.map(format_timestamp),
+            response_count: totals.responses,
+        })
+        .collect();
+    stats.sort_by(|a, b| {
+        b.message_count
+            .cmp(&a.message_count)
+            .then_with(|| a.sender.cmp(&b.sender))
+    });
+    stats
+}
+
+/// Mean rounded to two decimals
+fn average(total: usize, count: usize) -> f64 {
+    (total as f64 / count as f64 * 100.0).round() / 100.0
+}
+
+fn format_timestamp(ts: DateTime<Utc>) -> String {
+    ts.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// `OUTPUT.sender_stats.csv`, or `.json` for JSON and JSONL output
+pub fn stats_path(output: &Path, json: bool) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(if json {
+        ".sender_stats.json"
+    } else {
+        ".sender_stats.csv"
+    });
+    PathBuf::from(name)
+}
+
+/// Write `stats` to `path` as a JSON array or as CSV with a header row.
+pub fn write(stats: &[SenderStats], path: &Path, json: bool) -> Result<()> {
+    let write = || -> Result<()> {
+        if json {
+            fs::write(path, serde_json::to_string_pretty(stats)? + "\n")?;
+        } else {
+            let mut writer = csv::Writer::from_path(path)?;
+            for row in stats {
+                writer.serialize(row)?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    };
+    write().with_context(|| format!("Failed to write {}", path.display()))
+}
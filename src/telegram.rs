@@ -0,0 +1,180 @@
+//! Telegram export reader for options the `chatpack` parser can't support.
+//!
+//! The library parser skips messages without text (photos, stickers, voice
+//! notes), without a `from` field (Saved Messages), and service messages
+//! (group renamed, pinned message, ...). This reader follows the
+//! same rules for everything else but can keep those messages. It loads the
+//! whole export into memory and is only used when such an option is enabled.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chatpack::prelude::*;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+
+use crate::media;
+
+/// Options that change how raw Telegram messages are read
+#[derive(Debug, Clone, Default)]
+pub struct TelegramOptions {
+    /// Describe attached media instead of dropping media-only messages
+    pub media_descriptions: bool,
+    /// Sender for messages without a `from` field
+    pub default_sender: Option<String>,
+    /// Use the channel's name as the sender for messages without a `from` field
+    pub channel_mode: bool,
+    /// Keep group events as `[Group was renamed to "..."]`-style messages
+    pub service_messages: bool,
+}
+
+impl TelegramOptions {
+    /// Whether any option requires this reader instead of the library parser.
+    pub fn is_active(&self) -> bool {
+        self.media_descriptions
+            || self.default_sender.is_some()
+            || self.channel_mode
+            || self.service_messages
+    }
+}
+
+/// How much of the file to read when looking for the root `type`
+const HEADER_LEN: u64 = 4096;
+
+/// Read the export's root `type` (`personal_chat`, `saved_messages`, ...)
+/// without loading the whole file.
+///
+/// Telegram writes the root fields before the `messages` array, so the start
+/// of the file is enough.
+pub fn chat_type(path: &Path) -> Result<Option<String>> {
+    let mut head = Vec::new();
+    File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?
+        .take(HEADER_LEN)
+        .read_to_end(&mut head)?;
+
+    // The cut may split a multi-byte character; that only affects the tail
+    let head = String::from_utf8_lossy(&head);
+    let root = head.split("\"messages\"").next().unwrap_or_default();
+    let chat_type = root.split_once("\"type\"").and_then(|(_, rest)| {
+        let value = rest.trim_start().strip_prefix(':')?.trim_start();
+        let value = value.strip_prefix('"')?;
+        value.split_once('"').map(|(ty, _)| ty.to_string())
+    });
+
+    Ok(chat_type)
+}
+
+/// Parse a Telegram `result.json` export.
+pub fn parse(path: &Path, options: &TelegramOptions) -> Result<Vec<Message>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let root: Value = serde_json::from_str(&content).context("Failed to parse Telegram export")?;
+
+    let raw_messages = root["messages"].as_array().map_or(&[][..], Vec::as_slice);
+
+    let mut options = options.clone();
+    if options.channel_mode && options.default_sender.is_none() {
+        options.default_sender = root["name"].as_str().map(ToString::to_string);
+    }
+
+    Ok(raw_messages
+        .iter()
+        .filter_map(|raw| parse_message(raw, &options))
+        .collect())
+}
+
+fn parse_message(raw: &Value, options: &TelegramOptions) -> Option<Message> {
+    match raw["type"].as_str() {
+        Some("message") => {}
+        Some("service") if options.service_messages => return parse_service(raw, options),
+        _ => return None,
+    }
+
+    let sender = raw["from"].as_str().or(options.default_sender.as_deref())?;
+    let text = extract_text(&raw["text"]);
+
+    let content = match options
+        .media_descriptions
+        .then(|| media::telegram_description(raw))
+        .flatten()
+    {
+        Some(description) if text.trim().is_empty() => description,
+        Some(description) => format!("{}\n{}", description, text),
+        None => text,
+    };
+
+    if content.trim().is_empty() {
+        return None;
+    }
+
+    Some(Message::with_metadata(
+        sender,
+        content,
+        unix_timestamp(&raw["date_unixtime"]),
+        raw["id"].as_u64(),
+        raw["reply_to_message_id"].as_u64(),
+        unix_timestamp(&raw["edited_unixtime"]),
+    ))
+}
+
+/// A service message as a message from its actor describing the event.
+///
+/// Events without a description (calls, member changes, ...) are skipped.
+fn parse_service(raw: &Value, options: &TelegramOptions) -> Option<Message> {
+    let sender = raw["actor"]
+        .as_str()
+        .or(options.default_sender.as_deref())?;
+    let content = service_text(raw)?;
+
+    Some(Message::with_metadata(
+        sender,
+        content,
+        unix_timestamp(&raw["date_unixtime"]),
+        raw["id"].as_u64(),
+        None,
+        None,
+    ))
+}
+
+fn service_text(raw: &Value) -> Option<String> {
+    let title = raw["title"].as_str().unwrap_or_default();
+
+    let text = match raw["action"].as_str()? {
+        "create_group" => format!("[Group \"{}\" was created]", title),
+        "edit_group_title" => format!("[Group was renamed to \"{}\"]", title),
+        "edit_group_photo" => "[Group photo was changed]".to_string(),
+        "delete_group_photo" => "[Group photo was removed]".to_string(),
+        "pin_message" => match raw["message_id"].as_u64() {
+            Some(id) => format!("[Pinned message {}]", id),
+            None => "[Pinned a message]".to_string(),
+        },
+        "migrate_to_supergroup" => "[Group was upgraded to a supergroup]".to_string(),
+        "migrate_from_group" => format!("[Supergroup was created from group \"{}\"]", title),
+        _ => return None,
+    };
+
+    Some(text)
+}
+
+/// Flatten Telegram's `text` field, which is a string or an array of
+/// strings and `{"type": ..., "text": ...}` entities.
+pub fn extract_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part.as_str().or_else(|| part["text"].as_str()))
+            .collect(),
+        _ => String::new(),
+    }
+}
+
+fn unix_timestamp(value: &Value) -> Option<DateTime<Utc>> {
+    value
+        .as_str()
+        .and_then(|ts| ts.parse::<i64>().ok())
+        .and_then(|ts| DateTime::from_timestamp(ts, 0))
+}
@@ -0,0 +1,102 @@
+//! Keyword topics for `--topic-filter`.
+//!
+//! The file maps topic names to keywords, e.g.
+//! `{"sports": ["match", "goal"], "tech": ["rust", "compiler"]}`. Each message
+//! goes to the topic with the most of its keywords in the text. This is plain
+//! keyword matching: no stemming, no synonyms.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+
+/// Topic of messages that contain no keyword
+pub const UNCLASSIFIED: &str = "unclassified";
+
+/// Topics and their lowercased keywords, sorted by topic name
+#[derive(Debug, Clone, Default)]
+pub struct TopicClassifier {
+    topics: Vec<(String, Vec<String>)>,
+}
+
+impl TopicClassifier {
+    pub fn new(topics: HashMap<String, Vec<String>>) -> Self {
+        let mut topics: Vec<_> = topics
+            .into_iter()
+            .map(|(name, keywords)| {
+                let keywords = keywords
+                    .iter()
+                    .map(|keyword| keyword.trim().to_lowercase())
+                    .filter(|keyword| !keyword.is_empty())
+                    .collect();
+                (name, keywords)
+            })
+            .collect();
+        topics.sort_by(|a, b| a.0.cmp(&b.0));
+        Self { topics }
+    }
+
+    /// Load a JSON object of topic names to keyword lists.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let topics: HashMap<String, Vec<String>> =
+            serde_json::from_str(&content).with_context(|| {
+                format!(
+                    "Invalid topics in {}: expected a map of topic names to keyword lists",
+                    path.display()
+                )
+            })?;
+
+        if topics
+            .keys()
+            .any(|name| name.eq_ignore_ascii_case(UNCLASSIFIED))
+        {
+            bail!(
+                "Invalid topics in {}: '{}' is reserved for messages matching no topic",
+                path.display(),
+                UNCLASSIFIED
+            );
+        }
+        Ok(Self::new(topics))
+    }
+
+    /// Topic names in output order, ending with [`UNCLASSIFIED`].
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.topics
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .chain(std::iter::once(UNCLASSIFIED))
+    }
+
+    /// Topic with the most distinct keywords in `text`.
+    ///
+    /// Ties go to the topic whose name sorts first; text with no keyword is
+    /// [`UNCLASSIFIED`].
+    pub fn classify(&self, text: &str) -> &str {
+        let text = text.to_lowercase();
+        let mut best = (0, UNCLASSIFIED);
+        for (name, keywords) in &self.topics {
+            let hits = keywords
+                .iter()
+                .filter(|keyword| contains_word(&text, keyword))
+                .count();
+            if hits > best.0 {
+                best = (hits, name.as_str());
+            }
+        }
+        best.1
+    }
+}
+
+/// Whether `keyword` occurs in `text` on word boundaries, so "art" doesn't
+/// match "start"
+fn contains_word(text: &str, keyword: &str) -> bool {
+    text.match_indices(keyword).any(|(start, _)| {
+        let end = start + keyword.len();
+        let before = text[..start].chars().next_back();
+        let after = text[end..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
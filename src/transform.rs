@@ -0,0 +1,760 @@
+//! Message-list transformations applied between filtering and output.
+
+use std::collections::{BTreeMap, HashMap};
+
+use chatpack::prelude::*;
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use clap::ValueEnum;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+/// Unicode normalization form for message text and sender names
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum UnicodeForm {
+    /// Canonical composition (Android, most of the web)
+    #[value(name = "NFC", alias = "nfc")]
+    Nfc,
+    /// Canonical decomposition (common on iOS/macOS)
+    #[value(name = "NFD", alias = "nfd")]
+    Nfd,
+    /// Compatibility composition
+    #[value(name = "NFKC", alias = "nfkc")]
+    Nfkc,
+    /// Compatibility decomposition
+    #[value(name = "NFKD", alias = "nfkd")]
+    Nfkd,
+}
+
+impl UnicodeForm {
+    /// Normalize a string to this form.
+    pub fn apply(self, s: &str) -> String {
+        match self {
+            UnicodeForm::Nfc => s.nfc().collect(),
+            UnicodeForm::Nfd => s.nfd().collect(),
+            UnicodeForm::Nfkc => s.nfkc().collect(),
+            UnicodeForm::Nfkd => s.nfkd().collect(),
+        }
+    }
+}
+
+/// Normalize the content and sender of every message in place.
+pub fn normalize_unicode(messages: &mut [Message], form: UnicodeForm) {
+    for msg in messages {
+        msg.sender = form.apply(&msg.sender);
+        msg.content = form.apply(&msg.content);
+    }
+}
+
+/// Lowercase every sender name in place.
+pub fn lowercase_senders(messages: &mut [Message]) {
+    for msg in messages {
+        msg.sender = msg.sender.to_lowercase();
+    }
+}
+
+/// Keep the messages `keep` accepts, along with the values at the same index
+/// of `aligned`.
+pub fn retain_aligned<T>(
+    messages: &mut Vec<Message>,
+    aligned: Option<&mut Vec<T>>,
+    mut keep: impl FnMut(&Message) -> bool,
+) {
+    let kept: Vec<bool> = messages.iter().map(&mut keep).collect();
+    if let Some(aligned) = aligned {
+        let mut kept = kept.iter();
+        aligned.retain(|_| kept.next().copied().unwrap_or(false));
+    }
+    let mut kept = kept.iter();
+    messages.retain(|_| kept.next().copied().unwrap_or(false));
+}
+
+/// Give messages without a native ID one derived from their content.
+///
+/// The ID is the first 8 bytes of `sha256("{sender}|{timestamp}|{text}")`,
+/// so the same message gets the same ID in every export that contains it.
+pub fn hash_ids(messages: &mut [Message]) {
+    for msg in messages.iter_mut().filter(|msg| msg.id.is_none()) {
+        let timestamp = msg
+            .timestamp
+            .map(|ts| ts.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+            .unwrap_or_default();
+        let digest = Sha256::digest(format!("{}|{}|{}", msg.sender, timestamp, msg.content));
+        let mut id = [0u8; 8];
+        id.copy_from_slice(&digest[..8]);
+        msg.id = Some(u64::from_be_bytes(id));
+    }
+}
+
+/// Truncate text to at most `max_chars` characters, appending `…` if cut.
+///
+/// The cut is moved back to the last whitespace when there is one, so words
+/// are not split in half.
+pub fn truncate_text(text: &str, max_chars: usize) -> String {
+    let Some((cut, _)) = text.char_indices().nth(max_chars) else {
+        return text.to_string();
+    };
+
+    let kept = &text[..cut];
+    let kept = match kept.rfind(char::is_whitespace) {
+        Some(boundary) if boundary > 0 => &kept[..boundary],
+        _ => kept,
+    };
+
+    format!("{}…", kept.trim_end())
+}
+
+/// Hard-wrap each line of `text` so no physical line is longer than
+/// `max_chars` characters.
+///
+/// A line that is too long is cut mid-word, `marker` is put before each
+/// break, and every continuation starts with `indent`. The marker and indent
+/// count towards the limit, so `max_chars` must be longer than both.
+pub fn wrap_text(text: &str, max_chars: usize, marker: &str, indent: &str) -> String {
+    let marker_chars = marker.chars().count();
+    let indent_chars = indent.chars().count();
+    let mut wrapped = String::with_capacity(text.len());
+
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            wrapped.push('\n');
+        }
+
+        let mut rest = line;
+        let mut width = max_chars;
+        while rest.chars().nth(width).is_some() {
+            let kept = width.saturating_sub(marker_chars).max(1);
+            let cut = rest.char_indices().nth(kept).map_or(rest.len(), |(i, _)| i);
+            wrapped.push_str(&rest[..cut]);
+            wrapped.push_str(marker);
+            wrapped.push('\n');
+            wrapped.push_str(indent);
+            rest = &rest[cut..];
+            width = max_chars.saturating_sub(indent_chars);
+        }
+        wrapped.push_str(rest);
+    }
+
+    wrapped
+}
+
+/// Message text without the leading `> `-quoted lines of a reply.
+///
+/// Text that is nothing but quoted lines is returned unchanged.
+pub fn text_without_quotes(text: &str) -> &str {
+    let mut rest = text;
+    while rest.starts_with('>') {
+        match rest.split_once('\n') {
+            Some((_, next)) => rest = next,
+            None => return text,
+        }
+    }
+
+    match rest.trim_start_matches('\n') {
+        "" => text,
+        own => own,
+    }
+}
+
+/// Message methods the library doesn't provide.
+pub trait MessageExt {
+    /// Append the next message of a same-sender run to this one.
+    ///
+    /// Texts are joined with `separator`. The merged message keeps this
+    /// message's ID and timestamp, and the later edit time of the two.
+    fn merge_with(&mut self, other: Message, separator: &str);
+
+    /// Whether the message has no visible text.
+    ///
+    /// Whitespace, zero-width characters and direction marks don't count:
+    /// WhatsApp leaves a lone `U+200E` where media or deleted text was.
+    fn is_empty_text(&self) -> bool;
+}
+
+impl MessageExt for Message {
+    fn merge_with(&mut self, other: Message, separator: &str) {
+        self.content.push_str(separator);
+        self.content.push_str(&other.content);
+        self.edited = self.edited.max(other.edited);
+    }
+
+    fn is_empty_text(&self) -> bool {
+        self.content.chars().all(|c| {
+            c.is_whitespace()
+                || matches!(
+                    c,
+                    '\u{200b}'..='\u{200f}'
+                        | '\u{202a}'..='\u{202e}'
+                        | '\u{2060}'..='\u{2064}'
+                        | '\u{feff}'
+                )
+        })
+    }
+}
+
+/// Streaming form of [`merge_consecutive`]: joins runs of messages from the
+/// same sender as they come out of the inner iterator.
+///
+/// Only the current run is buffered. Text, IDs and timestamps match
+/// `merge_consecutive`; unlike it, a merged message keeps the latest
+/// `edited` time of its run (see [`MessageExt::merge_with`]).
+pub struct MergeIterator<I: Iterator<Item = Message>> {
+    inner: I,
+    /// First message of the next run, read while closing the current one
+    pending: Option<Message>,
+}
+
+impl<I: Iterator<Item = Message>> MergeIterator<I> {
+    pub fn new(inner: I) -> Self {
+        Self {
+            inner,
+            pending: None,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Message>> Iterator for MergeIterator<I> {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        let mut merged = self.pending.take().or_else(|| self.inner.next())?;
+
+        for msg in self.inner.by_ref() {
+            if msg.sender != merged.sender {
+                self.pending = Some(msg);
+                break;
+            }
+            merged.merge_with(msg, "\n");
+        }
+
+        Some(merged)
+    }
+}
+
+/// An output entry: a message, the start of a new calendar day, or a
+/// conversation boundary after a long silence.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageOrSeparator<'a> {
+    Message(&'a Message),
+    DaySeparator(NaiveDate),
+    /// Silence longer than `--conversation-boundary`, with its length
+    Boundary(TimeDelta),
+}
+
+/// Day separators and conversation boundaries, worked out one message at a
+/// time so the batch and streaming writers share the same rules.
+///
+/// A day separator goes before the first message of each calendar day (UTC).
+/// A boundary goes before each message that follows more than the gap of
+/// silence, ahead of the day separator of the same message. Messages without
+/// a timestamp neither start a new day nor start or end a silence.
+#[derive(Debug, Clone, Default)]
+pub struct Separators {
+    group_by_day: bool,
+    boundary: Option<TimeDelta>,
+    current_day: Option<NaiveDate>,
+    previous: Option<DateTime<Utc>>,
+}
+
+impl Separators {
+    pub fn new(group_by_day: bool, boundary: Option<TimeDelta>) -> Self {
+        Self {
+            group_by_day,
+            boundary,
+            ..Self::default()
+        }
+    }
+
+    /// The separators that go before `msg`, in output order.
+    pub fn before(&mut self, msg: &Message) -> Vec<MessageOrSeparator<'static>> {
+        let mut separators = Vec::new();
+
+        if let Some(gap) = self.boundary {
+            if let Some(silence) = silence_before(self.previous, msg, gap) {
+                separators.push(MessageOrSeparator::Boundary(silence));
+            }
+            self.previous = msg.timestamp.or(self.previous);
+        }
+
+        if let Some(day) = msg.timestamp.map(|ts| ts.date_naive()) {
+            if self.group_by_day && self.current_day != Some(day) {
+                separators.push(MessageOrSeparator::DaySeparator(day));
+                self.current_day = Some(day);
+            }
+        }
+
+        separators
+    }
+}
+
+/// Time since `previous` if `msg` comes more than `gap` after it.
+pub fn silence_before(
+    previous: Option<DateTime<Utc>>,
+    msg: &Message,
+    gap: TimeDelta,
+) -> Option<TimeDelta> {
+    let silence = msg.timestamp? - previous?;
+    (silence > gap).then_some(silence)
+}
+
+/// Describe a silence like `2h`, `1d 3h` or `45m`: the two largest units.
+pub fn format_gap(gap: TimeDelta) -> String {
+    let units = [
+        (gap.num_days(), "d"),
+        (gap.num_hours() % 24, "h"),
+        (gap.num_minutes() % 60, "m"),
+    ];
+    let parts: Vec<String> = units
+        .iter()
+        .skip_while(|(value, _)| *value == 0)
+        .take(2)
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect();
+
+    if parts.is_empty() {
+        "0m".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+/// Keep only each sender's first message.
+pub fn first_per_sender(messages: Vec<Message>) -> Vec<Message> {
+    one_per_sender(messages, false)
+}
+
+/// Keep only each sender's last message.
+pub fn last_per_sender(messages: Vec<Message>) -> Vec<Message> {
+    one_per_sender(messages, true)
+}
+
+/// Keep one message per sender, sorted by timestamp (ties keep input order).
+fn one_per_sender(messages: Vec<Message>, keep_last: bool) -> Vec<Message> {
+    let mut kept: HashMap<String, (usize, Message)> = HashMap::new();
+
+    for (index, msg) in messages.into_iter().enumerate() {
+        if keep_last || !kept.contains_key(&msg.sender) {
+            kept.insert(msg.sender.clone(), (index, msg));
+        }
+    }
+
+    let mut kept: Vec<(usize, Message)> = kept.into_values().collect();
+    kept.sort_by_key(|(index, msg)| (msg.timestamp, *index));
+    kept.into_iter().map(|(_, msg)| msg).collect()
+}
+
+/// Randomly keep at most `max_per_sender` messages from each sender.
+///
+/// Senders with fewer messages keep all of them. The sample is returned
+/// sorted by timestamp (ties keep input order). Senders are sampled in name
+/// order, so a seeded `rng` always picks the same messages.
+pub fn sample_balanced(
+    messages: Vec<Message>,
+    max_per_sender: usize,
+    rng: &mut impl Rng,
+) -> Vec<Message> {
+    let mut groups: BTreeMap<String, Vec<(usize, Message)>> = BTreeMap::new();
+    for (index, msg) in messages.into_iter().enumerate() {
+        groups
+            .entry(msg.sender.clone())
+            .or_default()
+            .push((index, msg));
+    }
+
+    let mut kept = Vec::new();
+    for mut group in groups.into_values() {
+        // partial_shuffle moves the chosen messages to the end of the group
+        let rest = group.len().saturating_sub(max_per_sender);
+        let _ = group.partial_shuffle(rng, max_per_sender);
+        kept.extend(group.drain(rest..));
+    }
+
+    kept.sort_by_key(|(index, msg)| (msg.timestamp, *index));
+    kept.into_iter().map(|(_, msg)| msg).collect()
+}
+
+/// Drop repeats of a message: the same text from the same sender within
+/// `window` of its previous occurrence.
+///
+/// Each occurrence, kept or not, restarts the window, so a message pasted
+/// every few minutes is dropped for as long as the repetition goes on. With
+/// no window, repeats are dropped anywhere in the list. Messages without a
+/// timestamp can't be placed in a window and are only dropped without one.
+pub fn dedup_first_occurrence(messages: Vec<Message>, window: Option<TimeDelta>) -> Vec<Message> {
+    let mut last_seen: HashMap<(String, String), Option<DateTime<Utc>>> = HashMap::new();
+
+    messages
+        .into_iter()
+        .filter(|msg| {
+            let key = (msg.sender.clone(), msg.content.clone());
+            let Some(previous) = last_seen.insert(key, msg.timestamp) else {
+                return true;
+            };
+            match (window, previous, msg.timestamp) {
+                (None, _, _) => false,
+                (Some(window), Some(previous), Some(ts)) => ts - previous > window,
+                (Some(_), _, _) => true,
+            }
+        })
+        .collect()
+}
+
+/// Split messages into windows of `size` consecutive messages, starting a new
+/// window every `step` messages.
+///
+/// The last window may be shorter than `size`; windows stop once one reaches
+/// the end of the list. `step == size` gives non-overlapping chunks.
+pub fn sliding_window(messages: &[Message], size: usize, step: usize) -> Vec<Vec<Message>> {
+    assert!(
+        size > 0 && step > 0,
+        "window size and step must be positive"
+    );
+
+    let mut windows = Vec::new();
+    let mut start = 0;
+
+    while start < messages.len() {
+        let end = (start + size).min(messages.len());
+        windows.push(messages[start..end].to_vec());
+        if end == messages.len() {
+            break;
+        }
+        start += step;
+    }
+
+    windows
+}
+
+/// Uninterrupted stretch of conversation between exactly two senders.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TwoPartyDialogue {
+    /// The two senders, in order of their first message
+    pub speakers: [String; 2],
+    pub messages: Vec<Message>,
+}
+
+/// Split messages into dialogues between two senders.
+///
+/// A dialogue ends when a third sender writes; their message starts the next
+/// one. Stretches with only one sender (monologues) are dropped.
+pub fn extract_two_party_dialogues(messages: Vec<Message>) -> Vec<TwoPartyDialogue> {
+    let mut dialogues = Vec::new();
+    let mut speakers: Vec<String> = Vec::new();
+    let mut current = Vec::new();
+
+    for msg in messages {
+        if !speakers.contains(&msg.sender) {
+            if speakers.len() == 2 {
+                dialogues.extend(two_party_dialogue(
+                    std::mem::take(&mut speakers),
+                    std::mem::take(&mut current),
+                ));
+            }
+            speakers.push(msg.sender.clone());
+        }
+        current.push(msg);
+    }
+    dialogues.extend(two_party_dialogue(speakers, current));
+
+    dialogues
+}
+
+fn two_party_dialogue(speakers: Vec<String>, messages: Vec<Message>) -> Option<TwoPartyDialogue> {
+    let speakers: [String; 2] = speakers.try_into().ok()?;
+    Some(TwoPartyDialogue { speakers, messages })
+}
+
+/// One human message followed by the assistant's answer.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Turn {
+    pub human: String,
+    pub assistant: String,
+}
+
+/// Pair messages into human → assistant turns for instruction fine-tuning.
+///
+/// Messages from `assistant` (case-insensitive) are the assistant's; everyone
+/// else is the human. Consecutive messages with the same role are joined with
+/// newlines. Assistant messages before the first human message and a trailing
+/// unanswered human message are dropped.
+pub fn conversation_turns(messages: &[Message], assistant: &str) -> Vec<Turn> {
+    // Collapse into alternating (is_assistant, text) runs
+    let mut runs: Vec<(bool, String)> = Vec::new();
+    for msg in messages {
+        let is_assistant = msg.sender.eq_ignore_ascii_case(assistant);
+        match runs.last_mut() {
+            Some((role, text)) if *role == is_assistant => {
+                text.push('\n');
+                text.push_str(&msg.content);
+            }
+            _ => runs.push((is_assistant, msg.content.clone())),
+        }
+    }
+
+    let mut runs = runs
+        .into_iter()
+        .skip_while(|(is_assistant, _)| *is_assistant);
+    let mut turns = Vec::new();
+    while let (Some((_, human)), Some((_, assistant))) = (runs.next(), runs.next()) {
+        turns.push(Turn { human, assistant });
+    }
+
+    turns
+}
+
+/// Time period of a `--pivot` row
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PivotPeriod {
+    #[default]
+    Day,
+    Hour,
+}
+
+/// Value of a `--pivot` cell
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum PivotMetric {
+    /// Number of messages
+    #[default]
+    Count,
+    /// Total characters of message text
+    Chars,
+}
+
+/// Wide-format activity table: one row per period, one column per sender.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PivotTable {
+    /// Column names, in order of each sender's first message
+    pub senders: Vec<String>,
+    /// Period label and one cell per sender, in chronological order
+    pub rows: Vec<(String, Vec<usize>)>,
+}
+
+/// Tally messages per period and sender.
+///
+/// Only periods with at least one message get a row; messages without a
+/// timestamp are skipped.
+pub fn pivot_messages(
+    messages: &[Message],
+    period: PivotPeriod,
+    metric: PivotMetric,
+) -> PivotTable {
+    let mut senders: Vec<String> = Vec::new();
+    let mut columns: HashMap<&str, usize> = HashMap::new();
+    let mut periods: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+    for msg in messages {
+        let Some(ts) = msg.timestamp else { continue };
+        let label = match period {
+            PivotPeriod::Day => ts.format("%Y-%m-%d"),
+            PivotPeriod::Hour => ts.format("%Y-%m-%d %H:00"),
+        };
+
+        let column = *columns.entry(&msg.sender).or_insert_with(|| {
+            senders.push(msg.sender.clone());
+            senders.len() - 1
+        });
+        let cells = periods.entry(label.to_string()).or_default();
+        if cells.len() <= column {
+            cells.resize(column + 1, 0);
+        }
+        cells[column] += match metric {
+            PivotMetric::Count => 1,
+            PivotMetric::Chars => msg.content.chars().count(),
+        };
+    }
+
+    let rows = periods
+        .into_iter()
+        .map(|(label, mut cells)| {
+            cells.resize(senders.len(), 0);
+            (label, cells)
+        })
+        .collect();
+
+    PivotTable { senders, rows }
+}
+
+/// Group messages by sender, preserving the original order within each group.
+pub fn group_by_sender(messages: Vec<Message>) -> HashMap<String, Vec<Message>> {
+    let mut groups: HashMap<String, Vec<Message>> = HashMap::new();
+
+    for msg in messages {
+        groups.entry(msg.sender.clone()).or_default().push(msg);
+    }
+
+    groups
+}
+
+/// Turn a sender name into a file-name-safe slug.
+///
+/// Lowercases the name and replaces every non-alphanumeric character with `_`.
+pub fn sender_slug(sender: &str) -> String {
+    sender
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Expand matched messages with up to `n` neighbours on each side.
+///
+/// Overlapping neighbourhoods are deduplicated and the result keeps the
+/// original (chronological) order of `all_messages`.
+pub fn expand_context(
+    matched_indices: &[usize],
+    all_messages: &[Message],
+    n: usize,
+) -> Vec<Message> {
+    let mut keep = vec![false; all_messages.len()];
+
+    for &i in matched_indices {
+        let start = i.saturating_sub(n);
+        let end = (i + n + 1).min(all_messages.len());
+        keep[start..end].iter_mut().for_each(|k| *k = true);
+    }
+
+    all_messages
+        .iter()
+        .zip(keep)
+        .filter(|(_, k)| *k)
+        .map(|(msg, _)| msg.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        DateTime::from_timestamp(i64::from(hour) * 3600, 0).unwrap()
+    }
+
+    #[test]
+    fn test_merge_with_joins_text_with_separator() {
+        let mut first = Message::new("Alice", "Hello")
+            .with_id(1)
+            .with_timestamp(at(1));
+        let second = Message::new("Alice", "World")
+            .with_id(2)
+            .with_timestamp(at(2));
+
+        first.merge_with(second, " | ");
+
+        assert_eq!(first.content, "Hello | World");
+        assert_eq!(first.id, Some(1));
+        assert_eq!(first.timestamp, Some(at(1)));
+    }
+
+    #[test]
+    fn test_merge_with_keeps_later_edit_time() {
+        let mut first = Message::new("Alice", "a").with_edited(at(5));
+        first.merge_with(Message::new("Alice", "b").with_edited(at(3)), "\n");
+        assert_eq!(first.edited, Some(at(5)));
+
+        let mut first = Message::new("Alice", "a").with_edited(at(3));
+        first.merge_with(Message::new("Alice", "b").with_edited(at(5)), "\n");
+        assert_eq!(first.edited, Some(at(5)));
+    }
+
+    #[test]
+    fn test_merge_with_keeps_edit_time_when_other_is_none() {
+        let mut first = Message::new("Alice", "a").with_edited(at(4));
+        first.merge_with(Message::new("Alice", "b"), "\n");
+        assert_eq!(first.edited, Some(at(4)));
+
+        let mut first = Message::new("Alice", "a");
+        first.merge_with(Message::new("Alice", "b").with_edited(at(4)), "\n");
+        assert_eq!(first.edited, Some(at(4)));
+
+        let mut first = Message::new("Alice", "a");
+        first.merge_with(Message::new("Alice", "b"), "\n");
+        assert_eq!(first.edited, None);
+    }
+
+    #[test]
+    fn test_merge_with_into_empty_message() {
+        // Matches `merge_consecutive`, which joins empty texts as well
+        let mut first = Message::new("Alice", "");
+        first.merge_with(Message::new("Alice", "later"), "\n");
+
+        assert_eq!(first.content, "\nlater");
+        assert_eq!(
+            merge_consecutive(vec![
+                Message::new("Alice", ""),
+                Message::new("Alice", "later")
+            ])[0]
+                .content,
+            first.content
+        );
+    }
+
+    fn merged(messages: Vec<Message>) -> Vec<Message> {
+        MergeIterator::new(messages.into_iter()).collect()
+    }
+
+    #[test]
+    fn test_merge_iterator_splits_runs_at_sender_change() {
+        let result = merged(vec![
+            Message::new("Alice", "one").with_id(1),
+            Message::new("Alice", "two").with_id(2),
+            Message::new("Bob", "three").with_id(3),
+            Message::new("Alice", "four").with_id(4),
+            Message::new("Alice", "five").with_id(5),
+        ]);
+
+        let runs: Vec<(&str, &str, Option<u64>)> = result
+            .iter()
+            .map(|msg| (msg.sender.as_str(), msg.content.as_str(), msg.id))
+            .collect();
+        assert_eq!(
+            runs,
+            [
+                ("Alice", "one\ntwo", Some(1)),
+                ("Bob", "three", Some(3)),
+                ("Alice", "four\nfive", Some(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_iterator_empty() {
+        assert!(merged(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn test_merge_iterator_single_message() {
+        let msg = Message::new("Alice", "only")
+            .with_id(7)
+            .with_timestamp(at(2))
+            .with_edited(at(3));
+
+        assert_eq!(merged(vec![msg.clone()]), [msg]);
+    }
+
+    #[test]
+    fn test_merge_iterator_keeps_latest_edit_time() {
+        let messages = vec![
+            Message::new("Alice", "a")
+                .with_timestamp(at(1))
+                .with_edited(at(4)),
+            Message::new("Alice", "b")
+                .with_timestamp(at(2))
+                .with_edited(at(6)),
+            Message::new("Alice", "c").with_timestamp(at(3)),
+        ];
+
+        let result = merged(messages.clone());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].content, "a\nb\nc");
+        assert_eq!(result[0].timestamp, Some(at(1)));
+        assert_eq!(result[0].edited, Some(at(6)));
+
+        // Same text as the library, which leaves `edited` as the first message's
+        let library = merge_consecutive(messages);
+        assert_eq!(library[0].content, result[0].content);
+        assert_eq!(library[0].timestamp, result[0].timestamp);
+    }
+}
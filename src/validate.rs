@@ -0,0 +1,421 @@
+//! `chatpack validate`: a quick look at an export before a long conversion.
+//!
+//! Only the start of the file is read. Up to [`SAMPLE_SIZE`] messages are
+//! taken from it and checked for the fields the parsers need: sender, text
+//! and timestamp. JSON exports are checked on the raw objects, since the
+//! parsers silently drop messages with missing fields; text exports
+//! (WhatsApp, Discord TXT/CSV) go through the `chatpack` parser.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chatpack::parsing::whatsapp::{DateFormat, detect_whatsapp_format};
+use chatpack::prelude::*;
+use serde_json::Value;
+
+use crate::instagram::InstagramFormat;
+use crate::telegram;
+use crate::whatsapp::{self, WhatsAppVariant};
+
+/// Number of messages checked
+pub const SAMPLE_SIZE: usize = 100;
+
+/// How much of the file is read
+const HEAD_LEN: u64 = 4 * 1024 * 1024;
+
+/// Outcome of checking an export
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Detected layout, e.g. "Telegram personal chat, current format"
+    pub variant: String,
+    /// Number of messages checked
+    pub sampled: usize,
+    /// Checked messages with a sender and text; timestamps are optional
+    pub usable: usize,
+    /// Problems found, with the number of messages affected
+    pub issues: Vec<(usize, String)>,
+    /// Problem that makes the file unprocessable
+    pub fatal: Option<String>,
+}
+
+impl Report {
+    /// Whether the file looks processable.
+    pub fn is_ok(&self) -> bool {
+        self.fatal.is_none() && self.usable > 0
+    }
+
+    fn fail(variant: impl Into<String>, reason: impl Into<String>) -> Self {
+        Report {
+            variant: variant.into(),
+            fatal: Some(reason.into()),
+            ..Report::default()
+        }
+    }
+
+    /// Record field checks for one message.
+    fn check(&mut self, sender: bool, text: bool, timestamp: bool) {
+        self.sampled += 1;
+        if sender && text {
+            self.usable += 1;
+        }
+
+        for (present, issue) in [
+            (sender, "without a sender"),
+            (text, "without text"),
+            (timestamp, "without a timestamp"),
+        ] {
+            if present {
+                continue;
+            }
+            match self.issues.iter_mut().find(|(_, what)| what == issue) {
+                Some((count, _)) => *count += 1,
+                None => self.issues.push((1, issue.to_string())),
+            }
+        }
+    }
+
+    /// Print the report to stdout.
+    pub fn print(&self, path: &Path) {
+        let mark = if self.is_ok() { "✅" } else { "❌" };
+        println!("{} {}: {}", mark, path.display(), self.variant);
+        println!("   🔍 Checked:  {} messages", self.sampled);
+
+        for (count, issue) in &self.issues {
+            println!("   ⚠️  {} messages {}", count, issue);
+        }
+
+        if let Some(ref reason) = self.fatal {
+            println!("   ❌ {}", reason);
+        } else if self.usable == 0 {
+            println!("   ❌ No message has both a sender and text");
+        }
+    }
+}
+
+/// Check the start of an export file.
+pub fn validate(platform: Platform, path: &Path) -> Result<Report> {
+    let head = read_head(path)?;
+
+    Ok(match platform {
+        Platform::Telegram => validate_telegram(path, &head)?,
+        Platform::WhatsApp => validate_whatsapp(&head),
+        Platform::Instagram => validate_instagram(&head),
+        Platform::Discord => validate_discord(&head),
+        other => Report::fail(format!("{:?}", other), "Unsupported platform"),
+    })
+}
+
+/// Read at most [`HEAD_LEN`] bytes, dropping a character split by the cut.
+fn read_head(path: &Path) -> Result<String> {
+    let mut head = Vec::new();
+    File::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?
+        .take(HEAD_LEN)
+        .read_to_end(&mut head)?;
+
+    Ok(String::from_utf8_lossy(&head)
+        .trim_end_matches('\u{fffd}')
+        .to_string())
+}
+
+fn validate_telegram(path: &Path, head: &str) -> Result<Report> {
+    let chat = telegram::chat_type(path)?
+        .map(|ty| ty.replace('_', " "))
+        .unwrap_or_else(|| "chat".to_string());
+
+    let Some(objects) = sample_array(head, "messages") else {
+        return Ok(Report::fail(
+            format!("Telegram {}", chat),
+            "No \"messages\" array found",
+        ));
+    };
+
+    let messages: Vec<Value> = match objects {
+        Ok(objects) => objects
+            .into_iter()
+            .filter(|raw| raw["type"].as_str() == Some("message"))
+            .take(SAMPLE_SIZE)
+            .collect(),
+        Err(reason) => return Ok(Report::fail(format!("Telegram {}", chat), reason)),
+    };
+
+    // Exports from before Telegram Desktop 2.x only have the local `date`
+    let legacy = !messages.is_empty()
+        && messages
+            .iter()
+            .all(|raw| raw.get("date_unixtime").is_none());
+    let layout = if legacy {
+        "legacy format without date_unixtime"
+    } else {
+        "current format"
+    };
+
+    let mut report = Report {
+        variant: format!("Telegram {}, {}", chat, layout),
+        ..Report::default()
+    };
+    for raw in &messages {
+        report.check(
+            raw["from"].as_str().is_some(),
+            !telegram::extract_text(&raw["text"]).trim().is_empty(),
+            raw["date_unixtime"].as_str().is_some(),
+        );
+    }
+
+    Ok(report)
+}
+
+fn validate_instagram(head: &str) -> Report {
+    let trimmed = head.trim_start();
+
+    let (format, objects) = if trimmed.starts_with('[') && !head.contains("\"conversation\"") {
+        (InstagramFormat::MessageArray, sample_objects(trimmed, 1))
+    } else if let Some(objects) = sample_array(head, "conversation") {
+        (InstagramFormat::MagicWords, objects)
+    } else if let Some(pos) = find_array(head, "messages") {
+        let format = if depth_at(head, pos) > 2 {
+            InstagramFormat::Nested
+        } else {
+            InstagramFormat::Current
+        };
+        (format, sample_objects(head, pos))
+    } else {
+        return Report::fail("Instagram", "No messages found");
+    };
+
+    let layout = match format {
+        InstagramFormat::Current => "current format",
+        InstagramFormat::MessageArray => "v1 format (message array)",
+        InstagramFormat::Nested => "v2 format (nested conversation)",
+        InstagramFormat::MagicWords => "v3 format (conversation threads)",
+        InstagramFormat::Unknown => "unknown format",
+    };
+    let variant = format!("Instagram conversation, {}", layout);
+
+    let objects = match objects {
+        Ok(objects) => objects,
+        Err(reason) => return Report::fail(variant, reason),
+    };
+
+    let (sender, text, timestamp) = match format {
+        InstagramFormat::MagicWords => ("sender", "text", "created_at"),
+        _ => ("sender_name", "content", "timestamp_ms"),
+    };
+
+    let mut report = Report {
+        variant,
+        ..Report::default()
+    };
+    for raw in objects.iter().take(SAMPLE_SIZE) {
+        report.check(
+            raw[sender].as_str().is_some(),
+            raw[text].as_str().is_some_and(|t| !t.trim().is_empty()),
+            !raw[timestamp].is_null(),
+        );
+    }
+
+    report
+}
+
+fn validate_discord(head: &str) -> Report {
+    if !head.trim_start().starts_with('{') {
+        let first_line = head.lines().next().unwrap_or_default();
+        let kind = if first_line.contains("AuthorID") {
+            "CSV"
+        } else {
+            "TXT"
+        };
+        return validate_text(Platform::Discord, format!("Discord {} export", kind), head);
+    }
+
+    let variant = "Discord JSON export";
+    let objects = match sample_array(head, "messages") {
+        Some(Ok(objects)) => objects,
+        Some(Err(reason)) => return Report::fail(variant, reason),
+        None => return Report::fail(variant, "No \"messages\" array found"),
+    };
+
+    let mut report = Report {
+        variant: variant.to_string(),
+        ..Report::default()
+    };
+    for raw in objects.iter().take(SAMPLE_SIZE) {
+        let author = &raw["author"];
+        report.check(
+            author["nickname"]
+                .as_str()
+                .or(author["name"].as_str())
+                .is_some(),
+            raw["content"]
+                .as_str()
+                .is_some_and(|t| !t.trim().is_empty())
+                || raw["attachments"].as_array().is_some_and(|a| !a.is_empty()),
+            raw["timestamp"].as_str().is_some(),
+        );
+    }
+
+    report
+}
+
+fn validate_whatsapp(head: &str) -> Report {
+    let lines: Vec<&str> = head.lines().take(SAMPLE_SIZE).collect();
+    let Some(format) = detect_whatsapp_format(&lines) else {
+        return Report::fail("WhatsApp", "No WhatsApp message lines found");
+    };
+
+    let dates = match format {
+        DateFormat::US => "[M/D/YY] dates",
+        DateFormat::EuDotBracketed => "[DD.MM.YY] dates",
+        DateFormat::EuDotNoBracket => "DD.MM.YYYY dates",
+        DateFormat::EuSlash => "DD/MM/YYYY dates",
+        DateFormat::EuSlashBracketed => "[DD/MM/YYYY] dates",
+    };
+    let chat = match whatsapp::variant_of(head) {
+        WhatsAppVariant::Personal => "WhatsApp chat",
+        WhatsAppVariant::Business { .. } => "WhatsApp Business chat",
+    };
+
+    validate_text(Platform::WhatsApp, format!("{}, {}", chat, dates), head)
+}
+
+/// Check a line-based export by parsing its complete lines.
+fn validate_text(platform: Platform, variant: String, head: &str) -> Report {
+    let complete_lines = head.rfind('\n').map_or(head, |end| &head[..end]);
+
+    let messages = match create_parser(platform).parse_str(complete_lines) {
+        Ok(messages) => messages,
+        Err(e) => return Report::fail(variant, e.to_string()),
+    };
+
+    let mut report = Report {
+        variant,
+        ..Report::default()
+    };
+    for msg in messages.iter().take(SAMPLE_SIZE) {
+        report.check(
+            !msg.sender.trim().is_empty(),
+            !msg.content.trim().is_empty(),
+            msg.timestamp.is_some(),
+        );
+    }
+
+    report
+}
+
+/// Objects of the first `"key": [...]` array in `head`.
+fn sample_array(head: &str, key: &str) -> Option<Result<Vec<Value>, String>> {
+    find_array(head, key).map(|pos| sample_objects(head, pos))
+}
+
+/// Complete objects of the first `"key": [...]` array in `text`, with the
+/// byte offset each one starts at.
+///
+/// Stops at the end of the array, at anything that isn't an object, or at an
+/// object cut off by the end of `text`.
+pub fn array_objects<'a>(text: &'a str, key: &str) -> impl Iterator<Item = (usize, &'a str)> {
+    let bytes = text.as_bytes();
+    let mut pos = find_array(text, key).unwrap_or(bytes.len());
+
+    std::iter::from_fn(move || {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b',') {
+            pos += 1;
+        }
+        if bytes.get(pos) != Some(&b'{') {
+            return None;
+        }
+
+        let start = pos;
+        pos = object_end(bytes, start)?;
+        Some((start, &text[start..pos]))
+    })
+}
+
+/// Position just after the `[` of the first `"key": [` in `head`.
+fn find_array(head: &str, key: &str) -> Option<usize> {
+    let quoted = format!("\"{}\"", key);
+    head.match_indices(&quoted).find_map(|(start, _)| {
+        let rest = head[start + quoted.len()..].trim_start();
+        let rest = rest.strip_prefix(':')?.trim_start();
+        rest.starts_with('[').then(|| head.len() - rest.len() + 1)
+    })
+}
+
+/// Nesting depth of objects and arrays at `pos`, counting an opened array.
+fn depth_at(head: &str, pos: usize) -> usize {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for byte in head[..pos].bytes() {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' if in_string => escaped = true,
+            b'"' => in_string = !in_string,
+            b'{' | b'[' if !in_string => depth += 1,
+            b'}' | b']' if !in_string => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    depth
+}
+
+/// Parse the complete objects of the array starting at `start`.
+///
+/// Stops at the end of the array, or at an object cut off by the end of
+/// `head`. Anything else that isn't a JSON object is reported as an error.
+fn sample_objects(head: &str, start: usize) -> Result<Vec<Value>, String> {
+    let bytes = head.as_bytes();
+    let mut objects = Vec::new();
+    let mut pos = start;
+
+    while objects.len() < SAMPLE_SIZE {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b',') {
+            pos += 1;
+        }
+
+        match bytes.get(pos) {
+            None | Some(b']') => break,
+            Some(b'{') => {}
+            Some(_) => return Err(format!("Unexpected data at byte {} of the array", pos)),
+        }
+
+        let Some(end) = object_end(bytes, pos) else {
+            break;
+        };
+
+        let object = serde_json::from_str(&head[pos..end])
+            .map_err(|e| format!("Message at byte {} is not valid JSON: {}", pos, e))?;
+        objects.push(object);
+        pos = end;
+    }
+
+    Ok(objects)
+}
+
+/// End of the JSON object starting at `start`, if it is complete.
+fn object_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, &byte) in bytes.iter().enumerate().skip(start) {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' if in_string => escaped = true,
+            b'"' => in_string = !in_string,
+            b'{' if !in_string => depth += 1,
+            b'}' if !in_string => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
@@ -0,0 +1,282 @@
+//! Non-fatal issues found while parsing an export.
+//!
+//! The `chatpack` parsers either succeed or fail; anything they skip or read
+//! loosely goes unreported. These checks look at the parsed messages and the
+//! raw export for such issues, so they can be shown without aborting (or,
+//! with `--fail-on-warning`, abort after all).
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chatpack::prelude::*;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+
+/// Telegram text entity types the exporter is known to write
+const TELEGRAM_ENTITY_TYPES: [&str; 20] = [
+    "plain",
+    "bold",
+    "italic",
+    "underline",
+    "strikethrough",
+    "spoiler",
+    "code",
+    "pre",
+    "link",
+    "text_link",
+    "mention",
+    "mention_name",
+    "hashtag",
+    "cashtag",
+    "bot_command",
+    "email",
+    "phone",
+    "bank_card",
+    "custom_emoji",
+    "blockquote",
+];
+
+/// A parse issue that doesn't stop processing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseWarning {
+    pub kind: ParseWarningKind,
+    /// Where the issue was found, e.g. the message ID
+    pub context: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarningKind {
+    /// Telegram text entity of a type the exporter isn't known to write;
+    /// its text is kept, its meaning may not be
+    UnknownEntity(String),
+    /// Discord CSV reaction not in the `emoji (count)` form
+    MalformedReaction(String),
+    /// Message dated after the time of processing
+    FutureTimestamp(DateTime<Utc>),
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ParseWarningKind::UnknownEntity(kind) => {
+                write!(f, "unknown text entity type \"{}\"", kind)?
+            }
+            ParseWarningKind::MalformedReaction(reaction) => {
+                write!(f, "malformed reaction \"{}\"", reaction)?
+            }
+            ParseWarningKind::FutureTimestamp(ts) => write!(
+                f,
+                "timestamp in the future ({})",
+                ts.format("%Y-%m-%d %H:%M:%S")
+            )?,
+        }
+        write!(f, " in {}", self.context)
+    }
+}
+
+/// Collect the warnings for an export and the messages parsed from it.
+pub fn collect(platform: Platform, path: &Path, messages: &[Message]) -> Result<Vec<ParseWarning>> {
+    let mut warnings = Vec::new();
+
+    if path.is_file() {
+        let is = |ext: &str| path.extension().is_some_and(|e| e == ext);
+        match platform {
+            Platform::Telegram if is("json") => {
+                let file = File::open(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                telegram_entities(BufReader::new(file), &mut warnings)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+            }
+            Platform::Discord if is("csv") => discord_reactions(path, &mut warnings)?,
+            _ => {}
+        }
+    }
+
+    let now = Utc::now();
+    for msg in messages {
+        if let Some(ts) = msg.timestamp.filter(|ts| *ts > now) {
+            warnings.push(ParseWarning {
+                kind: ParseWarningKind::FutureTimestamp(ts),
+                context: describe(msg),
+            });
+        }
+    }
+
+    Ok(warnings)
+}
+
+fn describe(msg: &Message) -> String {
+    match msg.id {
+        Some(id) => format!("message {} from {}", id, msg.sender),
+        None => format!("message from {}", msg.sender),
+    }
+}
+
+/// The parts of a raw Telegram message the entity check reads
+#[derive(Deserialize)]
+struct RawMessage {
+    #[serde(default)]
+    id: Value,
+    #[serde(default)]
+    text_entities: Value,
+}
+
+/// Check the text entity types of a Telegram export.
+///
+/// The export is deserialized as it is read, one message at a time, so the
+/// check holds no more than a single message in memory.
+fn telegram_entities<R: Read>(reader: R, warnings: &mut Vec<ParseWarning>) -> Result<()> {
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    ExportVisitor(warnings).deserialize(&mut deserializer)?;
+    Ok(())
+}
+
+/// Visits the top-level object of the export, skipping all but `messages`
+struct ExportVisitor<'a>(&'a mut Vec<ParseWarning>);
+
+impl<'de> DeserializeSeed<'de> for ExportVisitor<'_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ExportVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a Telegram export object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "messages" {
+                map.next_value_seed(MessagesVisitor(&mut *self.0))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Visits the `messages` array, checking each message as it is read
+struct MessagesVisitor<'a>(&'a mut Vec<ParseWarning>);
+
+impl<'de> DeserializeSeed<'de> for MessagesVisitor<'_> {
+    type Value = ();
+
+    fn deserialize<D: de::Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for MessagesVisitor<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an array of messages")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(raw) = seq.next_element::<RawMessage>()? {
+            let entities = raw.text_entities.as_array().map_or(&[][..], Vec::as_slice);
+
+            for kind in entities.iter().filter_map(|entity| entity["type"].as_str()) {
+                if !TELEGRAM_ENTITY_TYPES.contains(&kind) {
+                    self.0.push(ParseWarning {
+                        kind: ParseWarningKind::UnknownEntity(kind.to_string()),
+                        context: format!("message {}", raw.id),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check the `Reactions` column of a DiscordChatExporter CSV export.
+fn discord_reactions(path: &Path, warnings: &mut Vec<ParseWarning>) -> Result<()> {
+    let mut reader = csv::Reader::from_path(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let Some(column) = reader.headers()?.iter().position(|h| h == "Reactions") else {
+        return Ok(());
+    };
+
+    for (row, record) in reader.records().enumerate() {
+        let record = record?;
+        let reactions = record.get(column).unwrap_or_default();
+        for reaction in reactions
+            .split(',')
+            .map(str::trim)
+            .filter(|r| !r.is_empty())
+        {
+            if !is_reaction(reaction) {
+                warnings.push(ParseWarning {
+                    kind: ParseWarningKind::MalformedReaction(reaction.to_string()),
+                    // Row 1 is the header
+                    context: format!("row {}", row + 2),
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether a reaction reads `emoji (count)`
+fn is_reaction(reaction: &str) -> bool {
+    reaction
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once(" ("))
+        .is_some_and(|(emoji, count)| {
+            !emoji.trim().is_empty()
+                && !count.is_empty()
+                && count.bytes().all(|b| b.is_ascii_digit())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity_warnings(json: &str) -> Vec<ParseWarning> {
+        let mut warnings = Vec::new();
+        telegram_entities(json.as_bytes(), &mut warnings).unwrap();
+        warnings
+    }
+
+    #[test]
+    fn test_unknown_entity_found_while_streaming() {
+        let warnings = entity_warnings(
+            r#"{
+                "name": "Chat",
+                "messages": [
+                    {"id": 1, "text": "Hi", "text_entities": [{"type": "plain", "text": "Hi"}]},
+                    {"id": 2, "text_entities": [{"type": "sparkle", "text": "*"}]},
+                    {"id": 3, "type": "service"}
+                ],
+                "id": 42
+            }"#,
+        );
+
+        assert_eq!(
+            warnings,
+            [ParseWarning {
+                kind: ParseWarningKind::UnknownEntity("sparkle".to_string()),
+                context: "message 2".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_export_without_messages() {
+        assert!(entity_warnings(r#"{"name": "Chat"}"#).is_empty());
+    }
+}
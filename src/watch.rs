@@ -0,0 +1,86 @@
+//! Watch mode: re-run the pipeline whenever the input file changes.
+
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+
+enum WatchEvent {
+    Changed,
+    Stop,
+}
+
+/// Run `process` once, then again after every change to `path` until Ctrl-C.
+///
+/// Changes are debounced: processing starts only after `debounce` has passed
+/// without further events, so a file that is still being written is handled
+/// once. Errors during re-processing are reported and watching continues.
+pub fn watch_file(
+    path: &Path,
+    debounce: Duration,
+    quiet: bool,
+    mut process: impl FnMut() -> Result<()>,
+) -> Result<()> {
+    process()?;
+
+    let (tx, rx) = mpsc::channel();
+
+    let stop_tx = tx.clone();
+    ctrlc::set_handler(move || {
+        let _ = stop_tx.send(WatchEvent::Stop);
+    })
+    .context("Failed to install Ctrl-C handler")?;
+
+    let target = path.canonicalize()?;
+    let file_name = target.file_name().map(ToOwned::to_owned);
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else { return };
+        // Exporters often replace the file, so watch the directory and match by name
+        let relevant = event.kind.is_modify() || event.kind.is_create();
+        if relevant
+            && event
+                .paths
+                .iter()
+                .any(|p| p.file_name() == file_name.as_deref())
+        {
+            let _ = tx.send(WatchEvent::Changed);
+        }
+    })
+    .context("Failed to start file watcher")?;
+
+    let dir = target.parent().unwrap_or(Path::new("."));
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", path.display()))?;
+
+    if !quiet {
+        eprintln!("👀 Watching {} (Ctrl-C to stop)", path.display());
+    }
+
+    while let Ok(event) = rx.recv() {
+        if let WatchEvent::Stop = event {
+            break;
+        }
+
+        // Wait for the writes to settle
+        loop {
+            match rx.recv_timeout(debounce) {
+                Ok(WatchEvent::Changed) => continue,
+                Ok(WatchEvent::Stop) => return Ok(()),
+                Err(_) => break,
+            }
+        }
+
+        if !quiet {
+            eprintln!("⟳ File changed, reprocessing...");
+        }
+
+        if let Err(e) = process() {
+            eprintln!("Error: {:?}", e);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,124 @@
+//! WhatsApp Business exports.
+//!
+//! Chats with a business account look like personal chats, plus notices such
+//! as `Bakery is a business account.` Those notices identify the business, and
+//! its greeting and away messages (sent automatically, word for word) can then
+//! be told apart from the rest of the conversation.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chatpack::prelude::*;
+
+/// Lines only found in chats with a business account
+const BUSINESS_NOTICES: [&str; 3] = [
+    "This business uses a secure service from Meta to manage this chat",
+    "This chat is with a business account",
+    "This business works with other companies to manage this chat",
+];
+
+/// Suffix of the notice naming the business, e.g. `Bakery is a business account.`
+const BUSINESS_ACCOUNT_SUFFIX: &str = " is a business account";
+
+/// Kind of WhatsApp export
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WhatsAppVariant {
+    Personal,
+    /// Chat with a business account, named if the export says so
+    Business {
+        name: Option<String>,
+    },
+}
+
+/// Kind of a parsed message
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MessageKind {
+    Regular,
+    /// Greeting or away message sent automatically by a business account
+    AutoReply,
+}
+
+/// Detect the export variant from its business-account notices.
+pub fn detect_variant(path: &Path) -> Result<WhatsAppVariant> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(variant_of(&content))
+}
+
+/// Detect the variant of already loaded export text.
+pub fn variant_of(content: &str) -> WhatsAppVariant {
+    let mut is_business = false;
+    for line in content.lines() {
+        let notice = notice_text(line);
+        if let Some(name) = notice
+            .strip_suffix('.')
+            .unwrap_or(notice)
+            .strip_suffix(BUSINESS_ACCOUNT_SUFFIX)
+        {
+            return WhatsAppVariant::Business {
+                name: Some(name.trim().to_string()),
+            };
+        }
+        is_business |= is_business_notice(line);
+    }
+
+    if is_business {
+        WhatsAppVariant::Business { name: None }
+    } else {
+        WhatsAppVariant::Personal
+    }
+}
+
+/// Text after the `date, time - ` (or `[date, time] `) prefix of a line.
+fn notice_text(line: &str) -> &str {
+    let line = line.trim().trim_start_matches('\u{200e}');
+    line.split_once(" - ")
+        .or_else(|| line.split_once("] "))
+        .map_or(line, |(_, text)| text)
+        .trim_start_matches('\u{200e}')
+}
+
+fn is_business_notice(line: &str) -> bool {
+    let text = notice_text(line);
+    BUSINESS_NOTICES
+        .iter()
+        .any(|notice| text.starts_with(notice))
+        || text
+            .trim_end_matches('.')
+            .ends_with(BUSINESS_ACCOUNT_SUFFIX)
+}
+
+/// Drop business notices that the parser folded into the previous message.
+pub fn strip_business_notices(messages: &mut [Message]) {
+    for msg in messages {
+        if msg.content.lines().any(is_business_notice) {
+            msg.content = msg
+                .content
+                .lines()
+                .filter(|line| !is_business_notice(line))
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+    }
+}
+
+/// Classify messages; auto-replies are texts the business sent more than once.
+pub fn classify(messages: &[Message], business: &str) -> Vec<MessageKind> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for msg in messages.iter().filter(|msg| msg.sender == business) {
+        *counts.entry(msg.content.as_str()).or_default() += 1;
+    }
+
+    messages
+        .iter()
+        .map(|msg| {
+            if msg.sender == business && counts[msg.content.as_str()] > 1 {
+                MessageKind::AutoReply
+            } else {
+                MessageKind::Regular
+            }
+        })
+        .collect()
+}
@@ -209,6 +209,39 @@ mod telegram {
         let content = read_output(&output);
         assert!(content.contains("id"), "Should contain message IDs");
     }
+
+    #[test]
+    fn test_ids_on_every_message() {
+        // Both the library parser and the raw reader (media descriptions)
+        for (fixture, extra) in [
+            ("telegram_export.json", None),
+            ("telegram_media.json", Some("--include-media-description")),
+        ] {
+            let input = fixtures_dir().join(fixture);
+            let output = temp_output(&format!("tg_every_id_{fixture}"));
+
+            let mut args = vec![
+                "tg",
+                input.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+                "-f",
+                "json",
+                "--ids",
+                "--no-merge",
+                "-q",
+            ];
+            args.extend(extra);
+            assert_success(&run_chatpack(&args));
+
+            let records: Vec<serde_json::Value> =
+                serde_json::from_str(&read_output(&output)).unwrap();
+            assert!(!records.is_empty());
+            for record in &records {
+                assert!(record["id"].is_u64(), "missing id in {fixture}: {record}");
+            }
+        }
+    }
 }
 
 mod whatsapp {
@@ -340,6 +373,71 @@ mod discord {
 
         assert_success(&result);
     }
+
+    #[test]
+    fn test_exporter_csv_input() {
+        let input = fixtures_dir().join("discord_export.csv");
+        let output = temp_output("dc_from_csv.csv");
+
+        // Streaming is the default; CSV input falls back to the full parser
+        let result = run_chatpack(&[
+            "dc",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&output);
+        assert!(content.starts_with("Sender;Content\nalice;Hello everyone!\n"));
+        assert!(content.contains("bob;Hi Alice, how's it going?"));
+        assert!(content.contains("[Attachment: screenshot.png]"));
+        // Bob's empty last message is skipped
+        assert_eq!(content.matches("bob;").count(), 1);
+    }
+
+    #[test]
+    fn test_exporter_html_input() {
+        let input = fixtures_dir().join("discord_export.html");
+        let output = temp_output("dc_from_html.jsonl");
+
+        let result = run_chatpack(&[
+            "dc",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-t",
+            "-r",
+            "--ids",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&output);
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["sender"], "alice");
+        assert_eq!(lines[0]["content"], "Hello everyone!");
+        assert_eq!(lines[0]["timestamp"], "2024-01-15T10:30:00Z");
+        // Follow-ups without a header belong to the previous author
+        assert_eq!(lines[1]["sender"], "alice");
+        assert_eq!(lines[1]["id"], 1002);
+        assert_eq!(lines[2]["sender"], "bob");
+        assert_eq!(lines[2]["reply_to"], 1002);
+        assert_eq!(
+            lines[2]["content"],
+            "Here, with a screenshot\n[Attachment: screenshot.png]"
+        );
+    }
 }
 
 // ============================================================================
@@ -481,6 +579,97 @@ mod filtering {
         // Проверяем, что нет Боба
         assert!(!content.contains("Bob"));
     }
+
+    #[test]
+    fn test_filter_by_id_range() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_filter_ids.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--after-id",
+            "2",
+            "--before-id",
+            "6",
+            "--ids",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&output);
+        let ids: Vec<&str> = content
+            .lines()
+            .skip(1)
+            .map(|line| line.split(';').next().unwrap())
+            .collect();
+        assert_eq!(ids, ["3", "4", "5"]);
+    }
+
+    #[test]
+    fn test_after_id_only() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_filter_after_id.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--after-id",
+            "5",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&output);
+        assert!(content.contains("This is a message from February"));
+        assert!(!content.contains("No, what happened?"));
+    }
+
+    #[test]
+    fn test_filter_while_streaming_matches_full_parse() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let streamed = temp_output("wa_filter_streamed.csv");
+        let loaded = temp_output("wa_filter_loaded.csv");
+
+        for (output, extra) in [(&streamed, None), (&loaded, Some("--no-streaming"))] {
+            let mut args = vec![
+                "wa",
+                input.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+                "--from",
+                "Alice",
+            ];
+            args.extend(extra);
+
+            let result = run_chatpack(&args);
+            assert_success(&result);
+            // Messages dropped while streaming still count as parsed
+            assert!(String::from_utf8_lossy(&result.stderr).contains("Parsed:   7 messages"));
+        }
+
+        assert_eq!(read_output(&streamed), read_output(&loaded));
+    }
+
+    #[test]
+    fn test_empty_id_range_rejected() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "--after-id",
+            "6",
+            "--before-id",
+            "6",
+        ]);
+
+        assert!(!result.status.success());
+    }
 }
 
 // ============================================================================
@@ -531,6 +720,39 @@ mod merging {
             unmerged_lines
         );
     }
+
+    #[test]
+    fn test_merged_group_keeps_latest_edit() {
+        let input = temp_output("tg_merge_edited.json");
+        fs::write(
+            &input,
+            r#"{"messages": [
+                {"id": 1, "type": "message", "date_unixtime": "1705314600", "from": "Alice", "text": "First"},
+                {"id": 2, "type": "message", "date_unixtime": "1705314660", "from": "Alice", "text": "Second", "edited_unixtime": "1705318200"},
+                {"id": 3, "type": "message", "date_unixtime": "1705314720", "from": "Bob", "text": "Reply"}
+            ]}"#,
+        )
+        .unwrap();
+        let output = temp_output("tg_merge_edited.jsonl");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--ids",
+            "--edited",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&output);
+        let first: serde_json::Value =
+            serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(first["id"], 1);
+        assert_eq!(first["content"], "First\nSecond");
+        assert_eq!(first["edited"], "2024-01-15T11:30:00Z");
+    }
 }
 
 // ============================================================================
@@ -737,6 +959,78 @@ mod cli_interface {
         assert!(stdout.contains("chatpack"));
     }
 
+    #[test]
+    fn test_max_retries() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_max_retries.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--max-retries",
+            "3",
+            "-q",
+        ]);
+        assert_success(&result);
+        assert!(read_output(&output).contains("Alice"));
+
+        // Only the streaming parser retries
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "--max-retries",
+            "3",
+            "--no-streaming",
+        ]);
+        assert!(!result.status.success());
+    }
+
+    #[test]
+    fn test_benchmark_flag() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_benchmark.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--benchmark",
+        ]);
+
+        assert_success(&result);
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        for line in ["parse", "filter", "merge", "write", "total", "Throughput"] {
+            assert!(stderr.contains(line), "missing {line} in:\n{stderr}");
+        }
+
+        // Hidden from --help
+        let help = run_chatpack(&["--help"]);
+        assert!(!String::from_utf8_lossy(&help.stdout).contains("--benchmark"));
+    }
+
+    #[test]
+    fn test_quiet_progress_prints_one_summary_line() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("wa_quiet_progress.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--progress",
+            "-Q",
+        ]);
+
+        assert_success(&result);
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert_eq!(stderr.lines().count(), 1, "{stderr}");
+        assert!(stderr.starts_with("✅ Done! Parsed: 7 messages"));
+    }
+
     #[test]
     fn test_quiet_mode() {
         let input = fixtures_dir().join("telegram_export.json");
@@ -798,6 +1092,66 @@ mod cli_interface {
         // We verify command runs successfully; content check would depend on fixture having replies/edits
         assert!(output.exists());
     }
+
+    #[test]
+    fn test_format_inferred_from_extension() {
+        let input = fixtures_dir().join("telegram_export.json");
+
+        for (name, first_char) in [
+            ("infer.json", '['),
+            ("infer.jsonl", '{'),
+            ("infer.NDJSON", '{'),
+            ("infer.csv", 'S'),
+        ] {
+            let output = temp_output(name);
+            let result = run_chatpack(&[
+                "tg",
+                input.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+                "-q",
+            ]);
+
+            assert_success(&result);
+            assert!(read_output(&output).starts_with(first_char), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_explicit_format_overrides_extension() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("override.json");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "csv",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        assert!(read_output(&output).starts_with("Sender;Content"));
+    }
+
+    #[test]
+    fn test_unknown_extension_falls_back_to_csv() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("fallback.out");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+
+        assert_success(&result);
+        assert!(String::from_utf8_lossy(&result.stderr).contains("writing CSV"));
+        assert!(read_output(&output).starts_with("Sender;Content"));
+    }
 }
 
 // ============================================================================
@@ -954,3 +1308,5579 @@ mod metadata {
         }
     }
 }
+
+// ============================================================================
+// Mentions Tests
+// ============================================================================
+
+mod mentions {
+    use super::*;
+
+    #[test]
+    fn test_telegram_mentions_json() {
+        let input = fixtures_dir().join("telegram_mentions.json");
+        let output = temp_output("tg_mentions.json");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "json",
+            "--no-merge",
+            "--include-mentions",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let parsed: serde_json::Value = serde_json::from_str(&read_output(&output)).unwrap();
+        let arr = parsed.as_array().unwrap();
+        assert_eq!(arr.len(), 3);
+        assert_eq!(arr[0]["mentions"], serde_json::json!(["bob"]));
+        assert_eq!(arr[1]["mentions"], serde_json::json!(["Carol"]));
+        assert_eq!(arr[2]["mentions"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_mentions_combined_when_merged() {
+        let input = fixtures_dir().join("telegram_mentions.json");
+        let output = temp_output("tg_mentions_merged.jsonl");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--include-mentions",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        let first: serde_json::Value =
+            serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(first["sender"], "Alice");
+        assert_eq!(first["mentions"], serde_json::json!(["bob", "Carol"]));
+    }
+
+    #[test]
+    fn test_discord_mentions_csv() {
+        let input = fixtures_dir().join("discord_export.json");
+        let output = temp_output("dc_mentions.csv");
+
+        let result = run_chatpack(&[
+            "dc",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-streaming",
+            "--include-mentions",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.starts_with("Sender;Content;Mentions"));
+        assert!(
+            content.contains(";Alice\n"),
+            "Bob's reply should list Alice as a mention"
+        );
+    }
+
+    #[test]
+    fn test_mentions_absent_by_default() {
+        let input = fixtures_dir().join("telegram_mentions.json");
+        let output = temp_output("tg_no_mentions.json");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "json",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        assert!(!read_output(&output).contains("mentions"));
+    }
+}
+
+// ============================================================================
+// CSV Dialect Tests
+// ============================================================================
+
+mod csv_dialect {
+    use super::*;
+
+    #[test]
+    fn test_no_csv_header() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_no_header.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-csv-header",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(!content.contains("Sender;Content"));
+        assert!(content.starts_with("Alice;"));
+    }
+
+    #[test]
+    fn test_csv_header_overrides_no_csv_header() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_header_override.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-csv-header",
+            "--csv-header",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        assert!(read_output(&output).starts_with("Sender;Content"));
+    }
+
+    #[test]
+    fn test_custom_quote_and_escape() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_quote_escape.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--csv-quote-char",
+            "'",
+            "--csv-escape-char",
+            "\\",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.contains(r"'Hi Alice! I\'m doing great, thanks for asking.'"));
+    }
+
+    #[test]
+    fn test_invalid_quote_char() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_bad_quote.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--csv-quote-char",
+            "ab",
+        ]);
+
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// JSON Envelope Tests
+// ============================================================================
+
+mod json_envelope {
+    use super::*;
+
+    #[test]
+    fn test_json_envelope() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_envelope.json");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "json",
+            "--json-envelope",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let parsed: serde_json::Value = serde_json::from_str(&read_output(&output)).unwrap();
+        assert_eq!(parsed["platform"], "telegram");
+        assert_eq!(parsed["source_file"], "telegram_export.json");
+        assert!(parsed["chatpack_version"].is_string());
+        assert!(parsed["exported_at"].is_string());
+
+        let messages = parsed["messages"].as_array().unwrap();
+        assert_eq!(parsed["message_count"], messages.len());
+    }
+
+    #[test]
+    fn test_jsonl_envelope_metadata_line() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_envelope.jsonl");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--json-envelope",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        let meta: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(meta["type"], "metadata");
+        assert_eq!(meta["message_count"], lines.len() - 1);
+
+        for line in &lines[1..] {
+            let msg: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert!(msg.get("type").is_none());
+        }
+    }
+}
+
+// ============================================================================
+// Split By Sender Tests
+// ============================================================================
+
+mod split_by_sender {
+    use super::*;
+
+    #[test]
+    fn test_split_writes_one_file_per_sender() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let dir = temp_output("split_tg");
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("chat.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--split-by-sender",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let alice = read_output(&dir.join("chat_alice.csv"));
+        let bob = read_output(&dir.join("chat_bob.csv"));
+        assert!(!alice.contains("Bob;"));
+        assert!(!bob.contains("Alice;"));
+
+        // Unmerged: every message from Alice keeps its own row
+        assert_eq!(alice.lines().count(), 5);
+        assert!(!output.exists(), "Combined output should not be written");
+    }
+
+    #[test]
+    fn test_split_slugifies_sender_names() {
+        let input = temp_output("split_names.txt");
+        fs::write(
+            &input,
+            "15/01/2024, 10:30 - Mary-Jane O'Neil: Hi there\n\
+             15/01/2024, 10:31 - Bob: Hello\n",
+        )
+        .unwrap();
+        let dir = temp_output("split_wa");
+        fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.jsonl");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--split-by-sender",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&dir.join("out_mary_jane_o_neil.jsonl"));
+        assert!(content.contains("Hi there"));
+        assert!(dir.join("out_bob.jsonl").exists());
+    }
+}
+
+// ============================================================================
+// Context Tests
+// ============================================================================
+
+mod context {
+    use super::*;
+
+    #[test]
+    fn test_include_context_adds_neighbours() {
+        let input = fixtures_dir().join("telegram_mentions.json");
+        let output = temp_output("tg_context.jsonl");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--from",
+            "Bob",
+            "--include-context",
+            "1",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("Also pinging"));
+        assert!(lines[1].contains("I'm here!"));
+    }
+
+    #[test]
+    fn test_overlapping_context_is_deduplicated() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_context_overlap.jsonl");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--from",
+            "Bob",
+            "--include-context",
+            "2",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        // Bob's three messages with two neighbours each cover the whole chat exactly once
+        let content = read_output(&output);
+        assert_eq!(content.lines().count(), 7);
+        assert_eq!(content.matches("Hello! How are you?").count(), 1);
+    }
+}
+
+// ============================================================================
+// Conversation ID Tests
+// ============================================================================
+
+mod conversation_id {
+    use super::*;
+
+    #[test]
+    fn test_include_conversation_id_json() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_conversation.json");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "json",
+            "--include-conversation-id",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let parsed: serde_json::Value = serde_json::from_str(&read_output(&output)).unwrap();
+        for msg in parsed.as_array().unwrap() {
+            assert_eq!(msg["conversation_id"], "Test Chat");
+        }
+    }
+
+    #[test]
+    fn test_discord_conversation_is_channel_name() {
+        let input = fixtures_dir().join("discord_export.json");
+        let output = temp_output("dc_conversation.csv");
+
+        let result = run_chatpack(&[
+            "dc",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-streaming",
+            "--include-conversation-id",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.starts_with("Sender;Content;ConversationID"));
+        assert!(content.contains("Alice;Hello! How are you?;general"));
+    }
+
+    #[test]
+    fn test_only_conversation_filter() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let matching = temp_output("tg_only_conversation.jsonl");
+        let other = temp_output("tg_other_conversation.jsonl");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            matching.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--only-conversation",
+            "Test Chat",
+            "-q",
+        ]);
+        assert_success(&result);
+        assert!(read_output(&matching).lines().count() > 0);
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            other.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--only-conversation",
+            "Another Chat",
+            "-q",
+        ]);
+        assert_success(&result);
+        assert!(read_output(&other).is_empty());
+    }
+}
+
+// ============================================================================
+// Output Encoding Tests
+// ============================================================================
+
+mod output_encoding {
+    use super::*;
+
+    /// Decode output the way Excel does: sniff the BOM, then decode accordingly
+    fn decode_like_excel(bytes: &[u8]) -> String {
+        let utf16 = |rest: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+            let units: Vec<u16> = rest
+                .chunks_exact(2)
+                .map(|pair| from_bytes([pair[0], pair[1]]))
+                .collect();
+            String::from_utf16(&units).expect("valid UTF-16")
+        };
+
+        match bytes {
+            [0xEF, 0xBB, 0xBF, rest @ ..] => String::from_utf8(rest.to_vec()).expect("valid UTF-8"),
+            [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+            [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+            _ => panic!("Excel needs a BOM to detect the encoding"),
+        }
+    }
+
+    fn run_with_encoding(encoding: &str, name: &str) -> Vec<u8> {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output(name);
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--output-encoding",
+            encoding,
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+        fs::read(&output).unwrap()
+    }
+
+    #[test]
+    fn test_utf8_bom() {
+        let bytes = run_with_encoding("utf-8-bom", "tg_utf8_bom.csv");
+        assert_eq!(&bytes[..3], &[0xEF, 0xBB, 0xBF]);
+
+        let text = decode_like_excel(&bytes);
+        assert!(text.starts_with("Sender;Content\n"));
+        assert!(text.contains("Alice;Hello! How are you?"));
+    }
+
+    #[test]
+    fn test_utf16_le_and_be() {
+        let le = run_with_encoding("utf-16-le", "tg_utf16le.csv");
+        let be = run_with_encoding("utf-16-be", "tg_utf16be.csv");
+        assert_eq!(&le[..2], &[0xFF, 0xFE]);
+        assert_eq!(&be[..2], &[0xFE, 0xFF]);
+
+        let plain = run_with_encoding("utf-8", "tg_utf8_plain.csv");
+        let plain = String::from_utf8(plain).unwrap();
+        assert_eq!(decode_like_excel(&le), plain);
+        assert_eq!(decode_like_excel(&be), plain);
+    }
+
+    #[test]
+    fn test_default_has_no_bom() {
+        let bytes = run_with_encoding("utf-8", "tg_utf8_default.csv");
+        assert!(bytes.starts_with(b"Sender;Content"));
+    }
+}
+
+// ============================================================================
+// Watch Mode Tests
+// ============================================================================
+
+mod watch {
+    use super::*;
+    use std::process::Stdio;
+    use std::time::{Duration, Instant};
+
+    /// Poll `path` until `predicate` holds or the timeout expires
+    fn wait_for(path: &PathBuf, predicate: impl Fn(&str) -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline {
+            if fs::read_to_string(path).is_ok_and(|c| predicate(&c)) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    #[test]
+    fn test_watch_reprocesses_on_change() {
+        let dir = temp_output("watch");
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("chat.txt");
+        let output = dir.join("chat.csv");
+        let _ = fs::remove_file(&output);
+        fs::write(&input, "15/01/2024, 10:30 - Alice: First message\n").unwrap();
+
+        let mut child = Command::new(binary_path())
+            .args([
+                "wa",
+                input.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+                "--watch",
+                "--debounce-ms",
+                "50",
+                "-q",
+            ])
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to spawn chatpack");
+
+        let initial = wait_for(&output, |c| c.contains("First message"));
+
+        // Give the watcher a moment to start before changing the file
+        std::thread::sleep(Duration::from_millis(300));
+        fs::write(
+            &input,
+            "15/01/2024, 10:30 - Alice: First message\n\
+             15/01/2024, 10:31 - Bob: Second message\n",
+        )
+        .unwrap();
+        let updated = wait_for(&output, |c| c.contains("Second message"));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(initial, "Initial run should write output");
+        assert!(
+            updated,
+            "Output should be rewritten after the input changes"
+        );
+    }
+
+    #[test]
+    fn test_debounce_requires_watch() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let result = run_chatpack(&["tg", input.to_str().unwrap(), "--debounce-ms", "100"]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Media Description Tests
+// ============================================================================
+
+mod media_description {
+    use super::*;
+
+    #[test]
+    fn test_telegram_media_placeholders() {
+        let input = fixtures_dir().join("telegram_media.json");
+        let output = temp_output("tg_media.json");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "json",
+            "--include-media-description",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let parsed: serde_json::Value = serde_json::from_str(&read_output(&output)).unwrap();
+        let contents: Vec<&str> = parsed
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|msg| msg["content"].as_str().unwrap())
+            .collect();
+
+        assert_eq!(
+            contents,
+            [
+                "[Photo: photo_1@01-03-2024_10-00-00.jpg]\nLook at this",
+                "[Sticker: 😂]",
+                "[Audio: 12s]",
+                "[Video: 30s]",
+                "[Document: report.pdf]",
+                "Thanks!",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_telegram_media_dropped_by_default() {
+        let input = fixtures_dir().join("telegram_media.json");
+        let output = temp_output("tg_media_default.jsonl");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert_eq!(content.lines().count(), 2);
+        assert!(!content.contains("[Sticker"));
+    }
+
+    #[test]
+    fn test_whatsapp_media_placeholders() {
+        let input = temp_output("wa_media.txt");
+        let output = temp_output("wa_media.csv");
+        fs::write(
+            &input,
+            "15/01/2024, 10:30 - Alice: <Media omitted>\n\
+             15/01/2024, 10:31 - Bob: IMG-20240115-WA0001.jpg (file attached)\n\
+             15/01/2024, 10:32 - Alice: Nice\n",
+        )
+        .unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--include-media-description",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.contains("Alice;[Media]"));
+        assert!(content.contains("Bob;[Photo: IMG-20240115-WA0001.jpg]"));
+        assert!(content.contains("Alice;Nice"));
+    }
+}
+
+// ============================================================================
+// Unicode Normalization Tests
+// ============================================================================
+
+mod normalize_unicode {
+    use super::*;
+
+    // "José" written with a combining acute accent, as iOS exports it
+    const DECOMPOSED: &str = "Jose\u{301}";
+    const COMPOSED: &str = "Jos\u{e9}";
+
+    fn write_input(name: &str) -> PathBuf {
+        let input = temp_output(name);
+        fs::write(
+            &input,
+            format!(
+                "15/01/2024, 10:30 - {DECOMPOSED}: Cafe\u{301} at 5?\n\
+                 15/01/2024, 10:31 - Alice: Sure\n"
+            ),
+        )
+        .unwrap();
+        input
+    }
+
+    #[test]
+    fn test_normalize_nfc_matches_sender_filter() {
+        let input = write_input("wa_nfd.txt");
+        let output = temp_output("wa_nfc.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--normalize-unicode",
+            "NFC",
+            "--from",
+            COMPOSED,
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.contains(&format!("{COMPOSED};Caf\u{e9} at 5?")));
+        assert!(!content.contains('\u{301}'));
+    }
+
+    #[test]
+    fn test_without_normalization_forms_differ() {
+        let input = write_input("wa_nfd_raw.txt");
+        let output = temp_output("wa_nfd_raw.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--from",
+            COMPOSED,
+            "-q",
+        ]);
+
+        assert_success(&result);
+        assert_eq!(read_output(&output).lines().count(), 1);
+    }
+}
+
+// ============================================================================
+// Lowercase Senders Tests
+// ============================================================================
+
+mod lowercase_senders {
+    use super::*;
+
+    #[test]
+    fn test_lowercase_senders_merges_spellings() {
+        let input = temp_output("wa_mixed_case.txt");
+        let output = temp_output("wa_lowercase.csv");
+        fs::write(
+            &input,
+            "15/01/2024, 10:30 - Alice: Hi\n\
+             15/01/2024, 10:31 - Bob: Hello\n\
+             15/01/2024, 10:32 - alice: Typed on my phone\n",
+        )
+        .unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--lowercase-senders",
+            "--from",
+            "ALICE",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.contains("alice;\"Hi\nTyped on my phone\""));
+        assert!(!content.contains("Alice"));
+        assert!(!content.contains("bob"));
+    }
+}
+
+// ============================================================================
+// Truncate Text Tests
+// ============================================================================
+
+mod truncate_text {
+    use super::*;
+
+    #[test]
+    fn test_truncate_at_word_boundary() {
+        let input = temp_output("wa_long.txt");
+        let output = temp_output("wa_truncated.jsonl");
+        fs::write(
+            &input,
+            "15/01/2024, 10:30 - Alice: The quick brown fox jumps over the lazy dog\n\
+             15/01/2024, 10:31 - Bob: Short\n",
+        )
+        .unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--truncate-text",
+            "18",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        assert!(lines[0].contains(r#""content":"The quick brown…""#));
+        assert!(lines[1].contains(r#""content":"Short""#));
+    }
+
+    #[test]
+    fn test_truncate_counts_characters_not_bytes() {
+        let input = temp_output("wa_long_unicode.txt");
+        let output = temp_output("wa_truncated_unicode.csv");
+        fs::write(&input, "15/01/2024, 10:30 - Alice: Приветствую\n").unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--truncate-text",
+            "6",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        assert!(read_output(&output).contains("Alice;Привет…"));
+    }
+}
+
+// ============================================================================
+// Sender Formatting Tests
+// ============================================================================
+
+mod sender_formatting {
+    use super::*;
+
+    #[test]
+    fn test_omit_sender_csv() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_omit_sender.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--omit-sender",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.starts_with("Content\nHello! How are you?\n"));
+        assert!(!content.contains(";"));
+    }
+
+    #[test]
+    fn test_prepend_sender_once_per_merged_group() {
+        let input = fixtures_dir().join("telegram_mentions.json");
+        let output = temp_output("tg_prepend_sender.jsonl");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--prepend-sender",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""content":"Alice: Hey @bob"#));
+        assert_eq!(lines[0].matches("Alice: ").count(), 1);
+        assert!(lines[1].contains(r#""content":"Bob: I'm here!""#));
+    }
+
+    #[test]
+    fn test_omit_and_prepend_conflict() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "--omit-sender",
+            "--prepend-sender",
+        ]);
+
+        assert!(!result.status.success());
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("cannot be used with"));
+    }
+}
+
+// ============================================================================
+// Reply Format Tests
+// ============================================================================
+
+mod reply_format {
+    use super::*;
+
+    fn run_with_format(format: &str, output_format: &str, name: &str) -> String {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output(name);
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            output_format,
+            "--reply-format",
+            format,
+            "--no-merge",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+        read_output(&output)
+    }
+
+    #[test]
+    fn test_reply_format_id() {
+        let content = run_with_format("id", "csv", "tg_reply_id.csv");
+        assert!(content.starts_with("Sender;Content;ReplyTo"));
+        assert!(content.contains("Bob;No, what happened?;4"));
+    }
+
+    #[test]
+    fn test_reply_format_quote_csv() {
+        let content = run_with_format("quote", "csv", "tg_reply_quote.csv");
+        // Commas in the quote need no escaping with the `;` delimiter
+        assert!(content.contains("Bob;No, what happened?;> By the way, did you see the news?\n"));
+    }
+
+    #[test]
+    fn test_reply_format_quote_json() {
+        let content = run_with_format("quote", "json", "tg_reply_quote.json");
+        let parsed: serde_json::Value = serde_json::from_str(&content).unwrap();
+        let reply = &parsed.as_array().unwrap()[4];
+        assert_eq!(reply["reply_to"], "> By the way, did you see the news?");
+    }
+
+    #[test]
+    fn test_reply_format_inline() {
+        let content = run_with_format("inline", "jsonl", "tg_reply_inline.jsonl");
+        assert!(content.contains(r#""content":"@Alice No, what happened?""#));
+        assert!(!content.contains("reply_to"));
+    }
+
+    #[test]
+    fn test_reply_format_omit_overrides_replies_flag() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_reply_omit.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-r",
+            "--reply-format",
+            "omit",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+        assert!(read_output(&output).starts_with("Sender;Content\n"));
+    }
+}
+
+// ============================================================================
+// Group By Day Tests
+// ============================================================================
+
+mod group_by_day {
+    use super::*;
+
+    fn write_input(name: &str) -> PathBuf {
+        let input = temp_output(name);
+        fs::write(
+            &input,
+            "15/01/2024, 10:30 - Alice: Morning\n\
+             15/01/2024, 18:00 - Bob: Evening\n\
+             16/01/2024, 09:00 - Alice: Next day\n",
+        )
+        .unwrap();
+        input
+    }
+
+    #[test]
+    fn test_group_by_day_csv() {
+        let input = write_input("wa_days.txt");
+        let output = temp_output("wa_days.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--group-by-day",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "Sender;Content",
+                ";--- 2024-01-15 ---",
+                "Alice;Morning",
+                "Bob;Evening",
+                ";--- 2024-01-16 ---",
+                "Alice;Next day",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_by_day_jsonl() {
+        let input = write_input("wa_days_jsonl.txt");
+        let output = temp_output("wa_days.jsonl");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--group-by-day",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], r#"{"type":"day_separator","date":"2024-01-15"}"#);
+        assert_eq!(lines[3], r#"{"type":"day_separator","date":"2024-01-16"}"#);
+    }
+}
+
+// ============================================================================
+// Relative Timestamp Tests
+// ============================================================================
+
+mod relative_timestamps {
+    use super::*;
+
+    fn write_input(name: &str) -> PathBuf {
+        let input = temp_output(name);
+        fs::write(
+            &input,
+            "12/01/2024, 10:00 - Alice: Three days back\n\
+             14/01/2024, 15:30 - Bob: Yesterday afternoon\n\
+             15/01/2024, 08:00 - Alice: This morning\n\
+             15/01/2024, 10:00 - Bob: Latest\n",
+        )
+        .unwrap();
+        input
+    }
+
+    #[test]
+    fn test_relative_to_last_message() {
+        let input = write_input("wa_relative.txt");
+        let output = temp_output("wa_relative.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--relative-timestamps",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "Timestamp;Sender;Content",
+                "3 days ago;Alice;Three days back",
+                "yesterday at 15:30;Bob;Yesterday afternoon",
+                "2 hours ago;Alice;This morning",
+                "just now;Bob;Latest",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reference_time_override() {
+        let input = write_input("wa_relative_ref.txt");
+        let output = temp_output("wa_relative_ref.jsonl");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--relative-timestamps",
+            "--reference-time",
+            "2024-01-15T10:30:00Z",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.contains(r#""timestamp":"30 minutes ago""#));
+    }
+
+    #[test]
+    fn test_reference_time_requires_relative() {
+        let input = write_input("wa_relative_requires.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--reference-time",
+            "2024-01-15",
+        ]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Per-Sender Selection Tests
+// ============================================================================
+
+mod per_sender {
+    use super::*;
+
+    fn run_with(flag: &str, name: &str) -> Vec<String> {
+        let input = fixtures_dir().join("telegram_media.json");
+        let output = temp_output(name);
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--include-media-description",
+            flag,
+            "-q",
+        ]);
+
+        assert_success(&result);
+        read_output(&output).lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_first_per_sender() {
+        let lines = run_with("--first-per-sender", "tg_first_per_sender.jsonl");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""sender":"Alice""#));
+        assert!(lines[0].contains("Look at this"));
+        assert!(lines[1].contains(r#""content":"[Sticker: 😂]""#));
+    }
+
+    #[test]
+    fn test_last_per_sender() {
+        let lines = run_with("--last-per-sender", "tg_last_per_sender.jsonl");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""content":"[Document: report.pdf]""#));
+        assert!(lines[1].contains(r#""content":"Thanks!""#));
+    }
+
+    #[test]
+    fn test_first_and_last_conflict() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "--first-per-sender",
+            "--last-per-sender",
+        ]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Sliding Window Tests
+// ============================================================================
+
+mod sliding_window {
+    use super::*;
+    use std::path::Path;
+
+    fn write_input(name: &str, count: usize) -> PathBuf {
+        let input = temp_output(name);
+        let lines: String = (1..=count)
+            .map(|i| {
+                let sender = if i % 2 == 0 { "Bob" } else { "Alice" };
+                format!("15/01/2024, 10:{:02} - {}: Message {}\n", i, sender, i)
+            })
+            .collect();
+        fs::write(&input, lines).unwrap();
+        input
+    }
+
+    fn windows(input: &Path, output: &PathBuf, size: &str, step: &str) -> Vec<Vec<String>> {
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--window-size",
+            size,
+            "--window-step",
+            step,
+            "-q",
+        ]);
+        assert_success(&result);
+
+        read_output(output)
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                let window: serde_json::Value = serde_json::from_str(line).unwrap();
+                assert_eq!(window["window"], i);
+                window["messages"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|msg| msg["content"].as_str().unwrap().to_string())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_overlapping_windows() {
+        let input = write_input("wa_windows.txt", 5);
+        let output = temp_output("wa_windows.jsonl");
+
+        let windows = windows(&input, &output, "3", "1");
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[0], ["Message 1", "Message 2", "Message 3"]);
+        assert_eq!(windows[2], ["Message 3", "Message 4", "Message 5"]);
+    }
+
+    #[test]
+    fn test_last_window_shorter() {
+        let input = write_input("wa_windows_short.txt", 5);
+        let output = temp_output("wa_windows_short.jsonl");
+
+        let windows = windows(&input, &output, "2", "2");
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[2], ["Message 5"]);
+    }
+
+    #[test]
+    fn test_window_larger_than_input() {
+        let input = write_input("wa_windows_large.txt", 2);
+        let output = temp_output("wa_windows_large.jsonl");
+
+        let windows = windows(&input, &output, "10", "3");
+        assert_eq!(windows, [["Message 1", "Message 2"]]);
+    }
+
+    #[test]
+    fn test_csv_window_files() {
+        let input = write_input("wa_windows_csv.txt", 4);
+        let output = temp_output("wa_windows_csv.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--window-size",
+            "3",
+            "--window-step",
+            "2",
+            "-q",
+        ]);
+        assert_success(&result);
+
+        let first = read_output(&temp_output("wa_windows_csv_window_0.csv"));
+        let second = read_output(&temp_output("wa_windows_csv_window_1.csv"));
+        assert_eq!(first.lines().count(), 4);
+        assert_eq!(second.lines().count(), 3);
+        assert!(second.contains("Alice;Message 3"));
+    }
+
+    #[test]
+    fn test_zero_window_step_rejected() {
+        let input = write_input("wa_windows_zero.txt", 2);
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--window-size",
+            "2",
+            "--window-step",
+            "0",
+        ]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Conversation Turns Tests
+// ============================================================================
+
+mod conversation_turns {
+    use super::*;
+
+    fn write_input(name: &str) -> PathBuf {
+        let input = temp_output(name);
+        fs::write(
+            &input,
+            "15/01/2024, 10:00 - Bot: Welcome!\n\
+             15/01/2024, 10:01 - Alice: Hi\n\
+             15/01/2024, 10:02 - Alice: What's the weather?\n\
+             15/01/2024, 10:03 - Bot: Sunny.\n\
+             15/01/2024, 10:04 - Bob: Thanks\n\
+             15/01/2024, 10:05 - bot: You're welcome\n\
+             15/01/2024, 10:06 - Alice: Unanswered\n",
+        )
+        .unwrap();
+        input
+    }
+
+    #[test]
+    fn test_turns_json() {
+        let input = write_input("wa_turns.txt");
+        let output = temp_output("wa_turns.json");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "json",
+            "--conversation-turns",
+            "--assistant-name",
+            "Bot",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let parsed: serde_json::Value = serde_json::from_str(&read_output(&output)).unwrap();
+        assert_eq!(
+            parsed,
+            serde_json::json!([
+                {"human": "Hi\nWhat's the weather?", "assistant": "Sunny."},
+                {"human": "Thanks", "assistant": "You're welcome"}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_turns_csv() {
+        let input = write_input("wa_turns_csv.txt");
+        let output = temp_output("wa_turns.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--conversation-turns",
+            "--assistant-name",
+            "Bot",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.starts_with("Human;Assistant\n"));
+        assert!(content.contains("Thanks;You're welcome"));
+    }
+
+    #[test]
+    fn test_turns_require_assistant_name() {
+        let input = write_input("wa_turns_missing.txt");
+        let result = run_chatpack(&["wa", input.to_str().unwrap(), "--conversation-turns"]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Telegram Saved Messages Tests
+// ============================================================================
+
+mod telegram_saved {
+    use super::*;
+
+    #[test]
+    fn test_saved_messages_default_sender() {
+        let input = fixtures_dir().join("telegram_saved.json");
+        let output = temp_output("tg_saved.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "Sender;Content",
+                "Me;Buy milk",
+                "Me;Interesting article",
+                "Me;Call the dentist",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_saved_messages_custom_sender() {
+        let input = fixtures_dir().join("telegram_saved.json");
+        let output = temp_output("tg_saved_custom.jsonl");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--saved-messages-sender",
+            "Dana",
+            "-t",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert_eq!(content.lines().count(), 1);
+        assert!(content.contains(r#""sender":"Dana""#));
+        assert!(content.contains(r#""timestamp":"2024-04-01T09:00:00Z""#));
+    }
+
+    #[test]
+    fn test_regular_chat_unaffected() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("tg_not_saved.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-q",
+        ]);
+
+        assert_success(&result);
+        assert!(!read_output(&output).contains("Me;"));
+    }
+}
+
+// ============================================================================
+// Telegram Channel Tests
+// ============================================================================
+
+mod telegram_channel {
+    use super::*;
+
+    #[test]
+    fn test_channel_mode_uses_channel_name() {
+        let input = fixtures_dir().join("telegram_channel.json");
+        let output = temp_output("tg_channel.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--channel-mode",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.contains("Rust News;Rust 1.78 is out!"));
+        assert!(content.contains("Rust News;Weekly digest"));
+    }
+
+    #[test]
+    fn test_without_channel_mode_keeps_default_behavior() {
+        let input = fixtures_dir().join("telegram_channel.json");
+        let output = temp_output("tg_channel_default.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-q",
+        ]);
+
+        assert_success(&result);
+        assert!(!read_output(&output).contains("Rust 1.78"));
+    }
+
+    #[test]
+    fn test_include_channel_meta() {
+        let input = fixtures_dir().join("telegram_channel.json");
+        let output = temp_output("tg_channel_meta.json");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "json",
+            "--channel-mode",
+            "--include-channel-meta",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let parsed: serde_json::Value = serde_json::from_str(&read_output(&output)).unwrap();
+        assert_eq!(parsed[0]["views"], 1520);
+        assert_eq!(parsed[0]["forwards"], 34);
+        assert_eq!(parsed[1]["views"], 980);
+    }
+}
+
+// ============================================================================
+// Discord Server Tests
+// ============================================================================
+
+mod discord_server {
+    use super::*;
+
+    #[test]
+    fn test_server_channels_merged_by_time() {
+        let input = fixtures_dir().join("discord_server");
+        let output = temp_output("dc_server.jsonl");
+
+        let result = run_chatpack(&[
+            "dc",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--discord-server",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let records: Vec<serde_json::Value> = read_output(&output)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let channels: Vec<&str> = records
+            .iter()
+            .map(|r| r["channel_name"].as_str().unwrap())
+            .collect();
+        assert_eq!(channels, ["general", "random", "general"]);
+        assert_eq!(records[1]["content"], "Look at this cat");
+    }
+
+    #[test]
+    fn test_server_csv_channel_column() {
+        let input = fixtures_dir().join("discord_server");
+        let output = temp_output("dc_server.csv");
+
+        let result = run_chatpack(&[
+            "dc",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--discord-server",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.starts_with("Sender;Content;Channel\n"));
+        assert!(content.contains("Bob;Look at this cat;random"));
+    }
+
+    #[test]
+    fn test_server_requires_directory() {
+        let input = fixtures_dir().join("discord_export.json");
+        let result = run_chatpack(&["dc", input.to_str().unwrap(), "--discord-server"]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Instagram Inbox Tests
+// ============================================================================
+
+mod instagram_inbox {
+    use super::*;
+
+    #[test]
+    fn test_inbox_merged_with_conversation_id() {
+        let input = fixtures_dir().join("instagram_inbox");
+        let output = temp_output("ig_inbox.jsonl");
+
+        let result = run_chatpack(&[
+            "ig",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let records: Vec<serde_json::Value> = read_output(&output)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let summary: Vec<(&str, &str)> = records
+            .iter()
+            .map(|r| {
+                (
+                    r["conversation_id"].as_str().unwrap(),
+                    r["content"].as_str().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("alice_123", "Hey, long time!"),
+                ("alice_123", "Dinner at 8?"),
+                ("alice_123", "See you tomorrow"),
+                ("bookclub_456", "Chapter 3 done"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_by_conversation() {
+        let input = fixtures_dir().join("instagram_inbox");
+        let output = temp_output("ig_inbox_split.csv");
+
+        let result = run_chatpack(&[
+            "ig",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--split-by-conversation",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let alice = read_output(&temp_output("ig_inbox_split_alice_123.csv"));
+        let book_club = read_output(&temp_output("ig_inbox_split_bookclub_456.csv"));
+        assert_eq!(alice.lines().count(), 4);
+        assert!(!alice.contains("ConversationID"));
+        assert!(book_club.contains("bob_user;Chapter 3 done"));
+    }
+
+    #[test]
+    fn test_only_conversation_in_inbox() {
+        let input = fixtures_dir().join("instagram_inbox");
+        let output = temp_output("ig_inbox_only.csv");
+
+        let result = run_chatpack(&[
+            "ig",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--only-conversation",
+            "bookclub_456",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.contains("Chapter 3 done"));
+    }
+}
+
+// ============================================================================
+// WhatsApp Media Directory Tests
+// ============================================================================
+
+mod whatsapp_media_dir {
+    use super::*;
+
+    #[test]
+    fn test_media_marked_exists_or_missing() {
+        let dir = temp_output("wa_media_dir");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("IMG-0001.jpg"), b"jpeg").unwrap();
+        let _ = fs::remove_file(dir.join("AUD-0002.opus"));
+
+        let input = temp_output("wa_media_dir.txt");
+        let output = temp_output("wa_media_dir.csv");
+        fs::write(
+            &input,
+            "15/01/2024, 10:30 - Alice: IMG-0001.jpg (file attached)\n\
+             15/01/2024, 10:31 - Bob: AUD-0002.opus (file attached)\n",
+        )
+        .unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--include-media-description",
+            "--whatsapp-media-dir",
+            dir.to_str().unwrap(),
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        assert!(content.contains("Alice;[Photo: IMG-0001.jpg (exists)]"));
+        assert!(content.contains("Bob;[Audio: AUD-0002.opus (missing)]"));
+    }
+
+    #[test]
+    fn test_media_dir_requires_media_description() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let result = run_chatpack(&["wa", input.to_str().unwrap(), "--whatsapp-media-dir", "."]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// WhatsApp Business Tests
+// ============================================================================
+
+mod whatsapp_business {
+    use super::*;
+
+    const BUSINESS_CHAT: &str = "\
+15/01/2024, 10:00 - Bakery is a business account.
+15/01/2024, 10:01 - Alice: Do you have croissants?
+15/01/2024, 10:01 - Bakery: Thanks for your message! We'll reply soon.
+15/01/2024, 10:05 - Bakery: Yes, fresh this morning
+15/01/2024, 18:00 - Alice: And tomorrow?
+15/01/2024, 18:00 - Bakery: Thanks for your message! We'll reply soon.
+";
+
+    fn convert(name: &str, extra: &[&str]) -> String {
+        let input = temp_output(&format!("{name}.txt"));
+        let output = temp_output(&format!("{name}.csv"));
+        fs::write(&input, BUSINESS_CHAT).unwrap();
+
+        let mut args = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ];
+        args.extend_from_slice(extra);
+
+        let result = run_chatpack(&args);
+        assert_success(&result);
+        read_output(&output)
+    }
+
+    #[test]
+    fn test_auto_replies_kept_by_default() {
+        let content = convert("wa_business_default", &[]);
+        assert_eq!(content.matches("Thanks for your message!").count(), 2);
+        assert!(!content.contains("business account"));
+    }
+
+    #[test]
+    fn test_exclude_auto_replies() {
+        let content = convert("wa_business_exclude", &["--exclude-auto-replies"]);
+        assert!(!content.contains("Thanks for your message!"));
+        assert!(content.contains("Bakery;Yes, fresh this morning"));
+        assert!(content.contains("Alice;And tomorrow?"));
+    }
+
+    #[test]
+    fn test_personal_chat_unaffected() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let plain = temp_output("wa_personal_plain.csv");
+        let excluded = temp_output("wa_personal_excluded.csv");
+
+        for (output, extra) in [(&plain, None), (&excluded, Some("--exclude-auto-replies"))] {
+            let mut args = vec![
+                "wa",
+                input.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+            ];
+            args.extend(extra);
+            args.push("-q");
+            assert_success(&run_chatpack(&args));
+        }
+
+        assert_eq!(read_output(&plain), read_output(&excluded));
+    }
+}
+
+// ============================================================================
+// Validate Subcommand Tests
+// ============================================================================
+
+mod validate {
+    use super::*;
+
+    fn stdout(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    #[test]
+    fn test_valid_exports() {
+        for (source, fixture) in [
+            ("tg", "telegram_media.json"),
+            ("wa", "whatsapp_export.txt"),
+            ("ig", "instagram_export.json"),
+            ("dc", "discord_export.json"),
+        ] {
+            let input = fixtures_dir().join(fixture);
+            let result = run_chatpack(&["validate", source, input.to_str().unwrap()]);
+            assert_success(&result);
+        }
+    }
+
+    #[test]
+    fn test_reports_variant_and_issues() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let result = run_chatpack(&["validate", "tg", input.to_str().unwrap()]);
+
+        assert_success(&result);
+        let report = stdout(&result);
+        assert!(report.contains("Telegram personal chat, legacy format"));
+        assert!(report.contains("7 messages without a timestamp"));
+    }
+
+    #[test]
+    fn test_wrong_platform_fails() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let result = run_chatpack(&["validate", "tg", input.to_str().unwrap()]);
+
+        assert!(!result.status.success());
+        assert!(stdout(&result).contains("No \"messages\" array found"));
+    }
+
+    #[test]
+    fn test_only_reads_first_messages() {
+        // A cut-off export: conversion fails, but the first 100 messages are fine
+        let messages: Vec<String> = (1..=150)
+            .map(|i| {
+                format!(
+                    r#"    {{"id": {i}, "type": "message", "date_unixtime": "1705314600", "from": "Alice", "text": "Message {i}"}}"#
+                )
+            })
+            .collect();
+        let export = format!(
+            "{{\n  \"type\": \"personal_chat\",\n  \"messages\": [\n{}",
+            messages.join(",\n")
+        );
+        let input = temp_output("validate_truncated.json");
+        fs::write(&input, export).unwrap();
+
+        let result = run_chatpack(&["validate", "tg", input.to_str().unwrap()]);
+
+        assert_success(&result);
+        assert!(stdout(&result).contains("Checked:  100 messages"));
+    }
+}
+
+// ============================================================================
+// Output Schema Tests
+// ============================================================================
+
+mod output_schema {
+    use super::*;
+
+    fn schema(args: &[&str]) -> serde_json::Value {
+        // INPUT isn't read, so it doesn't need to exist
+        let mut all = vec!["tg", "missing.json", "--output-schema"];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        assert_success(&result);
+        serde_json::from_slice(&result.stdout).unwrap()
+    }
+
+    fn property_names(object: &serde_json::Value) -> Vec<String> {
+        object["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    #[test]
+    fn test_id_only_with_ids_flag() {
+        let without = schema(&["-f", "json"]);
+        assert_eq!(
+            without["$schema"],
+            "http://json-schema.org/draft-07/schema#"
+        );
+        assert_eq!(without["type"], "array");
+        assert!(!property_names(&without["items"]).contains(&"id".to_string()));
+
+        let with = schema(&["-f", "json", "--ids"]);
+        assert!(property_names(&with["items"]).contains(&"id".to_string()));
+    }
+
+    #[test]
+    fn test_matches_json_output() {
+        let flags = ["-t", "-r", "-e", "--ids"];
+        let schema = schema(&[&["-f", "jsonl"][..], &flags].concat());
+        let properties = property_names(&schema);
+
+        let input = fixtures_dir().join("telegram_media.json");
+        let output = temp_output("schema_output.jsonl");
+        let mut args = vec![
+            "tg",
+            input.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "-o",
+            output.to_str().unwrap(),
+            "-q",
+        ];
+        args.extend_from_slice(&flags);
+        assert_success(&run_chatpack(&args));
+
+        for line in read_output(&output).lines() {
+            let record: serde_json::Value = serde_json::from_str(line).unwrap();
+            for key in record.as_object().unwrap().keys() {
+                assert!(properties.contains(key), "{key} missing from schema");
+            }
+        }
+    }
+
+    #[test]
+    fn test_csv_row_columns() {
+        let schema = schema(&["-t", "--omit-sender"]);
+        assert_eq!(
+            schema["required"],
+            serde_json::json!(["Timestamp", "Content"])
+        );
+    }
+
+    #[test]
+    fn test_envelope_and_turns() {
+        let enveloped = schema(&["-f", "json", "--json-envelope"]);
+        assert_eq!(enveloped["type"], "object");
+        assert!(property_names(&enveloped).contains(&"messages".to_string()));
+
+        let turns = schema(&[
+            "-f",
+            "jsonl",
+            "--conversation-turns",
+            "--assistant-name",
+            "Bob",
+        ]);
+        assert_eq!(
+            property_names(&turns),
+            vec!["assistant".to_string(), "human".to_string()]
+        );
+    }
+}
+
+// ============================================================================
+// Version Info Tests
+// ============================================================================
+
+mod version_info {
+    use super::*;
+
+    #[test]
+    fn test_version_info_without_input() {
+        let result = run_chatpack(&["--version-info"]);
+        assert_success(&result);
+
+        let info = String::from_utf8_lossy(&result.stdout);
+        assert!(info.starts_with(&format!("chatpack-cli {}", env!("CARGO_PKG_VERSION"))));
+        for field in ["commit:", "rustc:", "profile:", "platforms:"] {
+            assert!(info.contains(field), "missing {field}");
+        }
+    }
+}
+
+// ============================================================================
+// Input Encoding Tests
+// ============================================================================
+
+mod input_encoding {
+    use super::*;
+
+    fn convert(name: &str, bytes: &[u8], encoding: &str) -> String {
+        let input = temp_output(&format!("{name}.txt"));
+        let output = temp_output(&format!("{name}.csv"));
+        fs::write(&input, bytes).unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--input-encoding",
+            encoding,
+            "-q",
+        ]);
+        assert_success(&result);
+        read_output(&output)
+    }
+
+    #[test]
+    fn test_windows_1252() {
+        let bytes = b"15/01/2024, 10:30 - Jos\xe9: Ol\xe1, \x93tudo bem?\x94\n";
+        let content = convert("enc_1252", bytes, "windows-1252");
+        assert!(content.contains("José;Olá, “tudo bem?”"));
+    }
+
+    #[test]
+    fn test_shift_jis() {
+        let mut bytes = b"15/01/2024, 10:30 - Yuki: ".to_vec();
+        // こんにちは
+        bytes.extend_from_slice(b"\x82\xb1\x82\xf1\x82\xc9\x82\xbf\x82\xcd\n");
+        let content = convert("enc_sjis", &bytes, "shift-jis");
+        assert!(content.contains("Yuki;こんにちは"));
+    }
+
+    #[test]
+    fn test_utf8_default_unchanged() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let plain = temp_output("enc_default.csv");
+        let explicit = temp_output("enc_explicit.csv");
+
+        for (output, extra) in [(&plain, None), (&explicit, Some("utf-8"))] {
+            let mut args = vec![
+                "wa",
+                input.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+            ];
+            if let Some(encoding) = extra {
+                args.extend(["--input-encoding", encoding]);
+            }
+            args.push("-q");
+            assert_success(&run_chatpack(&args));
+        }
+
+        assert_eq!(read_output(&plain), read_output(&explicit));
+    }
+
+    #[test]
+    fn test_summary_shows_original_input() {
+        let input = temp_output("enc_summary.txt");
+        fs::write(&input, b"15/01/2024, 10:30 - Jos\xe9: Ol\xe1\n").unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--input-encoding",
+            "windows-1252",
+            "--summary-only",
+            "--json",
+        ]);
+
+        assert_success(&result);
+        let summary: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+        assert_eq!(summary["input"], input.to_str().unwrap());
+    }
+
+    #[test]
+    fn test_report_shows_original_input() {
+        let input = temp_output("enc_report.txt");
+        let output = temp_output("enc_report.csv");
+        fs::write(&input, b"15/01/2024, 10:30 - Jos\xe9: Ol\xe1\n").unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--input-encoding",
+            "windows-1252",
+            "--report",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let report = read_output(&temp_output("enc_report.csv.report.md"));
+        assert!(report.contains(&format!("- **File:** `{}`", input.display())));
+    }
+}
+
+// ============================================================================
+// Strip Quotes Tests
+// ============================================================================
+
+mod strip_quotes {
+    use super::*;
+
+    fn convert(name: &str, strip: bool) -> String {
+        let input = temp_output(&format!("{name}.txt"));
+        let output = temp_output(&format!("{name}.jsonl"));
+        fs::write(
+            &input,
+            "15/01/2024, 10:30 - Alice: Are we still on for lunch?\n\
+             15/01/2024, 10:31 - Bob: > Are we still on for lunch?\n\
+             Yes, see you at noon\n\
+             15/01/2024, 10:32 - Alice: > just a quote\n",
+        )
+        .unwrap();
+
+        let mut args = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ];
+        if strip {
+            args.push("--strip-quotes");
+        }
+
+        assert_success(&run_chatpack(&args));
+        read_output(&output)
+    }
+
+    #[test]
+    fn test_leading_quote_lines_removed() {
+        let content = convert("wa_strip_quotes", true);
+        let lines: Vec<&str> = content.lines().collect();
+
+        assert!(lines[1].contains(r#""content":"Yes, see you at noon""#));
+        // Nothing but a quote: kept so the entry isn't empty
+        assert!(lines[2].contains(r#""content":"> just a quote""#));
+    }
+
+    #[test]
+    fn test_quotes_kept_by_default() {
+        let content = convert("wa_keep_quotes", false);
+        assert!(content.contains(r#"> Are we still on for lunch?\nYes, see you at noon"#));
+    }
+}
+
+// ============================================================================
+// Incremental Processing Tests
+// ============================================================================
+
+mod incremental {
+    use super::*;
+    use std::path::Path;
+
+    const FIRST_EXPORT: &str = "\
+15/01/2024, 10:30 - Alice: First
+15/01/2024, 10:31 - Bob: Second
+";
+
+    fn run(input: &Path, output: &Path) -> String {
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--incremental",
+            "--no-merge",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+        assert_success(&result);
+        read_output(&output.to_path_buf())
+    }
+
+    #[test]
+    fn test_second_run_only_writes_new_messages() {
+        let input = temp_output("wa_incremental.txt");
+        let output = temp_output("wa_incremental.csv");
+        let state = temp_output("wa_incremental.csv.state.json");
+        let _ = fs::remove_file(&state);
+
+        fs::write(&input, FIRST_EXPORT).unwrap();
+        let first = run(&input, &output);
+        assert!(first.contains("Alice;First"));
+        assert!(first.contains("Bob;Second"));
+
+        let saved: serde_json::Value = serde_json::from_str(&read_output(&state)).unwrap();
+        assert_eq!(saved["message_count"], 2);
+        assert_eq!(saved["last_timestamp"], "2024-01-15T10:31:00Z");
+
+        fs::write(
+            &input,
+            format!("{FIRST_EXPORT}16/01/2024, 09:00 - Alice: Third\n"),
+        )
+        .unwrap();
+        let second = run(&input, &output);
+        assert_eq!(second, "Sender;Content\nAlice;Third\n");
+
+        let saved: serde_json::Value = serde_json::from_str(&read_output(&state)).unwrap();
+        assert_eq!(saved["message_count"], 3);
+    }
+
+    #[test]
+    fn test_no_state_without_flag() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("wa_not_incremental.csv");
+        let state = temp_output("wa_not_incremental.csv.state.json");
+        let _ = fs::remove_file(&state);
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-q",
+        ]);
+        assert_success(&result);
+        assert!(!state.exists());
+    }
+}
+
+// ============================================================================
+// Append Mode Tests
+// ============================================================================
+
+mod append {
+    use super::*;
+    use std::path::Path;
+
+    fn write_chat(name: &str, line: &str) -> PathBuf {
+        let input = temp_output(name);
+        fs::write(&input, format!("15/01/2024, 10:30 - {line}\n")).unwrap();
+        input
+    }
+
+    fn append(input: &Path, output: &Path) {
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--append",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+        assert_success(&result);
+    }
+
+    #[test]
+    fn test_csv_header_written_once() {
+        let output = temp_output("append.csv");
+        let _ = fs::remove_file(&output);
+
+        append(&write_chat("append_1.txt", "Alice: One"), &output);
+        append(&write_chat("append_2.txt", "Bob: Two"), &output);
+
+        assert_eq!(read_output(&output), "Sender;Content\nAlice;One\nBob;Two\n");
+    }
+
+    #[test]
+    fn test_jsonl_lines_appended() {
+        let output = temp_output("append.jsonl");
+        let _ = fs::remove_file(&output);
+
+        append(&write_chat("append_3.txt", "Alice: One"), &output);
+        append(&write_chat("append_4.txt", "Bob: Two"), &output);
+
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].contains(r#""sender":"Bob""#));
+    }
+
+    #[test]
+    fn test_after_id_appends_only_new_messages() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("append_after_id.jsonl");
+        let _ = fs::remove_file(&output);
+
+        // First run saw messages up to ID 5; the second picks up from there
+        for range in [["--before-id", "6"], ["--after-id", "5"]] {
+            let result = run_chatpack(&[
+                "tg",
+                input.to_str().unwrap(),
+                "-o",
+                output.to_str().unwrap(),
+                range[0],
+                range[1],
+                "--ids",
+                "--no-merge",
+                "--append",
+                "-q",
+            ]);
+            assert_success(&result);
+        }
+
+        let content = read_output(&output);
+        let ids: Vec<u64> = content
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<serde_json::Value>(line).unwrap()["id"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_json_append_rejected() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("append.json");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--append",
+        ]);
+
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("-f jsonl"));
+    }
+}
+
+// ============================================================================
+// Instagram Format Detection Tests
+// ============================================================================
+
+mod instagram_formats {
+    use super::*;
+
+    fn assert_parses(fixture: &str) {
+        let input = fixtures_dir().join(fixture);
+        let output = temp_output(&format!("{fixture}.csv"));
+
+        let result = run_chatpack(&[
+            "ig",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-t",
+            "--no-streaming",
+            "-q",
+        ]);
+
+        assert_success(&result);
+
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "Timestamp;Sender;Content",
+                "2024-01-15 10:50:00;alice_user;Hello! How are you?",
+                "2024-01-15 10:50:15;bob_user;Hi! Doing great.",
+            ],
+            "{fixture} should parse in chronological order"
+        );
+    }
+
+    #[test]
+    fn test_v1_message_array() {
+        assert_parses("instagram_v1.json");
+    }
+
+    #[test]
+    fn test_v2_nested_conversation() {
+        assert_parses("instagram_v2.json");
+    }
+
+    #[test]
+    fn test_v3_magic_words() {
+        assert_parses("instagram_v3.json");
+    }
+
+    #[test]
+    fn test_unknown_format_fails() {
+        let input = temp_output("ig_unknown.json");
+        fs::write(&input, r#"{"threads": 3}"#).unwrap();
+
+        let result = run_chatpack(&["ig", input.to_str().unwrap(), "-q"]);
+
+        assert!(!result.status.success());
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("Unrecognized Instagram export format"));
+    }
+}
+
+// =============================================================================
+// Telegram Service Message Tests
+// =============================================================================
+
+mod telegram_service {
+    use super::*;
+
+    #[test]
+    fn test_service_messages_described() {
+        let input = fixtures_dir().join("telegram_service.json");
+        let output = temp_output("tg_service.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--include-service-messages",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&output);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "Sender;Content",
+                r#"Alice;"[Supergroup was created from group ""Rust Meetup""]""#,
+                "Alice;Welcome to the supergroup!",
+                "Alice;[Pinned message 2]",
+                r#"Bob;"[Group was renamed to ""Rust Meetup Berlin""]""#,
+                "Bob;New name, same meetup",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_service_messages_skipped_by_default() {
+        let input = fixtures_dir().join("telegram_service.json");
+        let output = temp_output("tg_service_default.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-q",
+        ]);
+
+        assert_success(&result);
+        assert!(!read_output(&output).contains("[Group was renamed"));
+    }
+}
+
+// =============================================================================
+// Summary-only Tests
+// =============================================================================
+
+mod summary_only {
+    use super::*;
+
+    #[test]
+    fn test_prints_counts_without_output() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("wa_summary_only.csv");
+        let _ = fs::remove_file(&output);
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--summary-only",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let stdout = String::from_utf8_lossy(&result.stdout);
+        assert!(stdout.contains("Parsed:   7 messages"));
+        assert!(stdout.contains("Senders:  2"));
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_json_summary() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--from",
+            "Alice",
+            "--summary-only",
+            "--json",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let summary: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+        assert_eq!(summary["platform"], "whatsapp");
+        assert_eq!(summary["parsed"], 7);
+        assert_eq!(summary["filtered"], 4);
+        assert_eq!(summary["entries"], 1);
+        assert_eq!(summary["senders"], 1);
+    }
+
+    #[test]
+    fn test_json_requires_summary_only() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let result = run_chatpack(&["wa", input.to_str().unwrap(), "--json"]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Line Number Tests
+// ============================================================================
+
+mod line_numbers {
+    use super::*;
+
+    fn run_jsonl(source: &str, fixture: &str, name: &str) -> Vec<serde_json::Value> {
+        run_jsonl_with(source, fixture, name, &[])
+    }
+
+    fn run_jsonl_with(
+        source: &str,
+        fixture: &str,
+        name: &str,
+        extra: &[&str],
+    ) -> Vec<serde_json::Value> {
+        let input = fixtures_dir().join(fixture);
+        let output = temp_output(name);
+
+        let mut args = vec![
+            source,
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--line-numbers",
+            "--no-merge",
+            "-q",
+        ];
+        args.extend_from_slice(extra);
+        let result = run_chatpack(&args);
+
+        assert_success(&result);
+        read_output(&output)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_telegram_line_numbers_point_at_message_objects() {
+        let messages = run_jsonl("tg", "telegram_export.json", "tg_lines.jsonl");
+
+        assert_eq!(messages[0]["source_line"], 6);
+        assert_eq!(messages[1]["source_line"], 14);
+        assert_eq!(messages[2]["source_line"], 22);
+    }
+
+    #[test]
+    fn test_whatsapp_line_numbers() {
+        let messages = run_jsonl("wa", "whatsapp_export.txt", "wa_lines.jsonl");
+
+        assert_eq!(messages[0]["source_line"], 1);
+        assert_eq!(messages[1]["source_line"], 2);
+        assert_eq!(messages[4]["source_line"], 5);
+        assert_eq!(messages[6]["source_line"], 7);
+    }
+
+    #[test]
+    fn test_whatsapp_same_sender_same_minute() {
+        let messages = run_jsonl("wa", "whatsapp_export.txt", "wa_lines_minute.jsonl");
+
+        // Lines 3 and 4 are both Alice at 10:31
+        assert_eq!(messages[2]["source_line"], 3);
+        assert_eq!(messages[3]["source_line"], 4);
+    }
+
+    #[test]
+    fn test_whatsapp_line_numbers_after_renaming() {
+        let messages = run_jsonl_with(
+            "wa",
+            "whatsapp_export.txt",
+            "wa_lines_lowercase.jsonl",
+            &["--lowercase-senders"],
+        );
+
+        assert_eq!(messages[0]["sender"], "alice");
+        let lines: Vec<_> = messages.iter().map(|msg| &msg["source_line"]).collect();
+        assert_eq!(lines, [1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_whatsapp_line_numbers_with_alias_file() {
+        let aliases = temp_output("wa_lines_aliases.json");
+        fs::write(&aliases, r#"{"Bob": "Robert"}"#).unwrap();
+        let messages = run_jsonl_with(
+            "wa",
+            "whatsapp_export.txt",
+            "wa_lines_aliases.jsonl",
+            &["--sender-alias-file", aliases.to_str().unwrap()],
+        );
+
+        assert_eq!(messages[4]["sender"], "Robert");
+        assert_eq!(messages[3]["source_line"], 4);
+        assert_eq!(messages[4]["source_line"], 5);
+    }
+
+    #[test]
+    fn test_whatsapp_merged_message_keeps_first_line() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("wa_lines_merged.jsonl");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--line-numbers",
+            "--lowercase-senders",
+            "-q",
+        ]);
+        assert_success(&result);
+
+        let messages: Vec<serde_json::Value> = read_output(&output)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(messages[2]["source_line"], 3);
+        assert_eq!(messages[3]["source_line"], 5);
+    }
+
+    #[test]
+    fn test_csv_source_line_column() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("wa_lines.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--line-numbers",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&output);
+        assert!(content.starts_with("Sender;Content;SourceLine\n"));
+        assert!(content.contains(";5\n"));
+    }
+}
+
+// ============================================================================
+// Hash ID Tests
+// ============================================================================
+
+mod hash_ids {
+    use super::*;
+
+    fn ids(name: &str) -> Vec<u64> {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output(name);
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--hash-ids",
+            "--ids",
+            "--no-merge",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        read_output(&output)
+            .lines()
+            .map(|line| {
+                let value: serde_json::Value = serde_json::from_str(line).unwrap();
+                value["id"].as_u64().expect("every message has an ID")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_hash_ids_are_stable() {
+        let first = ids("wa_hash_ids_1.jsonl");
+        let second = ids("wa_hash_ids_2.jsonl");
+
+        assert_eq!(first.len(), 7);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_hash_ids_are_distinct() {
+        let mut ids = ids("wa_hash_ids_distinct.jsonl");
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 7);
+    }
+}
+
+// ============================================================================
+// Pretty Names Tests
+// ============================================================================
+
+mod pretty_names {
+    use super::*;
+
+    fn write_inputs(name: &str) -> (PathBuf, PathBuf) {
+        let input = temp_output(&format!("{}.txt", name));
+        let contacts = temp_output(&format!("{}_contacts.csv", name));
+        fs::write(
+            &input,
+            "15/01/2024, 10:30 - \u{202a}+1 555-010-2030\u{202c}: Hi, it's Carol\n\
+             15/01/2024, 10:31 - Alice: Hello Carol!\n\
+             15/01/2024, 10:32 - +44 7700 900123: Wrong number?\n",
+        )
+        .unwrap();
+        fs::write(&contacts, "phone,name\n+15550102030,Carol\n").unwrap();
+        (input, contacts)
+    }
+
+    #[test]
+    fn test_phone_numbers_resolved_to_names() {
+        let (input, contacts) = write_inputs("wa_pretty_names");
+        let output = temp_output("wa_pretty_names.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--pretty-names",
+            contacts.to_str().unwrap(),
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&output);
+        assert!(content.contains("Carol;Hi, it's Carol"));
+        assert!(content.contains("+44 7700 900123;Wrong number?"));
+    }
+
+    #[test]
+    fn test_filters_use_resolved_names() {
+        let (input, contacts) = write_inputs("wa_pretty_names_from");
+        let output = temp_output("wa_pretty_names_from.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--pretty-names",
+            contacts.to_str().unwrap(),
+            "--from",
+            "Carol",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&output);
+        assert!(content.contains("Carol;Hi, it's Carol"));
+        assert!(!content.contains("Alice"));
+    }
+
+    #[test]
+    fn test_invalid_contacts_file() {
+        let (input, _) = write_inputs("wa_pretty_names_invalid");
+        let contacts = temp_output("wa_pretty_names_invalid_contacts.csv");
+        fs::write(&contacts, "number\n+15550102030\n").unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--pretty-names",
+            contacts.to_str().unwrap(),
+            "-q",
+        ]);
+
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Report Tests
+// ============================================================================
+
+mod report {
+    use super::*;
+
+    #[test]
+    fn test_report_written_next_to_output() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("wa_report.csv");
+        let report = temp_output("wa_report.csv.report.md");
+        let _ = fs::remove_file(&report);
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--report",
+            "--after",
+            "2024-01-01",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&report);
+        assert!(content.starts_with("# Chat export report"));
+        assert!(content.contains("- **Platform:** WhatsApp"));
+        assert!(content.contains("- **Period:** 2024-01-15 → 2024-03-10"));
+        assert!(content.contains("| Alice | 4 |"));
+        assert!(content.contains("| Bob | 3 |"));
+        assert!(content.contains("## Top words"));
+        assert!(content.contains("- **First:** 2024-01-15 10:30 — Alice: Hello! How are you?"));
+        assert!(content.contains("- `--after 2024-01-01`"));
+        assert!(content.contains("wa_report.csv` (CSV, "));
+    }
+
+    #[test]
+    fn test_report_conflicts_with_summary_only() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let result = run_chatpack(&["wa", input.to_str().unwrap(), "--report", "--summary-only"]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Participant List Tests
+// ============================================================================
+
+mod participant_list {
+    use super::*;
+
+    fn write_chat(name: &str) -> PathBuf {
+        let input = temp_output(name);
+        fs::write(
+            &input,
+            "15/01/2024, 10:30 - Alice: Hello\n\
+             15/01/2024, 10:31 - Bob: Hi\n\
+             20/02/2024, 14:00 - Carol; Jr: Joined late\n\
+             21/02/2024, 09:00 - Alice: Welcome\n",
+        )
+        .unwrap();
+        input
+    }
+
+    #[test]
+    fn test_one_sender_per_line() {
+        let input = write_chat("wa_participants.txt");
+        let result = run_chatpack(&["wa", input.to_str().unwrap(), "--participant-list"]);
+
+        assert_success(&result);
+        assert_eq!(
+            String::from_utf8_lossy(&result.stdout),
+            "Alice\nBob\nCarol; Jr\n"
+        );
+    }
+
+    #[test]
+    fn test_respects_filters() {
+        let input = write_chat("wa_participants_filtered.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--participant-list",
+            "--after",
+            "2024-02-01",
+            "--json",
+        ]);
+
+        assert_success(&result);
+        let senders: Vec<String> = serde_json::from_slice(&result.stdout).unwrap();
+        assert_eq!(senders, ["Carol; Jr", "Alice"]);
+    }
+
+    #[test]
+    fn test_csv_column() {
+        let input = write_chat("wa_participants_csv.txt");
+        let output = temp_output("wa_participants_not_written.csv");
+        let _ = fs::remove_file(&output);
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "csv",
+            "--participant-list",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+        assert_eq!(
+            String::from_utf8_lossy(&result.stdout),
+            "Sender\nAlice\nBob\n\"Carol; Jr\"\n"
+        );
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_conflicts_with_summary_only() {
+        let input = write_chat("wa_participants_conflict.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--participant-list",
+            "--summary-only",
+        ]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Date List Tests
+// ============================================================================
+
+mod date_list {
+    use super::*;
+
+    #[test]
+    fn test_one_date_per_line() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let result = run_chatpack(&["wa", input.to_str().unwrap(), "--date-list", "-q"]);
+
+        assert_success(&result);
+        assert_eq!(
+            String::from_utf8_lossy(&result.stdout),
+            "2024-01-15\n2024-02-20\n2024-03-10\n"
+        );
+    }
+
+    #[test]
+    fn test_json_respects_filters() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--date-list",
+            "--json",
+            "--from",
+            "Bob",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let dates: Vec<String> = serde_json::from_slice(&result.stdout).unwrap();
+        assert_eq!(dates, ["2024-01-15", "2024-03-10"]);
+    }
+}
+
+// ============================================================================
+// Encoding Report Tests
+// ============================================================================
+
+mod char_encoding_report {
+    use super::*;
+
+    #[test]
+    fn test_reports_bom_crlf_and_mojibake() {
+        let input = temp_output("wa_encoding_issues.txt");
+        let output = temp_output("wa_encoding_issues.csv");
+        fs::write(
+            &input,
+            "\u{feff}15/01/2024, 10:30 - Alice: CafÃ©\r\n15/01/2024, 10:31 - Bob: OK\r\n",
+        )
+        .unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--char-encoding-report",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("byte order mark at byte 0"));
+        assert!(stderr.contains("possible mojibake \"Ã©\" (\"é\") at byte 33"));
+        assert!(stderr.contains("CRLF line endings (2 lines), first at byte 37"));
+    }
+
+    #[test]
+    fn test_reports_invalid_utf8() {
+        let input = temp_output("wa_encoding_invalid.txt");
+        fs::write(&input, b"15/01/2024, 10:30 - Alice: caf\xe9\n").unwrap();
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--char-encoding-report",
+            "--input-encoding",
+            "windows-1252",
+            "-o",
+            temp_output("wa_encoding_invalid.csv").to_str().unwrap(),
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("invalid UTF-8 at byte 30"));
+    }
+
+    #[test]
+    fn test_clean_input() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            temp_output("wa_encoding_clean.csv").to_str().unwrap(),
+            "--char-encoding-report",
+        ]);
+
+        assert_success(&result);
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("No encoding issues found"));
+        assert!(!stderr.contains("Encoding:"));
+    }
+}
+
+// ============================================================================
+// Empty Message Tests
+// ============================================================================
+
+mod empty_messages {
+    use super::*;
+
+    fn run_with(flags: &[&str], name: &str) -> (Output, Vec<serde_json::Value>) {
+        let input = fixtures_dir().join("whatsapp_empty.txt");
+        let output = temp_output(name);
+
+        let mut args = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "-t",
+            "--no-merge",
+            "-q",
+        ];
+        args.extend_from_slice(flags);
+        let result = run_chatpack(&args);
+
+        assert_success(&result);
+        let messages = read_output(&output)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        (result, messages)
+    }
+
+    #[test]
+    fn test_kept_without_flags() {
+        let (result, messages) = run_with(&[], "wa_empty_default.jsonl");
+        assert_eq!(messages.len(), 5);
+        assert!(!String::from_utf8_lossy(&result.stderr).contains("Empty message"));
+    }
+
+    #[test]
+    fn test_warn_keeps_messages() {
+        let (result, messages) = run_with(&["--warn-empty-messages"], "wa_empty_warn.jsonl");
+        let stderr = String::from_utf8_lossy(&result.stderr);
+
+        assert_eq!(messages.len(), 5);
+        assert!(stderr.contains("Empty message from Bob at 2024-01-15 10:31:00"));
+        assert!(stderr.contains("Empty message from Alice at 2024-01-15 10:33:00"));
+        assert_eq!(stderr.matches("Empty message").count(), 2);
+    }
+
+    #[test]
+    fn test_skip_drops_silently() {
+        let (result, messages) = run_with(&["--skip-empty-messages"], "wa_empty_skip.jsonl");
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["content"], "Yes, looks great");
+        assert!(!String::from_utf8_lossy(&result.stderr).contains("Empty message"));
+    }
+
+    #[test]
+    fn test_warn_and_skip() {
+        let (result, messages) = run_with(
+            &["--warn-empty-messages", "--skip-empty-messages"],
+            "wa_empty_warn_skip.jsonl",
+        );
+
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            String::from_utf8_lossy(&result.stderr)
+                .matches("Empty message")
+                .count(),
+            2
+        );
+    }
+}
+
+// ============================================================================
+// HTTP Input/Output Tests
+// ============================================================================
+
+mod http_urls {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// A request as seen by the test server
+    struct Request {
+        headers: Vec<String>,
+        body: Vec<u8>,
+    }
+
+    impl Request {
+        fn header(&self, name: &str) -> Option<&str> {
+            self.headers.iter().find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.eq_ignore_ascii_case(name).then(|| value.trim())
+            })
+        }
+    }
+
+    /// Answer one request with `body`; the handle yields the request.
+    fn serve_once(path: &str, body: Vec<u8>) -> (String, thread::JoinHandle<Request>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}{}", listener.local_addr().unwrap(), path);
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut headers = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                headers.push(line.trim().to_string());
+            }
+
+            let mut request = Request {
+                headers,
+                body: Vec::new(),
+            };
+            let length = request
+                .header("content-length")
+                .map_or(0, |len| len.parse().unwrap());
+            request.body = vec![0; length];
+            reader.read_exact(&mut request.body).unwrap();
+
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(&body).unwrap();
+            request
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn test_fetches_export() {
+        let body = fs::read(fixtures_dir().join("whatsapp_export.txt")).unwrap();
+        let (url, server) = serve_once("/exports/chat.txt?sig=abc", body);
+        let output = temp_output("wa_from_url.csv");
+
+        let result = run_chatpack(&[
+            "wa",
+            "--input-url",
+            &url,
+            "--http-header",
+            "Authorization: Bearer secret",
+            "-o",
+            output.to_str().unwrap(),
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let request = server.join().unwrap();
+        assert!(request.headers[0].starts_with("GET /exports/chat.txt?sig=abc "));
+        assert_eq!(request.header("authorization"), Some("Bearer secret"));
+        assert!(read_output(&output).contains("Alice;Hello! How are you?"));
+    }
+
+    #[test]
+    fn test_posts_output() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let (url, server) = serve_once("/_bulk", Vec::new());
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-f",
+            "jsonl",
+            "--output-url",
+            &url,
+            "-q",
+        ]);
+
+        assert_success(&result);
+        let request = server.join().unwrap();
+        assert!(request.headers[0].starts_with("POST /_bulk "));
+        assert_eq!(request.header("content-type"), Some("application/x-ndjson"));
+
+        let body = String::from_utf8(request.body).unwrap();
+        let first: serde_json::Value = serde_json::from_str(body.lines().next().unwrap()).unwrap();
+        assert_eq!(first["sender"], "Alice");
+    }
+
+    #[test]
+    fn test_puts_csv_output() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let (url, server) = serve_once("/chats/42", Vec::new());
+
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--output-url",
+            &url,
+            "--http-method",
+            "put",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+        let request = server.join().unwrap();
+        assert!(request.headers[0].starts_with("PUT /chats/42 "));
+        assert_eq!(request.header("content-type"), Some("text/csv"));
+        assert!(
+            String::from_utf8(request.body)
+                .unwrap()
+                .starts_with("Sender;Content\n")
+        );
+    }
+
+    #[test]
+    fn test_conflicts_with_input() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--input-url",
+            "http://127.0.0.1:1/chat.txt",
+        ]);
+        assert!(!result.status.success());
+    }
+
+    #[test]
+    fn test_http_header_needs_url() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--http-header",
+            "Authorization: Bearer secret",
+        ]);
+
+        assert!(!result.status.success());
+        assert!(
+            String::from_utf8_lossy(&result.stderr)
+                .contains("--http-header needs --input-url or --output-url")
+        );
+    }
+}
+
+// ============================================================================
+// Parse Warning Tests
+// ============================================================================
+
+mod parse_warnings {
+    use super::*;
+
+    fn telegram_with_unknown_entity(name: &str) -> PathBuf {
+        let input = temp_output(name);
+        fs::write(
+            &input,
+            r#"{
+  "name": "Test Chat",
+  "messages": [
+    {
+      "id": 1,
+      "type": "message",
+      "date": "2024-01-15T10:30:00",
+      "date_unixtime": "1705314600",
+      "from": "Alice",
+      "text": [{"type": "sparkle", "text": "Hi"}],
+      "text_entities": [{"type": "sparkle", "text": "Hi"}]
+    }
+  ]
+}"#,
+        )
+        .unwrap();
+        input
+    }
+
+    #[test]
+    fn test_unknown_telegram_entity() {
+        let input = telegram_with_unknown_entity("tg_unknown_entity.json");
+        let output = temp_output("tg_unknown_entity.csv");
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+        ]);
+
+        assert_success(&result);
+        assert!(
+            String::from_utf8_lossy(&result.stderr)
+                .contains("Parse warning: unknown text entity type \"sparkle\" in message 1")
+        );
+        assert!(read_output(&output).contains("Alice;Hi"));
+    }
+
+    #[test]
+    fn test_malformed_discord_reaction_and_future_timestamp() {
+        let input = temp_output("dc_warnings.csv");
+        fs::write(
+            &input,
+            "AuthorID,Author,Date,Content,Attachments,Reactions\n\
+             1,alice,2024-01-15T10:30:00.000+00:00,Hello,,👍 (1)\n\
+             2,bob,2024-01-15T10:31:00.000+00:00,Hi,,👍 1\n\
+             1,alice,2999-01-01T00:00:00.000+00:00,From the future,,\n",
+        )
+        .unwrap();
+
+        let result = run_chatpack(&[
+            "dc",
+            input.to_str().unwrap(),
+            "-o",
+            temp_output("dc_warnings_out.csv").to_str().unwrap(),
+        ]);
+
+        assert_success(&result);
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("malformed reaction \"👍 1\" in row 3"));
+        assert!(
+            stderr.contains("timestamp in the future (2999-01-01 00:00:00) in message from alice")
+        );
+        assert_eq!(stderr.matches("Parse warning").count(), 2);
+    }
+
+    #[test]
+    fn test_fail_on_warning() {
+        let input = telegram_with_unknown_entity("tg_unknown_entity_strict.json");
+        let output = temp_output("tg_unknown_entity_strict.csv");
+        let _ = fs::remove_file(&output);
+
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--fail-on-warning",
+            "-q",
+        ]);
+
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("1 parse warning(s) found"));
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_fail_on_warning_passes_clean_export() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            temp_output("tg_clean_strict.csv").to_str().unwrap(),
+            "--fail-on-warning",
+        ]);
+
+        assert_success(&result);
+        assert!(!String::from_utf8_lossy(&result.stderr).contains("Parse warning"));
+    }
+}
+
+// ============================================================================
+// Memory Limit Tests
+// ============================================================================
+
+mod max_memory {
+    use super::*;
+
+    fn run_with(args: &[&str], name: &str) -> Output {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output(name);
+        let mut all = vec![
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-q",
+        ];
+        all.extend_from_slice(args);
+        run_chatpack(&all)
+    }
+
+    #[test]
+    fn test_refuses_in_memory_parse_over_limit() {
+        let result = run_with(&["--no-streaming", "--max-memory", "1KB"], "mem_over.csv");
+
+        assert!(!result.status.success());
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("over the --max-memory limit of 1.0 KB"));
+        assert!(stderr.contains("Drop --no-streaming"));
+    }
+
+    #[test]
+    fn test_streaming_ignores_limit() {
+        let result = run_with(&["--max-memory", "1KB"], "mem_streaming.csv");
+        assert_success(&result);
+    }
+
+    #[test]
+    fn test_under_limit() {
+        let result = run_with(&["--no-streaming", "--max-memory", "2GB"], "mem_under.csv");
+        assert_success(&result);
+    }
+
+    #[test]
+    fn test_invalid_size() {
+        let result = run_with(&["--max-memory", "2 parsecs"], "mem_invalid.csv");
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("unknown size unit"));
+    }
+}
+
+// ============================================================================
+// Output Verification Tests
+// ============================================================================
+
+mod verify_output {
+    use super::*;
+
+    fn run_with(output: &std::path::Path, args: &[&str]) -> Output {
+        let input = fixtures_dir().join("telegram_export.json");
+        let mut all = vec![
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--verify-output",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+        run_chatpack(&all)
+    }
+
+    #[test]
+    fn test_valid_outputs_pass() {
+        for name in ["verify.csv", "verify.json", "verify.jsonl"] {
+            let result = run_with(&temp_output(name), &["-t", "--ids", "--json-envelope"]);
+            assert_success(&result);
+        }
+    }
+
+    #[test]
+    fn test_utf16_with_escape_char() {
+        let result = run_with(
+            &temp_output("verify_utf16.csv"),
+            &["--output-encoding", "utf-16-le", "--csv-escape-char", "\\"],
+        );
+        assert_success(&result);
+    }
+
+    #[test]
+    fn test_multiple_output_files() {
+        let result = run_with(&temp_output("verify_split.jsonl"), &["--split-by-sender"]);
+        assert_success(&result);
+    }
+
+    #[test]
+    fn test_inconsistent_csv_fails() {
+        let output = temp_output("verify_bad.csv");
+        fs::write(&output, "a;b;c\n").unwrap();
+
+        let result = run_with(&output, &["--append"]);
+
+        assert!(!result.status.success());
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("is not valid CSV"));
+        assert!(stderr.contains("found record with 2 fields"));
+    }
+
+    #[test]
+    fn test_invalid_jsonl_line_fails() {
+        let output = temp_output("verify_bad.jsonl");
+        fs::write(&output, "{\"ok\":1}\nnot json\n").unwrap();
+
+        let result = run_with(&output, &["--append"]);
+
+        assert!(!result.status.success());
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("is not valid JSONL"));
+        assert!(stderr.contains("line 2"));
+    }
+
+    #[test]
+    fn test_conflicts_with_output_url() {
+        let result = run_with(
+            &temp_output("verify_url.csv"),
+            &["--output-url", "http://127.0.0.1:9/"],
+        );
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("cannot be used with"));
+    }
+}
+
+// ============================================================================
+// Sender Alias Tests
+// ============================================================================
+
+mod sender_aliases {
+    use super::*;
+
+    fn run_with_aliases(file_name: &str, aliases: &str, args: &[&str]) -> (Output, String) {
+        let alias_file = temp_output(file_name);
+        fs::write(&alias_file, aliases).unwrap();
+
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output(&format!("{}.csv", file_name));
+        let mut all = vec![
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--sender-alias-file",
+            alias_file.to_str().unwrap(),
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let content = if result.status.success() {
+            read_output(&output)
+        } else {
+            String::new()
+        };
+        (result, content)
+    }
+
+    #[test]
+    fn test_json_aliases() {
+        let (result, content) = run_with_aliases(
+            "aliases.json",
+            r#"{"Alice": "A.", "Bob": "B."}"#,
+            &["--no-merge"],
+        );
+
+        assert_success(&result);
+        assert!(content.contains("A.;Hello! How are you?"));
+        assert!(content.contains("B.;No, what happened?"));
+        assert!(!content.contains("Alice;"));
+    }
+
+    #[test]
+    fn test_toml_aliases() {
+        let (result, content) =
+            run_with_aliases("aliases.toml", "Bob = \"Robert\"\n", &["--no-merge"]);
+
+        assert_success(&result);
+        assert!(content.contains("Robert;No, what happened?"));
+        assert!(content.contains("Alice;Hello! How are you?"));
+    }
+
+    #[test]
+    fn test_merges_accounts() {
+        let (result, content) = run_with_aliases(
+            "aliases_merge.json",
+            r#"{"Alice": "Team", "Bob": "Team"}"#,
+            &[],
+        );
+
+        assert_success(&result);
+        // All messages now come from one sender and merge into one row
+        assert_eq!(
+            content.lines().filter(|l| l.starts_with("Team;")).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_sender_filter_uses_aliases() {
+        let (result, content) = run_with_aliases(
+            "aliases_from.json",
+            r#"{"Bob": "Robert"}"#,
+            &["--from", "Robert"],
+        );
+
+        assert_success(&result);
+        assert!(content.contains("Robert;"));
+        assert!(!content.contains("Alice;"));
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let (result, _) = run_with_aliases("aliases_bad.json", "[1, 2]", &[]);
+
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("Invalid sender aliases"));
+    }
+}
+
+// ============================================================================
+// Pivot Tests
+// ============================================================================
+
+mod pivot {
+    use super::*;
+
+    fn run_pivot(name: &str, args: &[&str]) -> (Output, String) {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--pivot",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let content = if result.status.success() {
+            read_output(&output)
+        } else {
+            String::new()
+        };
+        (result, content)
+    }
+
+    #[test]
+    fn test_messages_per_day() {
+        let (result, content) = run_pivot("pivot_day.csv", &["--output-newline", "lf"]);
+
+        assert_success(&result);
+        assert_eq!(
+            content,
+            "Period;Alice;Bob\n2024-01-15;3;2\n2024-02-20;1;0\n2024-03-10;0;1\n"
+        );
+    }
+
+    #[test]
+    fn test_chars_per_hour() {
+        let (result, content) = run_pivot(
+            "pivot_hour.csv",
+            &["--by", "hour", "--pivot-metric", "chars"],
+        );
+
+        assert_success(&result);
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines[0], "Period;Alice;Bob");
+        assert_eq!(lines[1], "2024-01-15 10:00;77;63");
+        assert_eq!(lines.len(), 4);
+    }
+
+    #[test]
+    fn test_respects_filters() {
+        let (result, content) = run_pivot("pivot_filtered.csv", &["--after", "2024-02-01"]);
+
+        assert_success(&result);
+        assert!(!content.contains("2024-01-15"));
+        assert!(content.contains("2024-02-20;1;0"));
+    }
+
+    #[test]
+    fn test_requires_csv() {
+        let (result, _) = run_pivot("pivot.json", &[]);
+
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("--pivot writes CSV"));
+    }
+
+    #[test]
+    fn test_by_requires_pivot() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("pivot_no_flag.csv");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--by",
+            "hour",
+        ]);
+
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Context Window Template Tests
+// ============================================================================
+
+mod context_window_format {
+    use super::*;
+
+    fn run_template(name: &str, template: &str, args: &[&str]) -> (Output, String) {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--context-window-format",
+            template,
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let content = if result.status.success() {
+            read_output(&output)
+        } else {
+            String::new()
+        };
+        (result, content)
+    }
+
+    #[test]
+    fn test_renders_each_message() {
+        let (result, content) = run_template(
+            "template.txt",
+            r"User: {sender}\nMessage: {text}\nTime: {timestamp}\n---\n",
+            &["--output-newline", "lf", "--no-merge", "-q"],
+        );
+
+        assert_success(&result);
+        assert!(content.starts_with(
+            "User: Alice\nMessage: Hello! How are you?\nTime: 2024-01-15 10:30:00\n---\n"
+        ));
+        assert_eq!(content.matches("---\n").count(), 7);
+    }
+
+    #[test]
+    fn test_merged_messages_render_once() {
+        let (result, content) =
+            run_template("template_merged.txt", r"[{sender}] {text}\n", &["-q"]);
+
+        assert_success(&result);
+        assert!(content.contains("[Alice] That's wonderful to hear!\nBy the way"));
+        assert_eq!(content.matches("[Alice]").count(), 3);
+    }
+
+    #[test]
+    fn test_ids_and_unknown_tokens() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output("template_ids.txt");
+        let result = run_chatpack(&[
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--context-window-format",
+            r"#{id} {unknown} {sender}\n",
+            "--no-merge",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+        let content = read_output(&output);
+        assert!(content.starts_with("#1 {unknown} Alice\n#2 {unknown} Bob\n"));
+    }
+
+    #[test]
+    fn test_no_extension_warning() {
+        let (result, _) = run_template("template.prompt", r"{text}\n", &[]);
+
+        assert_success(&result);
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(!stderr.contains("Unknown output extension"));
+        assert!(stderr.contains("(template)"));
+    }
+}
+
+// ============================================================================
+// Conversation Marker Tests
+// ============================================================================
+
+mod conversation_markers {
+    use super::*;
+
+    #[test]
+    fn test_markers_and_separator() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("markers.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--context-window-format",
+            "{sender}: {text}",
+            "--conversation-start-marker",
+            r"<chat>\n",
+            "--conversation-end-marker",
+            r"\n</chat>\n",
+            "--turn-separator",
+            r"\n\n",
+            "--after",
+            "2024-02-01",
+            "-q",
+            "--output-newline",
+            "lf",
+        ]);
+
+        assert_success(&result);
+        assert_eq!(
+            read_output(&output),
+            "<chat>\nAlice: This is a message from February\n\nBob: And this one is from March\n</chat>\n"
+        );
+    }
+
+    #[test]
+    fn test_markers_without_messages() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("markers_empty.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--context-window-format",
+            "{text}",
+            "--conversation-start-marker",
+            "[",
+            "--conversation-end-marker",
+            "]",
+            "--from",
+            "Nobody",
+            "-q",
+        ]);
+
+        assert_success(&result);
+        assert_eq!(read_output(&output), "[]");
+    }
+
+    #[test]
+    fn test_requires_template() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            temp_output("markers.csv").to_str().unwrap(),
+            "--turn-separator",
+            "|",
+        ]);
+
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("--context-window-format"));
+    }
+}
+
+// ============================================================================
+// Participant Limit Tests
+// ============================================================================
+
+mod max_participants {
+    use super::*;
+
+    fn run_with(args: &[&str], name: &str) -> Output {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-q",
+        ];
+        all.extend_from_slice(args);
+        run_chatpack(&all)
+    }
+
+    #[test]
+    fn test_too_many_participants() {
+        let result = run_with(&["--max-participants", "1"], "participants_over.csv");
+
+        assert!(!result.status.success());
+        assert!(
+            String::from_utf8_lossy(&result.stderr)
+                .contains("Too many participants (2 > 1); use --force to process anyway")
+        );
+    }
+
+    #[test]
+    fn test_within_limit() {
+        let result = run_with(&["--max-participants", "2"], "participants_ok.csv");
+        assert_success(&result);
+    }
+
+    #[test]
+    fn test_counts_after_filters() {
+        let result = run_with(
+            &["--max-participants", "1", "--from", "Alice"],
+            "participants_filtered.csv",
+        );
+        assert_success(&result);
+    }
+
+    #[test]
+    fn test_force_overrides() {
+        let result = run_with(
+            &["--max-participants", "1", "--force"],
+            "participants_forced.csv",
+        );
+        assert_success(&result);
+    }
+}
+
+// ============================================================================
+// Text Replacement Tests
+// ============================================================================
+
+mod replace_text_pattern {
+    use super::*;
+
+    fn run_with(name: &str, args: &[&str]) -> (Output, String) {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let content = if result.status.success() {
+            read_output(&output)
+        } else {
+            String::new()
+        };
+        (result, content)
+    }
+
+    #[test]
+    fn test_replacements_in_order() {
+        let (result, content) = run_with(
+            "replace.csv",
+            &[
+                "--replace-text-pattern",
+                r"\bAlice\b",
+                "[NAME]",
+                "--replace-text-pattern",
+                r"\[NAME\]",
+                "[PERSON]",
+            ],
+        );
+
+        assert_success(&result);
+        assert!(content.contains("Bob;Hi [PERSON]! I'm doing great"));
+        // Sender names are not message text
+        assert!(content.contains("Alice;Hello!"));
+    }
+
+    #[test]
+    fn test_capture_groups() {
+        let (result, content) = run_with(
+            "replace_groups.jsonl",
+            &["--replace-text-pattern", r"from (\w+)", "from <$1>"],
+        );
+
+        assert_success(&result);
+        assert!(content.contains("This is a message from <February>"));
+    }
+
+    #[test]
+    fn test_applies_after_prepend_sender() {
+        let (result, content) = run_with(
+            "replace_prepend.csv",
+            &[
+                "--prepend-sender",
+                "--replace-text-pattern",
+                "^Bob: ",
+                "B> ",
+            ],
+        );
+
+        assert_success(&result);
+        assert!(content.contains("Bob;B> No, what happened?"));
+    }
+
+    #[test]
+    fn test_invalid_regex() {
+        let (result, _) = run_with("replace_bad.csv", &["--replace-text-pattern", "(", "x"]);
+
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("Invalid --replace-text-pattern"));
+    }
+}
+
+// ============================================================================
+// Sender Pair Chunking Tests
+// ============================================================================
+
+mod chunk_by_sender_pairs {
+    use super::*;
+
+    fn run_chunks(name: &str, args: &[&str]) -> (Output, PathBuf) {
+        let input = fixtures_dir().join("whatsapp_group.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--chunk-by-sender-pairs",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+        (run_chatpack(&all), output)
+    }
+
+    #[test]
+    fn test_jsonl_dialogues() {
+        let (result, output) = run_chunks("pairs.jsonl", &[]);
+        assert_success(&result);
+
+        let lines: Vec<serde_json::Value> = read_output(&output)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["speakers"], serde_json::json!(["Alice", "Bob"]));
+        assert_eq!(lines[0]["messages"].as_array().unwrap().len(), 4);
+        // Carol's interjection starts the next dialogue
+        assert_eq!(lines[1]["speakers"], serde_json::json!(["Carol", "Alice"]));
+        assert_eq!(lines[1]["messages"][0]["content"], "Lunch anyone?");
+        assert_eq!(lines[2]["speakers"], serde_json::json!(["Bob", "Carol"]));
+    }
+
+    #[test]
+    fn test_monologues_dropped() {
+        let (result, output) = run_chunks("pairs_mono.jsonl", &["--from", "Bob"]);
+        assert_success(&result);
+        assert!(read_output(&output).is_empty());
+    }
+
+    #[test]
+    fn test_csv_file_per_dialogue() {
+        let (result, _) = run_chunks("pairs.csv", &["--output-newline", "lf"]);
+        assert_success(&result);
+
+        let second = read_output(&temp_output("pairs_dialogue_1.csv"));
+        assert!(second.starts_with("Sender;Content\nCarol;Lunch anyone?\n"));
+        assert!(!second.contains("Bob"));
+        assert!(temp_output("pairs_dialogue_2.csv").exists());
+    }
+}
+
+// ============================================================================
+// Longest Exchange Tests
+// ============================================================================
+
+mod longest_exchange {
+    use super::*;
+
+    fn run_report(args: &[&str]) -> (Output, serde_json::Value) {
+        let input = fixtures_dir().join("whatsapp_group.txt");
+        let mut all = vec!["wa", input.to_str().unwrap(), "-q"];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let report = if result.status.success() {
+            serde_json::from_slice(&result.stdout).unwrap()
+        } else {
+            serde_json::Value::Null
+        };
+        (result, report)
+    }
+
+    #[test]
+    fn test_ranked_by_message_count() {
+        let (result, report) = run_report(&["--longest-exchange", "3"]);
+        assert_success(&result);
+
+        let entries = report.as_array().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0]["speakers"], serde_json::json!(["Alice", "Bob"]));
+        assert_eq!(entries[0]["message_count"], 4);
+        assert_eq!(entries[0]["start_time"], "2024-01-15T10:30:00Z");
+        assert_eq!(entries[0]["end_time"], "2024-01-15T10:33:00Z");
+        assert_eq!(
+            entries[1]["speakers"],
+            serde_json::json!(["Carol", "Alice"])
+        );
+        assert_eq!(entries[2]["message_count"], 3);
+    }
+
+    #[test]
+    fn test_sample_is_first_three_messages() {
+        let (result, report) = run_report(&["--longest-exchange", "1"]);
+        assert_success(&result);
+
+        let entries = report.as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        let sample = entries[0]["sample"].as_array().unwrap();
+        assert_eq!(sample.len(), 3);
+        assert_eq!(sample[0]["content"], "Morning! Is the release out?");
+        assert_eq!(sample[2]["sender"], "Alice");
+    }
+
+    #[test]
+    fn test_writes_no_output() {
+        let output = temp_output("longest_exchange.csv");
+        let _ = fs::remove_file(&output);
+        let (result, _) = run_report(&["--longest-exchange", "1", "-o", output.to_str().unwrap()]);
+
+        assert_success(&result);
+        assert!(!output.exists());
+    }
+}
+
+// ============================================================================
+// Conversation Boundary Tests
+// ============================================================================
+
+mod conversation_boundary {
+    use super::*;
+
+    fn run_boundaries(name: &str, args: &[&str]) -> String {
+        let input = fixtures_dir().join("whatsapp_group.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        assert_success(&result);
+        read_output(&output)
+    }
+
+    #[test]
+    fn test_csv_boundary_line() {
+        let content = run_boundaries("boundary.csv", &["--conversation-boundary", "20"]);
+
+        let lines: Vec<&str> = content.lines().collect();
+        let boundary = lines
+            .iter()
+            .position(|l| *l == ";--- [30m gap] ---")
+            .unwrap();
+        assert_eq!(lines[boundary - 1], "Bob;Release is out!");
+        assert_eq!(lines[boundary + 1], "Bob;Changelog is on the wiki.");
+        assert_eq!(content.matches("gap] ---").count(), 1);
+    }
+
+    #[test]
+    fn test_jsonl_boundary_object() {
+        let content = run_boundaries("boundary.jsonl", &["--conversation-boundary", "20"]);
+
+        let boundaries: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|value| value["type"] == "boundary")
+            .collect();
+        assert_eq!(
+            boundaries,
+            vec![serde_json::json!({"type": "boundary", "gap_minutes": 30})]
+        );
+    }
+
+    #[test]
+    fn test_json_boundary_before_day_separator() {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output("boundary_days.json");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--conversation-boundary",
+            "120",
+            "--group-by-day",
+            "-q",
+        ]);
+        assert_success(&result);
+
+        let entries: Vec<serde_json::Value> = serde_json::from_str(&read_output(&output)).unwrap();
+        let boundary = entries
+            .iter()
+            .position(|e| e["type"] == "boundary")
+            .unwrap();
+        assert_eq!(entries[boundary + 1]["type"], "day_separator");
+        assert_eq!(entries[boundary + 1]["date"], "2024-02-20");
+    }
+
+    #[test]
+    fn test_no_boundary_under_gap() {
+        let content = run_boundaries("boundary_none.csv", &["--conversation-boundary", "60"]);
+        assert!(!content.contains("gap] ---"));
+    }
+}
+
+// ============================================================================
+// First Occurrence Deduplication Tests
+// ============================================================================
+
+mod first_occurrence_only {
+    use super::*;
+
+    fn run_dedup(name: &str, args: &[&str]) -> String {
+        let input = fixtures_dir().join("whatsapp_repeats.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-t",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        assert_success(&result);
+        read_output(&output)
+    }
+
+    #[test]
+    fn test_drops_repeats_anywhere() {
+        let content = run_dedup(
+            "dedup_all.csv",
+            &["--output-newline", "lf", "--first-occurrence-only"],
+        );
+
+        assert_eq!(
+            content,
+            "Timestamp;Sender;Content\n\
+             2024-01-15 10:00:00;Alice;Vote in the poll please!\n\
+             2024-01-15 10:01:00;Bob;Done.\n\
+             2024-01-15 10:03:00;Bob;Vote in the poll please!\n"
+        );
+    }
+
+    #[test]
+    fn test_window_restarts_on_each_repeat() {
+        let content = run_dedup(
+            "dedup_window.csv",
+            &["--first-occurrence-only", "--dedup-window", "300"],
+        );
+
+        // 10:02 and 10:05 are within 5 minutes of the previous repeat; 12:00 isn't
+        assert_eq!(content.matches("Alice;Vote in the poll please!").count(), 2);
+        assert!(content.contains("12:00:00;Alice;Vote in the poll please!"));
+        assert!(content.contains("12:01:00;Bob;Done."));
+    }
+
+    #[test]
+    fn test_window_requires_flag() {
+        let input = fixtures_dir().join("whatsapp_repeats.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            temp_output("dedup_no_flag.csv").to_str().unwrap(),
+            "--dedup-window",
+            "60",
+        ]);
+
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Language Filter Tests
+// ============================================================================
+
+mod language_filter {
+    use super::*;
+
+    fn run_filter(name: &str, args: &[&str]) -> (Output, String) {
+        let input = fixtures_dir().join("whatsapp_multilingual.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let content = if result.status.success() {
+            read_output(&output)
+        } else {
+            String::new()
+        };
+        (result, content)
+    }
+
+    #[test]
+    fn test_keeps_matching_language() {
+        let (result, content) = run_filter("lang_de.csv", &["--language-filter", "de"]);
+
+        assert_success(&result);
+        assert!(content.contains("Jonas;Ich glaube"));
+        assert!(!content.contains("Does anyone know"));
+        assert!(!content.contains("Lucía"));
+        assert!(!content.contains("Anya"));
+    }
+
+    #[test]
+    fn test_short_messages_kept() {
+        let (result, content) = run_filter("lang_short.csv", &["--language-filter", "eng"]);
+
+        assert_success(&result);
+        assert!(content.contains("Alice;ok danke"));
+        assert!(content.contains("Alice;Great, then we can all sit together"));
+        assert!(!content.contains("Jonas"));
+    }
+
+    #[test]
+    fn test_min_words_zero_checks_everything() {
+        let (result, content) = run_filter(
+            "lang_min_words.csv",
+            &["--language-filter", "ru", "--language-min-words", "0"],
+        );
+
+        assert_success(&result);
+        assert_eq!(content.lines().count(), 2);
+        assert!(content.contains("Anya;"));
+    }
+
+    #[test]
+    fn test_unsupported_language() {
+        let (result, _) = run_filter("lang_bad.csv", &["--language-filter", "kk"]);
+
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("unsupported language 'kk'"));
+    }
+}
+
+// ============================================================================
+// Topic Filter Tests
+// ============================================================================
+
+mod topic_filter {
+    use super::*;
+
+    fn run_topics(name: &str, topics: &str, args: &[&str]) -> (Output, PathBuf) {
+        let topic_file = temp_output(&format!("{}.topics.json", name));
+        fs::write(&topic_file, topics).unwrap();
+
+        let input = fixtures_dir().join("whatsapp_group.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--topic-filter",
+            topic_file.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+        (run_chatpack(&all), output)
+    }
+
+    const TOPICS: &str =
+        r#"{"release": ["release", "CI", "changelog"], "food": ["lunch", "noon"]}"#;
+
+    #[test]
+    fn test_file_per_topic() {
+        let (result, _) = run_topics("topics.csv", TOPICS, &[]);
+        assert_success(&result);
+
+        let release = read_output(&temp_output("topics_release.csv"));
+        assert_eq!(release.lines().count(), 5);
+        assert!(release.contains("Bob;Not yet, CI is still running."));
+        assert!(release.contains("Bob;Changelog is on the wiki."));
+
+        let food = read_output(&temp_output("topics_food.csv"));
+        assert_eq!(food.lines().count(), 4);
+        assert!(food.contains("Carol;Lunch anyone?"));
+
+        let unclassified = read_output(&temp_output("topics_unclassified.csv"));
+        assert_eq!(unclassified.lines().count(), 5);
+        assert!(unclassified.contains("Alice;Great."));
+    }
+
+    #[test]
+    fn test_keywords_match_whole_words() {
+        // "out" must not match "about"
+        let (result, _) = run_topics("topics_words.csv", r#"{"status": ["out"]}"#, &[]);
+        assert_success(&result);
+
+        let status = read_output(&temp_output("topics_words_status.csv"));
+        assert_eq!(status.lines().count(), 3);
+        assert!(!status.contains("About ten minutes."));
+    }
+
+    #[test]
+    fn test_empty_topic_still_written() {
+        let (result, _) = run_topics("topics_empty.jsonl", r#"{"sports": ["goal"]}"#, &[]);
+        assert_success(&result);
+
+        assert!(read_output(&temp_output("topics_empty_sports.jsonl")).is_empty());
+        let unclassified = read_output(&temp_output("topics_empty_unclassified.jsonl"));
+        assert_eq!(unclassified.lines().count(), 11);
+    }
+
+    #[test]
+    fn test_reserved_topic_name() {
+        let (result, _) = run_topics("topics_reserved.csv", r#"{"Unclassified": ["x"]}"#, &[]);
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("is reserved"));
+    }
+
+    #[test]
+    fn test_invalid_topic_file() {
+        let (result, _) = run_topics("topics_invalid.csv", r#"{"food": "lunch"}"#, &[]);
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("Invalid topics"));
+    }
+}
+
+// ============================================================================
+// Balanced Sampling Tests
+// ============================================================================
+
+mod sample_balanced_by_sender {
+    use super::*;
+
+    fn run_sample(name: &str, args: &[&str]) -> (Output, String) {
+        let input = fixtures_dir().join("whatsapp_group.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-t",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let content = if result.status.success() {
+            read_output(&output)
+        } else {
+            String::new()
+        };
+        (result, content)
+    }
+
+    fn count_sender(content: &str, sender: &str) -> usize {
+        content
+            .lines()
+            .filter(|line| line.contains(&format!(";{};", sender)))
+            .count()
+    }
+
+    #[test]
+    fn test_at_most_n_per_sender() {
+        let (result, content) = run_sample("sample_two.csv", &["--sample-balanced-by-sender", "2"]);
+        assert_success(&result);
+
+        assert_eq!(count_sender(&content, "Alice"), 2);
+        assert_eq!(count_sender(&content, "Bob"), 2);
+        assert_eq!(count_sender(&content, "Carol"), 2);
+    }
+
+    #[test]
+    fn test_small_senders_keep_everything() {
+        let (result, content) =
+            run_sample("sample_ten.csv", &["--sample-balanced-by-sender", "10"]);
+        assert_success(&result);
+        assert_eq!(content.lines().count(), 12);
+    }
+
+    #[test]
+    fn test_chronological_order() {
+        let (result, content) = run_sample(
+            "sample_order.csv",
+            &["--sample-balanced-by-sender", "1", "--seed", "3"],
+        );
+        assert_success(&result);
+
+        let timestamps: Vec<&str> = content
+            .lines()
+            .skip(1)
+            .map(|line| line.split(';').next().unwrap())
+            .collect();
+        assert_eq!(timestamps.len(), 3);
+        let mut sorted = timestamps.clone();
+        sorted.sort();
+        assert_eq!(timestamps, sorted);
+    }
+
+    #[test]
+    fn test_seed_is_reproducible() {
+        let args = ["--sample-balanced-by-sender", "2", "--seed", "42"];
+        let (first_result, first) = run_sample("sample_seed_a.csv", &args);
+        let (second_result, second) = run_sample("sample_seed_b.csv", &args);
+        assert_success(&first_result);
+        assert_success(&second_result);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_seed_requires_sampling() {
+        let (result, _) = run_sample("sample_no_n.csv", &["--seed", "1"]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Output Newline Tests
+// ============================================================================
+
+mod output_newline {
+    use super::*;
+
+    fn run_newline(name: &str, args: &[&str]) -> (Output, Vec<u8>) {
+        let input = fixtures_dir().join("whatsapp_group.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let bytes = if result.status.success() {
+            fs::read(&output).unwrap()
+        } else {
+            Vec::new()
+        };
+        (result, bytes)
+    }
+
+    fn count(bytes: &[u8], pattern: &[u8]) -> usize {
+        bytes
+            .windows(pattern.len())
+            .filter(|window| *window == pattern)
+            .count()
+    }
+
+    #[test]
+    fn test_csv_crlf() {
+        let (result, bytes) = run_newline("newline_crlf.csv", &["--output-newline", "CRLF"]);
+        assert_success(&result);
+        assert!(bytes.starts_with(b"Sender;Content\r\nAlice;"));
+        assert_eq!(count(&bytes, b"\r\n"), 12);
+        assert_eq!(count(&bytes, b"\n"), 12);
+    }
+
+    #[test]
+    fn test_jsonl_cr() {
+        let (result, bytes) = run_newline(
+            "newline_cr.jsonl",
+            &["--output-newline", "cr", "--verify-output"],
+        );
+        assert_success(&result);
+        assert_eq!(count(&bytes, b"\r"), 11);
+        assert!(!bytes.contains(&b'\n'));
+    }
+
+    #[test]
+    fn test_lf() {
+        let (result, bytes) = run_newline("newline_lf.csv", &["--output-newline", "LF"]);
+        assert_success(&result);
+        assert!(!bytes.contains(&b'\r'));
+    }
+
+    #[test]
+    fn test_json_unaffected() {
+        let (result, bytes) = run_newline("newline_json.json", &["--output-newline", "CR"]);
+        assert_success(&result);
+        assert!(!bytes.contains(&b'\r'));
+    }
+
+    #[test]
+    fn test_template_crlf() {
+        let (result, bytes) = run_newline(
+            "newline_template.txt",
+            &[
+                "--output-newline",
+                "CRLF",
+                "--context-window-format",
+                "{sender}: {text}\\n",
+            ],
+        );
+        assert_success(&result);
+        assert!(bytes.starts_with(b"Alice: Morning! Is the release out?\r\nBob:"));
+    }
+
+    #[test]
+    fn test_crlf_with_utf16() {
+        let (result, bytes) = run_newline(
+            "newline_utf16.csv",
+            &["--output-newline", "CRLF", "--output-encoding", "utf-16-le"],
+        );
+        assert_success(&result);
+        assert_eq!(count(&bytes, b"\r\0\n\0"), 12);
+    }
+
+    /// Merged messages are joined with `\n`, which is message text rather
+    /// than a line ending
+    fn run_merged(name: &str, args: &[&str]) -> Vec<u8> {
+        let input = fixtures_dir().join("whatsapp_export.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--output-newline",
+            "crlf",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        assert_success(&result);
+        fs::read(&output).unwrap()
+    }
+
+    #[test]
+    fn test_csv_crlf_keeps_line_breaks_in_text() {
+        let bytes = run_merged("newline_multiline.csv", &[]);
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.contains(
+            "Alice;\"That's wonderful to hear!\nBy the way, did you see the news?\"\r\nBob;"
+        ));
+    }
+
+    #[test]
+    fn test_template_crlf_keeps_line_breaks_in_text() {
+        let bytes = run_merged(
+            "newline_multiline.txt",
+            &["--context-window-format", "{sender}: {text}\\n"],
+        );
+        let text = String::from_utf8(bytes).unwrap();
+        assert!(text.contains(
+            "Alice: That's wonderful to hear!\nBy the way, did you see the news?\r\nBob:"
+        ));
+    }
+}
+
+// ============================================================================
+// Null Replacement Tests
+// ============================================================================
+
+mod null_replacement {
+    use super::*;
+
+    fn run_null(name: &str, source: &str, fixture: &str, args: &[&str]) -> (Output, String) {
+        let input = fixtures_dir().join(fixture);
+        let output = temp_output(name);
+        let mut all = vec![
+            source,
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let content = if result.status.success() {
+            read_output(&output)
+        } else {
+            String::new()
+        };
+        (result, content)
+    }
+
+    #[test]
+    fn test_missing_reply_replaced() {
+        let (result, content) = run_null(
+            "null_replies.csv",
+            "tg",
+            "telegram_export.json",
+            &[
+                "--output-newline",
+                "lf",
+                "--ids",
+                "-r",
+                "--null-replacement",
+                "N/A",
+            ],
+        );
+        assert_success(&result);
+        assert!(content.contains("1;Alice;Hello! How are you?;N/A\n"));
+        // Values that exist are untouched
+        assert!(content.contains("5;Bob;No, what happened?;4\n"));
+    }
+
+    #[test]
+    fn test_mysql_null_marker() {
+        let (result, content) = run_null(
+            "null_mysql.csv",
+            "wa",
+            "whatsapp_group.txt",
+            &[
+                "--output-newline",
+                "lf",
+                "--ids",
+                "-t",
+                "--null-replacement",
+                "\\N",
+            ],
+        );
+        assert_success(&result);
+        assert!(content.contains("\\N;2024-01-15 10:30:00;Alice;Morning! Is the release out?\n"));
+    }
+
+    #[test]
+    fn test_separator_rows() {
+        let (result, content) = run_null(
+            "null_separators.csv",
+            "wa",
+            "whatsapp_group.txt",
+            &[
+                "--output-newline",
+                "lf",
+                "-t",
+                "--group-by-day",
+                "--null-replacement",
+                "NULL",
+            ],
+        );
+        assert_success(&result);
+        assert!(content.contains("NULL;NULL;--- 2024-01-15 ---\n"));
+    }
+
+    #[test]
+    fn test_default_empty() {
+        let (result, content) = run_null(
+            "null_default.csv",
+            "tg",
+            "telegram_export.json",
+            &["--output-newline", "lf", "--ids", "-r"],
+        );
+        assert_success(&result);
+        assert!(content.contains("1;Alice;Hello! How are you?;\n"));
+    }
+}
+
+// ============================================================================
+// Field Order Tests
+// ============================================================================
+
+mod field_order {
+    use super::*;
+
+    fn run_order(name: &str, args: &[&str]) -> (Output, String) {
+        let input = fixtures_dir().join("whatsapp_group.txt");
+        let output = temp_output(name);
+        let mut all = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let content = if result.status.success() {
+            read_output(&output)
+        } else {
+            String::new()
+        };
+        (result, content)
+    }
+
+    #[test]
+    fn test_reordered_columns() {
+        let (result, content) = run_order(
+            "order_basic.csv",
+            &["-t", "--ids", "--field-order", "text,sender,timestamp"],
+        );
+        assert_success(&result);
+
+        let mut lines = content.lines();
+        // --ids is enabled but not listed, so the column is left out
+        assert_eq!(lines.next(), Some("Content;Sender;Timestamp"));
+        assert_eq!(
+            lines.next(),
+            Some("Morning! Is the release out?;Alice;2024-01-15 10:30:00")
+        );
+    }
+
+    #[test]
+    fn test_spaces_around_names() {
+        let (result, content) = run_order(
+            "order_spaces.csv",
+            &[
+                "--output-newline",
+                "lf",
+                "-t",
+                "--field-order",
+                "timestamp, text",
+            ],
+        );
+        assert_success(&result);
+        assert!(content.starts_with("Timestamp;Content\n2024-01-15 10:30:00;Morning!"));
+    }
+
+    #[test]
+    fn test_disabled_field_rejected() {
+        let (result, _) = run_order("order_disabled.csv", &["--field-order", "sender,timestamp"]);
+        assert!(!result.status.success());
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("Field 'timestamp' in --field-order needs --timestamps"));
+    }
+
+    #[test]
+    fn test_unknown_field_rejected() {
+        let (result, _) = run_order("order_unknown.csv", &["--field-order", "sender,body"]);
+        assert!(!result.status.success());
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(stderr.contains("Unknown field 'body'"));
+        assert!(stderr.contains("reply_to"));
+    }
+
+    #[test]
+    fn test_duplicate_field_rejected() {
+        let (result, _) = run_order("order_twice.csv", &["--field-order", "text,text"]);
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("listed twice"));
+    }
+
+    #[test]
+    fn test_output_schema_follows_order() {
+        let result = run_chatpack(&[
+            "wa",
+            "chat.txt",
+            "-o",
+            "out.csv",
+            "-t",
+            "--field-order",
+            "timestamp,text",
+            "--output-schema",
+        ]);
+        assert_success(&result);
+
+        let schema: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+        let required = schema.to_string();
+        assert!(required.contains(r#"["Timestamp","Content"]"#));
+    }
+}
+
+// ============================================================================
+// Output BOM Tests
+// ============================================================================
+
+mod output_bom {
+    use super::*;
+
+    fn run_bom(name: &str, args: &[&str]) -> Output {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output(name);
+        let mut all = vec![
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "-q",
+        ];
+        all.extend_from_slice(args);
+        run_chatpack(&all)
+    }
+
+    #[test]
+    fn test_csv_starts_with_bom() {
+        let result = run_bom("bom.csv", &["--output-bom", "--verify-output"]);
+        assert_success(&result);
+
+        let bytes = fs::read(temp_output("bom.csv")).unwrap();
+        assert!(bytes.starts_with(b"\xEF\xBB\xBFSender;Content"));
+        // The BOM is written once, not per record
+        assert_eq!(
+            bytes.windows(3).filter(|w| *w == b"\xEF\xBB\xBF").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_same_as_utf8_bom_encoding() {
+        assert_success(&run_bom("bom_flag.csv", &["--output-bom"]));
+        assert_success(&run_bom(
+            "bom_encoding.csv",
+            &["--output-encoding", "utf-8-bom"],
+        ));
+        assert_eq!(
+            fs::read(temp_output("bom_flag.csv")).unwrap(),
+            fs::read(temp_output("bom_encoding.csv")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rejected_for_json() {
+        let result = run_bom("bom.json", &["--output-bom"]);
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("--output-bom is for CSV"));
+    }
+
+    #[test]
+    fn test_conflicts_with_output_encoding() {
+        let result = run_bom(
+            "bom_conflict.csv",
+            &["--output-bom", "--output-encoding", "utf-16-le"],
+        );
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Max Line Length Tests
+// ============================================================================
+
+mod max_line_length {
+    use super::*;
+
+    fn run_wrap(name: &str, args: &[&str]) -> (Output, String) {
+        let input = fixtures_dir().join("telegram_export.json");
+        let output = temp_output(name);
+        let mut all = vec![
+            "tg",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--no-merge",
+            "-q",
+        ];
+        all.extend_from_slice(args);
+
+        let result = run_chatpack(&all);
+        let content = if result.status.success() {
+            read_output(&output)
+        } else {
+            String::new()
+        };
+        (result, content)
+    }
+
+    #[test]
+    fn test_csv_cell_wrapped() {
+        let (result, content) = run_wrap(
+            "wrap.csv",
+            &["--output-newline", "lf", "--max-line-length", "16"],
+        );
+        assert_success(&result);
+        assert!(content.contains("Alice;\"Hello! How ar ↵ \ne you?\"\n"));
+    }
+
+    #[test]
+    fn test_no_line_too_long() {
+        let (result, content) = run_wrap(
+            "wrap_limit.csv",
+            &[
+                "--max-line-length",
+                "12",
+                "--omit-sender",
+                "--no-csv-header",
+            ],
+        );
+        assert_success(&result);
+        for line in content.lines() {
+            // +2 for the quotes around the cell
+            assert!(line.chars().count() <= 12 + 2, "too long: {:?}", line);
+        }
+    }
+
+    #[test]
+    fn test_custom_marker() {
+        let (result, content) = run_wrap(
+            "wrap_marker.csv",
+            &[
+                "--output-newline",
+                "lf",
+                "--max-line-length",
+                "16",
+                "--continuation-marker",
+                "\\",
+            ],
+        );
+        assert_success(&result);
+        assert!(content.contains("Alice;\"Hello! How are \\\nyou?\"\n"));
+    }
+
+    #[test]
+    fn test_template_continuations_indented() {
+        let (result, content) = run_wrap(
+            "wrap_template.txt",
+            &[
+                "--output-newline",
+                "lf",
+                "--max-line-length",
+                "20",
+                "--context-window-format",
+                "{sender}: {text}\\n",
+            ],
+        );
+        assert_success(&result);
+        assert!(
+            content.contains("Bob: Hi Alice! I'm doi ↵ \n  ng great, thank ↵ \n  s for asking.\n")
+        );
+    }
+
+    #[test]
+    fn test_json_not_wrapped() {
+        let (result, content) = run_wrap("wrap.json", &["--max-line-length", "16"]);
+        assert_success(&result);
+        assert!(content.contains("Hi Alice! I'm doing great, thanks for asking."));
+    }
+
+    #[test]
+    fn test_too_short_for_marker() {
+        let (result, _) = run_wrap("wrap_short.csv", &["--max-line-length", "5"]);
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("leaves no room"));
+    }
+}
+
+// ============================================================================
+// Activity Stats Tests
+// ============================================================================
+
+mod activity_stats {
+    use super::*;
+
+    fn summary_json() -> serde_json::Value {
+        let input = fixtures_dir().join("whatsapp_activity.txt");
+        let result = run_chatpack(&["wa", input.to_str().unwrap(), "--summary-only", "--json"]);
+        assert_success(&result);
+        serde_json::from_slice(&result.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_hourly_counts() {
+        let summary = summary_json();
+        let mut expected = vec![0; 24];
+        expected[9] = 3;
+        expected[14] = 5;
+        expected[22] = 1;
+        assert_eq!(summary["hourly_counts"], serde_json::json!(expected));
+        assert_eq!(summary["most_active_hour"], 14);
+    }
+
+    #[test]
+    fn test_daily_counts() {
+        let summary = summary_json();
+        // Monday first: Mon 15th, Tue 16th, Wed 17th, Sat 20th, Sun 21st
+        assert_eq!(
+            summary["daily_counts"],
+            serde_json::json!([3, 2, 1, 0, 0, 2, 1])
+        );
+        assert_eq!(summary["most_active_day"], "Monday");
+    }
+
+    #[test]
+    fn test_counts_follow_filters() {
+        let input = fixtures_dir().join("whatsapp_activity.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--summary-only",
+            "--json",
+            "--after",
+            "2024-01-17",
+        ]);
+        assert_success(&result);
+
+        let summary: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+        assert_eq!(
+            summary["daily_counts"],
+            serde_json::json!([0, 0, 1, 0, 0, 2, 1])
+        );
+        assert_eq!(summary["most_active_day"], "Saturday");
+    }
+
+    #[test]
+    fn test_no_timestamps() {
+        let input = fixtures_dir().join("telegram_export.json");
+        let result = run_chatpack(&["tg", input.to_str().unwrap(), "--summary-only", "--json"]);
+        assert_success(&result);
+
+        let summary: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+        assert_eq!(summary["hourly_counts"], serde_json::json!(vec![0; 24]));
+        assert!(summary["most_active_hour"].is_null());
+        assert!(summary["most_active_day"].is_null());
+    }
+
+    #[test]
+    fn test_report_activity_section() {
+        let input = fixtures_dir().join("whatsapp_activity.txt");
+        let output = temp_output("activity_report.csv");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--report",
+            "-q",
+        ]);
+        assert_success(&result);
+
+        let report = read_output(&temp_output("activity_report.csv.report.md"));
+        assert!(report.contains("- **Busiest hour:** 14:00–14:59 UTC\n"));
+        assert!(report.contains("- **Busiest day:** Monday\n"));
+    }
+}
+
+// ============================================================================
+// Response Time Tests
+// ============================================================================
+
+mod response_times {
+    use super::*;
+
+    fn response_times(fixture: &str, source: &str, args: &[&str]) -> serde_json::Value {
+        let input = fixtures_dir().join(fixture);
+        let mut all = vec![source, input.to_str().unwrap(), "--summary-only", "--json"];
+        all.extend_from_slice(args);
+        let result = run_chatpack(&all);
+        assert_success(&result);
+
+        let summary: serde_json::Value = serde_json::from_slice(&result.stdout).unwrap();
+        summary["response_times"].clone()
+    }
+
+    #[test]
+    fn test_known_response_times() {
+        // Responses: 30m, 4h 30m, 10m, 15m and 18h 30m. Bob's reply after
+        // 24h 10m and Alice's follow-up to herself don't count.
+        let stats = response_times("whatsapp_activity.txt", "wa", &[]);
+        assert_eq!(stats["min_seconds"], 600);
+        assert_eq!(stats["max_seconds"], 66600);
+        assert_eq!(stats["median_seconds"], 1800.0);
+        assert_eq!(stats["p95_seconds"], 66600.0);
+    }
+
+    #[test]
+    fn test_per_sender_median() {
+        let stats = response_times("whatsapp_activity.txt", "wa", &[]);
+        assert_eq!(stats["per_sender"]["Alice"], 900.0);
+        // Even count: mean of 30m and 18h 30m
+        assert_eq!(stats["per_sender"]["Bob"], 34200.0);
+    }
+
+    #[test]
+    fn test_none_without_replies() {
+        let stats = response_times("whatsapp_activity.txt", "wa", &["--from", "Alice"]);
+        assert!(stats.is_null());
+    }
+
+    #[test]
+    fn test_none_without_timestamps() {
+        let stats = response_times("telegram_export.json", "tg", &[]);
+        assert!(stats.is_null());
+    }
+}
+
+// ============================================================================
+// Top Words Tests
+// ============================================================================
+
+mod top_words {
+    use super::*;
+
+    fn summary(args: &[&str]) -> serde_json::Value {
+        let input = fixtures_dir().join("whatsapp_activity.txt");
+        let mut all = vec!["wa", input.to_str().unwrap(), "--summary-only", "--json"];
+        all.extend_from_slice(args);
+        let result = run_chatpack(&all);
+        assert_success(&result);
+        serde_json::from_slice(&result.stdout).unwrap()
+    }
+
+    #[test]
+    fn test_known_frequencies() {
+        let summary = summary(&[]);
+        let top = summary["top_words"].as_array().unwrap();
+        assert_eq!(top.len(), 10);
+        // "the" and other built-in stopwords are left out
+        assert_eq!(top[0], serde_json::json!(["coffee", 5]));
+        assert_eq!(top[1], serde_json::json!(["standup", 3]));
+        assert_eq!(top[2], serde_json::json!(["deploy", 2]));
+        // Ties are alphabetical
+        assert_eq!(top[3], serde_json::json!(["again", 1]));
+    }
+
+    #[test]
+    fn test_per_sender() {
+        let summary = summary(&[]);
+        let per_sender = &summary["per_sender_top_words"];
+        assert_eq!(per_sender["Alice"][0], serde_json::json!(["coffee", 3]));
+        assert_eq!(per_sender["Alice"][1], serde_json::json!(["standup", 2]));
+        assert_eq!(per_sender["Bob"][0], serde_json::json!(["coffee", 2]));
+    }
+
+    #[test]
+    fn test_stopwords_file() {
+        let stopwords = temp_output("stopwords.txt");
+        fs::write(&stopwords, "# office words\nCoffee\n\nstandup\n").unwrap();
+
+        let summary = summary(&["--stopwords-file", stopwords.to_str().unwrap()]);
+        let top = summary["top_words"].as_array().unwrap();
+        // The file replaces the built-in list, so "the" and "at" are counted now
+        assert_eq!(top[0], serde_json::json!(["the", 3]));
+        assert_eq!(top[1], serde_json::json!(["at", 2]));
+        assert_eq!(top[2], serde_json::json!(["deploy", 2]));
+        assert!(
+            !summary["per_sender_top_words"]["Bob"]
+                .to_string()
+                .contains("coffee")
+        );
+    }
+
+    #[test]
+    fn test_report_uses_stopwords_file() {
+        let stopwords = temp_output("stopwords_report.txt");
+        fs::write(&stopwords, "coffee\n").unwrap();
+
+        let input = fixtures_dir().join("whatsapp_activity.txt");
+        let output = temp_output("top_words_report.csv");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--report",
+            "--stopwords-file",
+            stopwords.to_str().unwrap(),
+            "-q",
+        ]);
+        assert_success(&result);
+
+        let report = read_output(&temp_output("top_words_report.csv.report.md"));
+        assert!(report.contains("1. standup (3)"));
+        assert!(!report.contains("coffee ("));
+    }
+
+    #[test]
+    fn test_missing_stopwords_file() {
+        let result = run_chatpack(&[
+            "wa",
+            fixtures_dir()
+                .join("whatsapp_activity.txt")
+                .to_str()
+                .unwrap(),
+            "--summary-only",
+            "--stopwords-file",
+            "/nonexistent/stopwords.txt",
+        ]);
+        assert!(!result.status.success());
+        assert!(String::from_utf8_lossy(&result.stderr).contains("Failed to read"));
+    }
+}
+
+// ============================================================================
+// Emoji Stats Tests
+// ============================================================================
+
+mod emoji_stats {
+    use super::*;
+
+    fn emoji_stats(name: &str, extra: &[&str]) -> serde_json::Value {
+        let input = fixtures_dir().join("whatsapp_emoji.txt");
+        let output = temp_output(name);
+        let stats = temp_output(&format!("{}.emoji_stats.json", name));
+        let _ = fs::remove_file(&stats);
+
+        let mut args = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--emoji-stats",
+            "-q",
+        ];
+        args.extend_from_slice(extra);
+        let result = run_chatpack(&args);
+        assert_success(&result);
+        assert!(output.exists(), "Normal output is still written");
+        serde_json::from_str(&read_output(&stats)).unwrap()
+    }
+
+    #[test]
+    fn test_counts_sorted_by_frequency() {
+        let stats = emoji_stats("emoji_counts.csv", &[]);
+        assert_eq!(stats["total_unique"], 5);
+        assert_eq!(stats["total_count"], 7);
+        assert_eq!(
+            stats["emojis"][0],
+            serde_json::json!({"emoji": "😂", "count": 3})
+        );
+    }
+
+    #[test]
+    fn test_sequences_count_once() {
+        let stats = emoji_stats("emoji_sequences.csv", &[]);
+        let emojis: Vec<&str> = stats["emojis"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["emoji"].as_str().unwrap())
+            .collect();
+        // Variation selector, skin tone, ZWJ family and flag
+        assert!(emojis.contains(&"☀\u{FE0F}"));
+        assert!(emojis.contains(&"👍🏽"));
+        assert!(emojis.contains(&"👨\u{200D}👩\u{200D}👧"));
+        assert!(emojis.contains(&"🇩🇪"));
+        // Plain text symbols are not emoji
+        assert!(!emojis.contains(&"✓"));
+    }
+
+    #[test]
+    fn test_counts_filtered_messages() {
+        let stats = emoji_stats("emoji_filtered.csv", &["--from", "Bob"]);
+        assert_eq!(stats["total_count"], 4);
+        assert_eq!(
+            stats["emojis"][0],
+            serde_json::json!({"emoji": "😂", "count": 2})
+        );
+    }
+
+    #[test]
+    fn test_conflicts_with_summary_only() {
+        let input = fixtures_dir().join("whatsapp_emoji.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--emoji-stats",
+            "--summary-only",
+        ]);
+        assert!(!result.status.success());
+    }
+}
+
+// ============================================================================
+// Sender Stats Tests
+// ============================================================================
+
+mod sender_stats {
+    use super::*;
+
+    fn run(name: &str, extra: &[&str]) {
+        let input = fixtures_dir().join("whatsapp_activity.txt");
+        let output = temp_output(name);
+        let mut args = vec![
+            "wa",
+            input.to_str().unwrap(),
+            "-o",
+            output.to_str().unwrap(),
+            "--sender-stats",
+            "-q",
+        ];
+        args.extend_from_slice(extra);
+        let result = run_chatpack(&args);
+        assert_success(&result);
+        assert!(output.exists(), "Normal output is still written");
+    }
+
+    #[test]
+    fn test_csv_rows() {
+        run("sender_stats.csv", &[]);
+        let content = read_output(&temp_output("sender_stats.csv.sender_stats.csv"));
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(
+            lines[0],
+            "sender,message_count,total_chars,avg_chars,total_words,avg_words,first_message_at,last_message_at,response_count"
+        );
+        assert_eq!(
+            lines[1],
+            "Alice,5,117,23.4,21,4.2,2024-01-15T09:00:00Z,2024-01-20T14:45:00Z,3"
+        );
+        assert_eq!(
+            lines[2],
+            "Bob,4,75,18.75,14,3.5,2024-01-15T09:30:00Z,2024-01-21T09:15:00Z,4"
+        );
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn test_json_for_json_output() {
+        run("sender_stats.jsonl", &["-f", "jsonl"]);
+        let content = read_output(&temp_output("sender_stats.jsonl.sender_stats.json"));
+        let stats: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(stats[0]["sender"], "Alice");
+        assert_eq!(stats[0]["response_count"], 3);
+        assert_eq!(stats[1]["sender"], "Bob");
+        assert_eq!(stats[1]["avg_chars"], 18.75);
+    }
+
+    #[test]
+    fn test_responses_follow_filtered_messages() {
+        run("sender_stats_filtered.csv", &["--after", "2024-01-17"]);
+        let content = read_output(&temp_output("sender_stats_filtered.csv.sender_stats.csv"));
+        // Alice's first kept message follows nothing; every later one is a reply
+        assert!(content.contains("\nAlice,2,"));
+        assert!(content.lines().nth(1).unwrap().ends_with(",1"));
+        assert!(content.contains("\nBob,2,"));
+        assert!(content.lines().nth(2).unwrap().ends_with(",2"));
+    }
+
+    #[test]
+    fn test_conflicts_with_summary_only() {
+        let input = fixtures_dir().join("whatsapp_activity.txt");
+        let result = run_chatpack(&[
+            "wa",
+            input.to_str().unwrap(),
+            "--sender-stats",
+            "--summary-only",
+        ]);
+        assert!(!result.status.success());
+    }
+}